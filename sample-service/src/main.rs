@@ -6,10 +6,10 @@
 //! Action handlers live in src/actions/, one file per namespace leaf.
 //! The module path determines the SCAMP namespace automatically.
 
-use std::net::Ipv4Addr;
 use std::sync::Arc;
 
 use anyhow::Result;
+use scamp::config::Config;
 use scamp::rpc_support::auto_discover_into;
 use scamp::service::ScampService;
 
@@ -31,14 +31,19 @@ async fn main() -> Result<()> {
         start_time: std::time::Instant::now(),
     });
 
-    let mut service = ScampService::new("SampleService", "main");
-    auto_discover_into(&mut service, state, "main");
+    // Config is optional for the demo: without one, addresses come from bus_info defaults.
+    let config = Config::new(None).unwrap_or_else(|_| Config::from_content("").unwrap());
 
-    // Self-signed cert for demo (production would use real certs from config)
+    // Self-signed cert for demo (production would read <name>.key / <name>.cert from config)
     let (key_pem, cert_pem) = generate_demo_keypair();
-    service.bind_pem(&key_pem, &cert_pem, Ipv4Addr::LOCALHOST).await?;
+    let mut service = ScampService::builder(&config)
+        .name("SampleService")
+        .sector("main")
+        .tls_pem(&key_pem, &cert_pem)
+        .build()?;
+    auto_discover_into(&mut service, state, "main");
 
-    log::info!("Service: {} ({})", service.identity(), service.address().unwrap());
+    log::info!("Service: {}", service.identity());
     for action in service.actions_snapshot() {
         log::info!("  {}.v{} [{}]", action.name, action.version, action.flags.join(","));
     }
@@ -50,7 +55,7 @@ async fn main() -> Result<()> {
         shutdown_tx.send(true).ok();
     });
 
-    service.run(shutdown_rx).await
+    service.serve(shutdown_rx).await
 }
 
 fn generate_demo_keypair() -> (Vec<u8>, Vec<u8>) {
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use scamp::bus_info::BusInfo;
+use scamp::config::Config;
+use scamp::discovery::cache_writer::{CacheWriter, CacheWriterConfig, DEFAULT_WRITE_INTERVAL_SECS};
+use scamp::discovery::observer::ObserverConfig;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct CacheWriterCommand {
+    /// Cache file to maintain (overrides discovery.cache_path)
+    #[arg(long)]
+    path: Option<String>,
+
+    /// Seconds between rewrites (overrides discovery.cache_write_interval)
+    #[arg(long)]
+    interval: Option<u64>,
+}
+
+impl CacheWriterCommand {
+    pub async fn run(&self, config: &Config) -> Result<()> {
+        let mut writer_config = match (&self.path, CacheWriterConfig::from_config(config)) {
+            (Some(path), Ok(from_config)) => CacheWriterConfig {
+                path: path.into(),
+                ..from_config
+            },
+            (Some(path), Err(_)) => CacheWriterConfig {
+                path: path.into(),
+                interval: Duration::from_secs(DEFAULT_WRITE_INTERVAL_SECS),
+            },
+            (None, result) => result?,
+        };
+        if let Some(secs) = self.interval {
+            writer_config.interval = Duration::from_secs(secs.max(1));
+        }
+
+        let interface = BusInfo::from_config(config)
+            .discovery_addrs
+            .first()
+            .copied()
+            .unwrap_or(std::net::Ipv4Addr::UNSPECIFIED);
+        let obs_config = ObserverConfig::from_config(config, interface);
+
+        println!(
+            "  * Writing {} every {}s",
+            writer_config.path.display(),
+            writer_config.interval.as_secs()
+        );
+        println!("  * Observing {}:{} on {}", obs_config.group, obs_config.port, interface);
+        println!("  * Press Ctrl+C to stop");
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            tokio::signal::ctrl_c().await.ok();
+            println!("\n  * Shutting down (writing the cache one last time)...");
+            let _ = shutdown_tx.send(true);
+        });
+
+        let writer = tokio::task::spawn_blocking(move || CacheWriter::new(writer_config)).await?;
+        println!("  * Resuming with {} announcements from the existing cache", writer.len());
+        Arc::new(writer).run(obs_config, shutdown_rx).await
+    }
+}
@@ -1,10 +1,14 @@
 use anyhow::Result;
+use cache_writer::CacheWriterCommand;
 use clap::{Parser, Subcommand};
 use list::ListCommand;
+use relay::RelayCommand;
 use request::RequestCommand;
 use scamp::{config::Config, discovery::service_registry::ServiceRegistry};
 use serve::ServeCommand;
+mod cache_writer;
 mod list;
+mod relay;
 mod request;
 mod serve;
 
@@ -28,6 +32,13 @@ enum Commands {
     Request(RequestCommand),
     /// Start a test service
     Serve(ServeCommand),
+    /// Maintain the discovery cache file from multicast announcements
+    CacheWriter(CacheWriterCommand),
+    /// Carry announcements over TLS where multicast doesn't reach
+    Relay {
+        #[command(subcommand)]
+        command: RelayCommand,
+    },
 }
 
 impl Commands {
@@ -47,6 +58,8 @@ impl Commands {
                 command.run(config, &registry).await
             }
             Commands::Serve(command) => command.run(config).await,
+            Commands::CacheWriter(command) => command.run(config).await,
+            Commands::Relay { command } => command.run(config).await,
         }
     }
 }
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use scamp::auth::authorized_services::AuthorizedServices;
+use scamp::bus_info::BusInfo;
+use scamp::config::Config;
+use scamp::discovery::observer::ObserverConfig;
+use scamp::discovery::relay::{run_relay_forwarder, RelayForwarderConfig, RelayReceiver, RelayReceiverConfig, DEFAULT_RECONNECT_SECS};
+use scamp::discovery::service_registry::ServiceRegistry;
+use tokio::sync::{watch, RwLock};
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum RelayCommand {
+    /// Forward multicast announcements to a relay receiver
+    Forward {
+        /// Receiver as host:port (overrides discovery.relay_to)
+        #[arg(long)]
+        to: Option<String>,
+
+        /// Expected receiver certificate fingerprint (overrides discovery.relay_fingerprint)
+        #[arg(long)]
+        fingerprint: Option<String>,
+    },
+    /// Accept relayed announcements and print the changes they make
+    Receive {
+        /// Address to listen on (overrides discovery.relay_listen)
+        #[arg(long)]
+        listen: Option<String>,
+
+        /// Path to PEM-encoded key (overrides discovery.relay_key)
+        #[arg(long)]
+        key: Option<String>,
+
+        /// Path to PEM-encoded certificate (overrides discovery.relay_cert)
+        #[arg(long)]
+        cert: Option<String>,
+    },
+}
+
+impl RelayCommand {
+    pub async fn run(&self, config: &Config) -> Result<()> {
+        match self {
+            RelayCommand::Forward { to, fingerprint } => {
+                let mut relay_config = match (to, RelayForwarderConfig::from_config(config)) {
+                    (Some(to), Ok(from_config)) => RelayForwarderConfig {
+                        target: to.clone(),
+                        ..from_config
+                    },
+                    (Some(to), Err(_)) => RelayForwarderConfig {
+                        target: to.clone(),
+                        fingerprint: None,
+                        reconnect_delay: Duration::from_secs(DEFAULT_RECONNECT_SECS),
+                    },
+                    (None, result) => result?,
+                };
+                if let Some(fingerprint) = fingerprint {
+                    relay_config.fingerprint = Some(fingerprint.clone());
+                }
+                let interface = BusInfo::from_config(config)
+                    .discovery_addrs
+                    .first()
+                    .copied()
+                    .unwrap_or(std::net::Ipv4Addr::UNSPECIFIED);
+                let obs_config = ObserverConfig::from_config(config, interface);
+
+                println!("  * Observing {}:{} on {}", obs_config.group, obs_config.port, interface);
+                println!("  * Forwarding to {}", relay_config.target);
+                println!("  * Press Ctrl+C to stop");
+                run_relay_forwarder(relay_config, obs_config, shutdown_on_ctrl_c()).await
+            }
+            RelayCommand::Receive { listen, key, cert } => {
+                let setting = |arg: &Option<String>, flag: &str, key: &str| {
+                    arg.clone()
+                        .or_else(|| config.get::<String>(key).and_then(|r| r.ok()))
+                        .ok_or_else(|| anyhow!("Give --{} or set {}", flag, key))
+                };
+                let relay_config = RelayReceiverConfig {
+                    listen: setting(listen, "listen", "discovery.relay_listen")?.parse()?,
+                    key_pem: std::fs::read(setting(key, "key", "discovery.relay_key")?)?,
+                    cert_pem: std::fs::read(setting(cert, "cert", "discovery.relay_cert")?)?,
+                };
+                let receiver = RelayReceiver::bind(&relay_config).await?;
+                let registry = Arc::new(RwLock::new(ServiceRegistry::empty()));
+                let mut events = registry.read().await.subscribe();
+                tokio::spawn(async move {
+                    while let Ok(event) = events.recv().await {
+                        println!("{:?}", event);
+                    }
+                });
+
+                println!("  * Listening on {}", receiver.local_addr()?);
+                println!("  * Press Ctrl+C to stop");
+                let auth = Arc::new(RwLock::new(AuthorizedServices::from_config(config)));
+                receiver.run(registry, auth, shutdown_on_ctrl_c()).await
+            }
+        }
+    }
+}
+
+fn shutdown_on_ctrl_c() -> watch::Receiver<bool> {
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c().await.ok();
+        let _ = shutdown_tx.send(true);
+    });
+    shutdown_rx
+}
@@ -0,0 +1,125 @@
+//! BEEPish framing throughput: `PacketCodec` against the hand-rolled loops
+//! it replaced (4 KiB stack reads appended to a `Vec`, copying
+//! `Packet::parse`, `drain`; three `write_all`s per packet).
+//!
+//!     cargo bench -p scamp --bench framing
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures_util::{SinkExt, StreamExt};
+use scamp::transport::beepish::proto::{Packet, PacketCodec, PacketType, ParseResult, MAX_PACKET_SIZE};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::runtime::Runtime;
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+const BODY_SIZE: usize = 8 * 1024 * 1024;
+
+fn packets(packet_size: usize) -> Vec<Packet> {
+    let body = Bytes::from(vec![b'x'; BODY_SIZE]);
+    (0..BODY_SIZE)
+        .step_by(packet_size)
+        .map(|at| Packet {
+            packet_type: PacketType::Data,
+            msg_no: 0,
+            packet_header: None,
+            body: body.slice(at..(at + packet_size).min(BODY_SIZE)),
+        })
+        .collect()
+}
+
+async fn encode_all(packets: &[Packet]) -> Vec<u8> {
+    let mut wire = Vec::new();
+    for packet in packets {
+        packet.write(&mut wire).await.unwrap();
+    }
+    wire
+}
+
+async fn legacy_decode(mut reader: impl AsyncRead + Unpin) -> usize {
+    let mut buf = Vec::new();
+    let mut total = 0;
+    loop {
+        let mut tmp = [0u8; 4096];
+        let n = reader.read(&mut tmp).await.unwrap();
+        if n == 0 {
+            return total;
+        }
+        buf.extend_from_slice(&tmp[..n]);
+
+        let mut consumed = 0;
+        while consumed < buf.len() {
+            match Packet::parse(&buf[consumed..]) {
+                ParseResult::Success { packet, bytes_used } => {
+                    consumed += bytes_used;
+                    total += packet.body.len();
+                }
+                ParseResult::Drop { bytes_used } => consumed += bytes_used,
+                ParseResult::TooShort | ParseResult::NeedBytes { .. } => break,
+                ParseResult::Fatal(err) => panic!("{err}"),
+            }
+        }
+        buf.drain(..consumed);
+    }
+}
+
+async fn codec_decode(reader: impl AsyncRead + Unpin) -> usize {
+    let mut frames = FramedRead::new(reader, PacketCodec);
+    let mut total = 0;
+    while let Some(packet) = frames.next().await {
+        total += packet.unwrap().body.len();
+    }
+    total
+}
+
+async fn legacy_encode(packets: &[Packet]) -> usize {
+    let mut out = Vec::new();
+    for packet in packets {
+        let line = format!("{} {} {}\r\n", packet.packet_type.wire_name(), packet.msg_no, packet.body.len());
+        out.write_all(line.as_bytes()).await.unwrap();
+        out.write_all(&packet.body).await.unwrap();
+        out.write_all(b"END\r\n").await.unwrap();
+    }
+    out.len()
+}
+
+async fn codec_encode(packets: &[Packet]) -> usize {
+    let mut frames = FramedWrite::new(Vec::new(), PacketCodec);
+    for packet in packets {
+        frames.feed(packet).await.unwrap();
+    }
+    SinkExt::<&Packet>::flush(&mut frames).await.unwrap();
+    frames.into_inner().len()
+}
+
+fn framing(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+
+    let mut decode = c.benchmark_group("decode");
+    decode.throughput(Throughput::Bytes(BODY_SIZE as u64));
+    for packet_size in [2048, MAX_PACKET_SIZE] {
+        let wire = rt.block_on(encode_all(&packets(packet_size)));
+        decode.bench_with_input(BenchmarkId::new("legacy", packet_size), &wire, |b, wire| {
+            b.iter(|| rt.block_on(legacy_decode(&wire[..])))
+        });
+        decode.bench_with_input(BenchmarkId::new("codec", packet_size), &wire, |b, wire| {
+            b.iter(|| rt.block_on(codec_decode(&wire[..])))
+        });
+    }
+    decode.finish();
+
+    let mut encode = c.benchmark_group("encode");
+    encode.throughput(Throughput::Bytes(BODY_SIZE as u64));
+    for packet_size in [2048, MAX_PACKET_SIZE] {
+        let packets = packets(packet_size);
+        encode.bench_with_input(BenchmarkId::new("legacy", packet_size), &packets, |b, packets| {
+            b.iter(|| rt.block_on(legacy_encode(packets)))
+        });
+        encode.bench_with_input(BenchmarkId::new("codec", packet_size), &packets, |b, packets| {
+            b.iter(|| rt.block_on(codec_encode(packets)))
+        });
+    }
+    encode.finish();
+}
+
+criterion_group!(benches, framing);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+//! Announcement JSON bodies, including the v4 RLE action vectors.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use scamp::discovery::AnnouncementBody;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = AnnouncementBody::parse(text);
+    }
+});
@@ -0,0 +1,14 @@
+//! Whole announcements as they arrive off multicast: JSON, certificate, signature.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use scamp::discovery::packet::AnnouncementPacket;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Ok(packet) = AnnouncementPacket::parse(text) {
+            let _ = packet.signature_is_valid();
+        }
+    }
+});
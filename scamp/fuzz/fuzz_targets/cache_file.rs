@@ -0,0 +1,29 @@
+//! Discovery cache files, read in pieces whose size comes from the first byte
+//! so record delimiters land on every possible read boundary.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use scamp::discovery::cache_file::CacheFileAnnouncementIterator;
+use std::io::Read;
+
+struct Pieces<'a>(&'a [u8], usize);
+
+impl Read for Pieces<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.0.len().min(buf.len()).min(self.1);
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&piece, file)) = data.split_first() else {
+        return;
+    };
+    let reader = Pieces(file, usize::from(piece).max(1));
+    for announcement in CacheFileAnnouncementIterator::new(reader) {
+        let _ = announcement;
+    }
+});
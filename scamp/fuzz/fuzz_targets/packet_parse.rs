@@ -0,0 +1,44 @@
+//! `Packet::parse` and `PacketCodec` must agree on every input, and any
+//! packet they accept must survive being written and parsed again.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use scamp::transport::beepish::proto::{Packet, PacketCodec, ParseResult};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn header_json(packet: &Packet) -> Option<serde_json::Value> {
+    packet.packet_header.as_ref().map(|h| serde_json::to_value(h).unwrap())
+}
+
+fuzz_target!(|data: &[u8]| {
+    let parsed = Packet::parse(data);
+    let decoded = PacketCodec.decode(&mut BytesMut::from(data));
+
+    let packet = match (parsed, decoded) {
+        (ParseResult::Success { packet, bytes_used }, Ok(Some(frame))) => {
+            assert!(bytes_used <= data.len());
+            assert_eq!(packet.packet_type, frame.packet_type);
+            assert_eq!(packet.msg_no, frame.msg_no);
+            assert_eq!(packet.body, frame.body);
+            packet
+        }
+        (ParseResult::TooShort | ParseResult::NeedBytes { .. }, Ok(None)) => return,
+        (ParseResult::Fatal(_), Err(_)) => return,
+        _ => panic!("Packet::parse and PacketCodec disagree"),
+    };
+
+    let mut wire = BytesMut::new();
+    PacketCodec.encode(&packet, &mut wire).unwrap();
+    match Packet::parse(&wire) {
+        ParseResult::Success { packet: again, bytes_used } => {
+            assert_eq!(bytes_used, wire.len());
+            assert_eq!(again.packet_type, packet.packet_type);
+            assert_eq!(again.msg_no, packet.msg_no);
+            assert_eq!(again.body, packet.body);
+            assert_eq!(header_json(&again), header_json(&packet));
+        }
+        _ => panic!("re-encoded packet did not parse"),
+    }
+});
@@ -0,0 +1,14 @@
+//! Tickets as presented by clients, before any signature check.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use scamp::auth::ticket::Ticket;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Ok((ticket, _signature)) = Ticket::parse(text) {
+            let _ = ticket.has_all_privileges(&[1, 2, 3]);
+        }
+    }
+});
@@ -21,6 +21,8 @@ use std::collections::HashMap;
 use std::fs;
 use std::time::SystemTime;
 
+use crate::config::Config;
+
 /// Authorized services registry. Maps certificate fingerprints to action patterns.
 pub struct AuthorizedServices {
     entries: HashMap<String, AuthEntry>,
@@ -55,6 +57,29 @@ impl AuthorizedServices {
         }
     }
 
+    /// Build from authorized_services text held in memory; never reloads.
+    pub fn from_content(content: &str) -> Self {
+        let mut svc = Self::empty();
+        svc.parse_content(content);
+        svc
+    }
+
+    /// Load the file named by `bus.authorized_services`, or allow nothing
+    /// if it is unset or unreadable. An unreadable file is still watched by
+    /// [`reload_if_changed`](Self::reload_if_changed).
+    pub fn from_config(config: &Config) -> Self {
+        match config.get::<String>("bus.authorized_services") {
+            Some(Ok(path)) => Self::load(&path).unwrap_or_else(|e| {
+                log::warn!("Failed to load authorized_services: {}", e);
+                AuthorizedServices {
+                    file_path: path,
+                    ..Self::empty()
+                }
+            }),
+            _ => Self::empty(),
+        }
+    }
+
     /// Reload if the file has been modified (hot-reload).
     /// Matches Perl ServiceInfo.pm:117-118 mtime check.
     pub fn reload_if_changed(&mut self) -> Result<bool> {
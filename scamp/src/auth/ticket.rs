@@ -90,7 +90,7 @@ impl Ticket {
         if now < ticket.validity_start {
             return Err(anyhow!("Ticket not yet valid (starts at {})", ticket.validity_start));
         }
-        if now >= ticket.validity_start + ticket.ttl {
+        if now >= ticket.validity_start.saturating_add(ticket.ttl) {
             return Err(anyhow!("Ticket expired"));
         }
 
@@ -140,6 +140,38 @@ mod tests {
         assert!(ticket.privileges.is_empty());
     }
 
+    #[test]
+    fn test_parse_round_trips_random_tickets() {
+        use base64::Engine;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        for seed in 0..256 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let privileges: Vec<u64> = (0..rng.gen_range(0..6)).map(|_| rng.gen()).collect();
+            let signature: Vec<u8> = (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect();
+            let (user_id, client_id, validity_start, ttl): (u64, u64, u64, u64) = rng.gen();
+            let ticket_str = format!(
+                "1,{},{},{},{},{},{}",
+                user_id,
+                client_id,
+                validity_start,
+                ttl,
+                privileges.iter().map(u64::to_string).collect::<Vec<_>>().join("+"),
+                base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&signature)
+            );
+
+            let (ticket, sig) = Ticket::parse(&ticket_str).unwrap();
+            assert_eq!(
+                (ticket.user_id, ticket.client_id, ticket.validity_start, ticket.ttl),
+                (user_id, client_id, validity_start, ttl),
+                "seed {seed}"
+            );
+            assert_eq!(ticket.privileges, privileges, "seed {seed}");
+            assert_eq!(sig, signature, "seed {seed}");
+        }
+    }
+
     #[test]
     fn test_parse_ticket_too_few_fields() {
         let result = Ticket::parse("1,42,100");
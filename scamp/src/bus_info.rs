@@ -19,12 +19,37 @@ pub struct BusInfo {
     pub group: Ipv4Addr,
     /// Discovery port
     pub port: u16,
+    /// Every IPv4 interface on this host with its netmask
+    pub local_networks: Vec<LocalNetwork>,
+}
+
+/// An interface address and the subnet it sits on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalNetwork {
+    pub addr: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+}
+
+impl LocalNetwork {
+    pub fn new(addr: Ipv4Addr, netmask: Ipv4Addr) -> Self {
+        LocalNetwork { addr, netmask }
+    }
+
+    /// Whether `ip` is on this interface's subnet.
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let mask = u32::from(self.netmask);
+        u32::from(ip) & mask == u32::from(self.addr) & mask
+    }
 }
 
 impl BusInfo {
     /// Build BusInfo from config — Perl Config.pm:59-112.
     pub fn from_config(config: &Config) -> Self {
-        let interfaces = get_interface_addrs();
+        let local_networks = get_interface_networks();
+        let mut interfaces: HashMap<String, Vec<Ipv4Addr>> = HashMap::new();
+        for (name, network) in &local_networks {
+            interfaces.entry(name.clone()).or_default().push(network.addr);
+        }
         let default_ip = find_default_ip(&interfaces);
 
         let service_addrs = resolve_addr_list(config.get::<String>("bus.address"), &interfaces, default_ip);
@@ -41,6 +66,7 @@ impl BusInfo {
             discovery_addrs,
             group,
             port,
+            local_networks: local_networks.into_iter().map(|(_, network)| network).collect(),
         }
     }
 
@@ -108,10 +134,10 @@ fn find_default_ip(interfaces: &HashMap<String, Vec<Ipv4Addr>>) -> Option<Ipv4Ad
     all_ips.into_iter().next()
 }
 
-/// Enumerate network interfaces and their IPv4 addresses.
+/// Enumerate network interfaces and their IPv4 addresses and netmasks.
 /// Uses libc getifaddrs — equivalent to Perl's _build_interface_info.
-fn get_interface_addrs() -> HashMap<String, Vec<Ipv4Addr>> {
-    let mut result: HashMap<String, Vec<Ipv4Addr>> = HashMap::new();
+fn get_interface_networks() -> Vec<(String, LocalNetwork)> {
+    let mut result = Vec::new();
 
     unsafe {
         let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
@@ -125,9 +151,14 @@ fn get_interface_addrs() -> HashMap<String, Vec<Ipv4Addr>> {
             let ifa = &*current;
             if !ifa.ifa_addr.is_null() && (*ifa.ifa_addr).sa_family == libc::AF_INET as libc::sa_family_t {
                 let name = std::ffi::CStr::from_ptr(ifa.ifa_name).to_string_lossy().into_owned();
-                let sockaddr = ifa.ifa_addr as *const libc::sockaddr_in;
-                let ip = Ipv4Addr::from(u32::from_be((*sockaddr).sin_addr.s_addr));
-                result.entry(name).or_default().push(ip);
+                let ip = Ipv4Addr::from(u32::from_be((*(ifa.ifa_addr as *const libc::sockaddr_in)).sin_addr.s_addr));
+                // A missing netmask means a host route: only the address itself.
+                let netmask = if ifa.ifa_netmask.is_null() {
+                    Ipv4Addr::BROADCAST
+                } else {
+                    Ipv4Addr::from(u32::from_be((*(ifa.ifa_netmask as *const libc::sockaddr_in)).sin_addr.s_addr))
+                };
+                result.push((name, LocalNetwork::new(ip, netmask)));
             }
             current = ifa.ifa_next;
         }
@@ -142,11 +173,22 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_get_interface_addrs_has_loopback() {
-        let addrs = get_interface_addrs();
+    fn test_get_interface_networks_has_loopback() {
+        let networks = get_interface_networks();
         // Every system has a loopback interface
-        let has_loopback = addrs.values().flatten().any(|ip| ip.is_loopback());
-        assert!(has_loopback, "Should find loopback interface");
+        let loopback = networks.iter().map(|(_, n)| n).find(|n| n.addr.is_loopback());
+        let loopback = loopback.expect("Should find loopback interface");
+        assert!(loopback.contains(Ipv4Addr::new(127, 0, 0, 2)));
+    }
+
+    #[test]
+    fn test_local_network_contains() {
+        let network = LocalNetwork::new(Ipv4Addr::new(10, 1, 2, 3), Ipv4Addr::new(255, 255, 255, 0));
+        assert!(network.contains(Ipv4Addr::new(10, 1, 2, 200)));
+        assert!(!network.contains(Ipv4Addr::new(10, 1, 3, 3)));
+        let host = LocalNetwork::new(Ipv4Addr::new(10, 1, 2, 3), Ipv4Addr::BROADCAST);
+        assert!(host.contains(Ipv4Addr::new(10, 1, 2, 3)));
+        assert!(!host.contains(Ipv4Addr::new(10, 1, 2, 4)));
     }
 
     #[test]
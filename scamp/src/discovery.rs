@@ -1,8 +1,26 @@
 pub mod cache_file;
+pub mod cache_writer;
+#[cfg(test)]
+mod cache_writer_tests;
+pub mod events;
+pub mod live;
+#[cfg(test)]
+mod live_tests;
 pub mod observer;
 pub mod packet;
+pub mod relay;
+#[cfg(test)]
+mod relay_tests;
+pub mod selection;
+#[cfg(test)]
+mod selection_tests;
 pub mod service_info;
 pub mod service_registry;
+#[cfg(test)]
+mod service_registry_tests;
+pub mod signatures;
+pub mod static_services;
 
+pub use events::{GoneReason, RegistryEvent};
 pub use service_info::*;
 pub use service_registry::*;
@@ -9,7 +9,7 @@ pub struct CacheFileAnnouncementIterator<R: Read> {
     reader: BufReader<R>,
 }
 
-static RECORD_DELIMITER: &str = "\n%%%\n";
+pub(crate) static RECORD_DELIMITER: &str = "\n%%%\n";
 
 impl<R: Read> CacheFileAnnouncementIterator<R> {
     pub fn new(reader: R) -> Self {
@@ -30,7 +30,7 @@ impl<R: Read> Iterator for CacheFileAnnouncementIterator<R> {
 
         loop {
             let (consume, matched) = match self.reader.fill_buf() {
-                Ok(buffer) if buffer.is_empty() => {
+                Ok([]) => {
                     // M6: EOF — yield any accumulated data as final record
                     if announcement_data.is_empty() {
                         return None;
@@ -41,13 +41,22 @@ impl<R: Read> Iterator for CacheFileAnnouncementIterator<R> {
                     }
                 }
                 Ok(buffer) => {
-                    if let Some(pos) = buffer.windows(delimiter_bytes.len()).position(|window| window == delimiter_bytes) {
-                        announcement_data.extend_from_slice(&buffer[..pos]);
-                        (pos + delimiter_bytes.len(), true)
-                    } else {
-                        announcement_data.extend_from_slice(buffer);
-
-                        (buffer.len(), false)
+                    // The delimiter may straddle two reads, so search from
+                    // just before where this read's bytes start.
+                    let start = announcement_data.len().saturating_sub(delimiter_bytes.len() - 1);
+                    let previous = announcement_data.len();
+                    announcement_data.extend_from_slice(buffer);
+                    match announcement_data[start..]
+                        .windows(delimiter_bytes.len())
+                        .position(|window| window == delimiter_bytes)
+                    {
+                        Some(pos) => {
+                            let end = start + pos;
+                            let consume = end + delimiter_bytes.len() - previous;
+                            announcement_data.truncate(end);
+                            (consume, true)
+                        }
+                        None => (buffer.len(), false),
                     }
                 }
                 Err(e) => return Some(Err(e.into())),
@@ -81,4 +90,27 @@ mod tests {
         let ok_count = announcements.iter().filter(|a| a.is_ok()).count();
         assert!(ok_count > 0, "At least one announcement should parse successfully");
     }
+
+    /// Hands out its data a few bytes at a time, like a slow pipe.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(self.1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_delimiter_split_across_reads() {
+        let record = include_str!("../../samples/service_info_packet_v3_full.txt").trim_end();
+        let file = [record; 3].join(RECORD_DELIMITER);
+        for step in [1, 2, 3, 4, 5, 7, 4096] {
+            let announcements: Vec<_> = CacheFileAnnouncementIterator::new(Trickle(file.as_bytes(), step)).collect();
+            assert_eq!(announcements.len(), 3, "reads of {step} bytes");
+            assert!(announcements.iter().all(|a| a.is_ok()), "reads of {step} bytes");
+        }
+    }
 }
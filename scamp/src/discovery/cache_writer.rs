@@ -0,0 +1,214 @@
+//! Discovery cache writer: a Rust stand-in for the Perl cache manager.
+//!
+//! Observes multicast announcements, keeps the newest validly signed one per
+//! service, and rewrites `discovery.cache_path` on an interval. Each rewrite
+//! goes to a temporary file that is renamed into place, so readers never see
+//! a partial cache.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::watch;
+
+use super::cache_file::{CacheFileAnnouncementIterator, RECORD_DELIMITER};
+use super::observer::{observe_packets, ObserverConfig};
+use super::packet::AnnouncementPacket;
+use super::signatures::SignatureCache;
+use crate::bus_info::BusInfo;
+use crate::config::Config;
+
+/// Rewrite interval when `discovery.cache_write_interval` is unset.
+pub const DEFAULT_WRITE_INTERVAL_SECS: u64 = 5;
+
+/// Where and how often to write the cache.
+#[derive(Debug, Clone)]
+pub struct CacheWriterConfig {
+    pub path: PathBuf,
+    pub interval: Duration,
+}
+
+impl CacheWriterConfig {
+    /// `discovery.cache_path` (required) and `discovery.cache_write_interval` in seconds.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let path: String = config
+            .get("discovery.cache_path")
+            .ok_or_else(|| anyhow!("No cache path found"))?
+            .map_err(|e| anyhow!("Failed to get cache path: {}", e))?;
+        let interval = config
+            .get::<u64>("discovery.cache_write_interval")
+            .and_then(|r| r.ok())
+            .unwrap_or(DEFAULT_WRITE_INTERVAL_SECS);
+        Ok(CacheWriterConfig {
+            path: path.into(),
+            interval: Duration::from_secs(interval.max(1)),
+        })
+    }
+}
+
+/// The newest announcement per service, ready to be written out.
+pub struct CacheWriter {
+    config: CacheWriterConfig,
+    /// Keyed like the registry's replay state: `fingerprint identity`.
+    latest: Mutex<HashMap<String, AnnouncementPacket>>,
+    signatures: SignatureCache,
+}
+
+impl CacheWriter {
+    /// A writer that starts from whatever unexpired records are already in
+    /// the cache file, so a restart doesn't blank it.
+    pub fn new(config: CacheWriterConfig) -> Self {
+        let writer = CacheWriter {
+            config,
+            latest: Mutex::default(),
+            signatures: SignatureCache::new(),
+        };
+        if let Ok(file) = File::open(&writer.config.path) {
+            let packets = CacheFileAnnouncementIterator::new(file).flatten().collect();
+            for checked in writer.signatures.check_all(packets) {
+                if checked.valid {
+                    writer.keep(checked.packet, now());
+                }
+            }
+        }
+        writer
+    }
+
+    /// Keep `packet` if it is validly signed, unexpired and newer than what
+    /// is held for its service. Verifies the signature, so this blocks.
+    pub fn offer(&self, packet: AnnouncementPacket) -> bool {
+        let checked = self.signatures.check(packet);
+        if !checked.valid {
+            log::debug!(
+                "Cache writer: dropping badly signed announcement from {}",
+                checked.packet.body.info.identity
+            );
+            return false;
+        }
+        self.keep(checked.packet, now())
+    }
+
+    fn keep(&self, packet: AnnouncementPacket, now: f64) -> bool {
+        if packet.body.params.expires_at() < now {
+            return false;
+        }
+        let info = &packet.body.info;
+        let key = format!("{} {}", info.fingerprint.as_deref().unwrap_or(""), info.identity);
+        let mut latest = self.latest.lock().unwrap();
+        match latest.get(&key) {
+            Some(held) if held.body.params.timestamp >= packet.body.params.timestamp => false,
+            _ => {
+                latest.insert(key, packet);
+                true
+            }
+        }
+    }
+
+    /// Services currently held.
+    pub fn len(&self) -> usize {
+        self.latest.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop expired announcements and atomically rewrite the cache file.
+    /// Blocks on file IO. Returns the number of records written.
+    pub fn flush(&self) -> std::io::Result<usize> {
+        self.flush_as_of(now())
+    }
+
+    pub(crate) fn flush_as_of(&self, now: f64) -> std::io::Result<usize> {
+        let records = {
+            let mut latest = self.latest.lock().unwrap();
+            latest.retain(|_, packet| packet.body.params.expires_at() >= now);
+            let mut records: Vec<_> = latest.iter().map(|(key, packet)| (key.clone(), packet.text())).collect();
+            records.sort();
+            records
+        };
+        let mut contents = String::new();
+        for (_, text) in &records {
+            contents.push_str(text);
+            contents.push_str(RECORD_DELIMITER);
+        }
+        write_atomic(&self.config.path, contents.as_bytes())?;
+        Ok(records.len())
+    }
+
+    /// Feed the writer from the multicast observer and flush every
+    /// interval until shutdown, then flush once more.
+    pub async fn run(self: Arc<Self>, obs_config: ObserverConfig, mut shutdown_rx: watch::Receiver<bool>) -> Result<()> {
+        let observe = observe_packets(obs_config, shutdown_rx.clone(), |packet| {
+            let writer = self.clone();
+            async move {
+                let _ = tokio::task::spawn_blocking(move || writer.offer(packet)).await;
+            }
+        });
+        let flush = async {
+            let mut ticker = tokio::time::interval(self.config.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                self.flush_in_background().await;
+            }
+        };
+        let result = tokio::select! {
+            result = observe => result,
+            _ = flush => unreachable!("flush loop never ends"),
+            _ = shutdown_rx.wait_for(|stop| *stop) => Ok(()),
+        };
+        self.flush_in_background().await;
+        result
+    }
+
+    async fn flush_in_background(self: &Arc<Self>) {
+        let writer = self.clone();
+        match tokio::task::spawn_blocking(move || writer.flush()).await {
+            Ok(Ok(records)) => log::debug!("Wrote {} announcements to {}", records, self.config.path.display()),
+            Ok(Err(e)) => log::error!("Failed to write discovery cache {}: {}", self.config.path.display(), e),
+            Err(e) => log::error!("Discovery cache write task failed: {}", e),
+        }
+    }
+}
+
+/// Observe multicast on this host's discovery interface and maintain
+/// `discovery.cache_path` until shutdown.
+pub async fn run_cache_writer(config: &Config, shutdown_rx: watch::Receiver<bool>) -> Result<()> {
+    let writer_config = CacheWriterConfig::from_config(config)?;
+    let interface = BusInfo::from_config(config)
+        .discovery_addrs
+        .first()
+        .copied()
+        .unwrap_or(std::net::Ipv4Addr::UNSPECIFIED);
+    let obs_config = ObserverConfig::from_config(config, interface);
+    let writer = tokio::task::spawn_blocking(move || CacheWriter::new(writer_config)).await?;
+    Arc::new(writer).run(obs_config, shutdown_rx).await
+}
+
+/// Write `contents` to a temporary file beside `path`, then rename it over `path`.
+fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("discovery");
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use super::cache_file::CacheFileAnnouncementIterator;
+use super::cache_writer::{CacheWriter, CacheWriterConfig};
+use super::packet::AnnouncementPacket;
+use crate::test_helpers::{generate_test_keypair, signed_announcement};
+
+fn packet(text: &str) -> AnnouncementPacket {
+    AnnouncementPacket::parse(text).unwrap()
+}
+
+fn writer_in(dir: &tempfile::TempDir) -> CacheWriter {
+    CacheWriter::new(CacheWriterConfig {
+        path: dir.path().join("discovery"),
+        interval: Duration::from_secs(1),
+    })
+}
+
+fn identities(dir: &tempfile::TempDir) -> Vec<String> {
+    let file = std::fs::File::open(dir.path().join("discovery")).unwrap();
+    CacheFileAnnouncementIterator::new(file)
+        .map(|p| {
+            let p = p.unwrap();
+            assert!(p.signature_is_valid(), "records are written back byte for byte");
+            p.body.info.identity
+        })
+        .collect()
+}
+
+#[test]
+fn test_keeps_newest_valid_announcement_per_service() {
+    let keypair = generate_test_keypair();
+    let dir = tempfile::tempdir().unwrap();
+    let writer = writer_in(&dir);
+
+    let old = signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"]);
+    let new = signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch", "Widget.store"]);
+    assert!(writer.offer(packet(&old)));
+    assert!(writer.offer(packet(&new)));
+    assert!(!writer.offer(packet(&old)), "older announcement replaces nothing");
+
+    let mut tampered = packet(&signed_announcement(&keypair, "svc:2", 5, &["Widget.fetch"]));
+    tampered.json_blob = tampered.json_blob.replace("Widget", "Gadget");
+    assert!(!writer.offer(tampered));
+
+    assert!(writer.offer(packet(&signed_announcement(&keypair, "svc:3", 5, &["Widget.fetch"]))));
+    assert_eq!(writer.flush().unwrap(), 2);
+    assert_eq!(identities(&dir), ["svc:1", "svc:3"]);
+
+    let file = std::fs::File::open(dir.path().join("discovery")).unwrap();
+    let svc1 = CacheFileAnnouncementIterator::new(file).next().unwrap().unwrap();
+    assert_eq!(svc1.body.actions.len(), 2);
+    let leftovers: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+    assert_eq!(leftovers.len(), 1, "no temporary files left behind");
+}
+
+#[test]
+fn test_expired_records_are_dropped_and_restart_resumes() {
+    let keypair = generate_test_keypair();
+    let dir = tempfile::tempdir().unwrap();
+    let writer = writer_in(&dir);
+    let text = signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"]);
+    writer.offer(packet(&text));
+    writer.flush().unwrap();
+
+    // A new writer picks up where the file left off.
+    let restarted = writer_in(&dir);
+    assert_eq!(restarted.len(), 1);
+
+    let sent = packet(&text).body.params.timestamp;
+    assert_eq!(restarted.flush_as_of(sent + 11.0).unwrap(), 0);
+    assert!(restarted.is_empty());
+    assert!(identities(&dir).is_empty());
+}
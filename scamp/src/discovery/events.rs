@@ -0,0 +1,130 @@
+//! Change notifications from a [`ServiceRegistry`](super::ServiceRegistry).
+//!
+//! Subscribers get a [`RegistryEvent`] for every service-level change the
+//! registry makes, whether from multicast, a cache reload or expiry. Events
+//! are worked out by comparing per-service summaries before and after each
+//! change, so a reload that re-reads an unchanged cache is silent.
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::service_registry::ActionEntry;
+
+/// Events buffered per subscriber before the slowest one starts lagging.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// Something changed about a service instance in the registry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryEvent {
+    /// A service the registry hadn't seen, or had dropped, is now indexed.
+    ServiceAppeared {
+        identity: String,
+        uri: String,
+        weight: u32,
+        actions: Vec<String>,
+    },
+    /// A known service announced a different set of actions.
+    ActionsChanged {
+        identity: String,
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    /// A known, routable service announced a new weight.
+    WeightChanged { identity: String, from: u32, to: u32 },
+    /// A service is no longer routable.
+    ServiceGone { identity: String, reason: GoneReason },
+    /// An announcement failed signature verification and was dropped.
+    SignatureRejected { identity: String, fingerprint: Option<String> },
+}
+
+/// Why a [`RegistryEvent::ServiceGone`] was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoneReason {
+    /// Its announcements stopped, or it left the cache file.
+    Expired,
+    /// It announced weight 0, usually because it is shutting down.
+    WeightZero,
+}
+
+/// What subscribers are told about for one service.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ServiceSummary {
+    uri: String,
+    weight: u32,
+    /// `sector:path.vN`, without CRUD aliases.
+    actions: BTreeSet<String>,
+}
+
+/// Summarise the indexed entries by service identity.
+pub(crate) fn summarize<'a>(entries: impl Iterator<Item = &'a ActionEntry>) -> HashMap<String, ServiceSummary> {
+    let mut services: HashMap<String, ServiceSummary> = HashMap::new();
+    for entry in entries {
+        let summary = services
+            .entry(entry.service_info.identity.clone())
+            .or_insert_with(|| ServiceSummary {
+                uri: entry.service_info.uri.clone(),
+                weight: entry.announcement_params.weight,
+                actions: BTreeSet::new(),
+            });
+        let action = &entry.action;
+        summary
+            .actions
+            .insert(format!("{}:{}.v{}", action.sector, action.path, action.version));
+    }
+    services
+}
+
+/// The events that turn `before` into `after`, in identity order.
+pub(crate) fn diff(before: &HashMap<String, ServiceSummary>, after: &HashMap<String, ServiceSummary>) -> Vec<RegistryEvent> {
+    let identities: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let mut events = Vec::new();
+    for identity in identities {
+        let identity = identity.clone();
+        let (old, new) = match (before.get(&identity), after.get(&identity)) {
+            (Some(old), Some(new)) if old.weight > 0 || new.weight == 0 => (old, new),
+            // Dropped, or only just routable again after weight 0.
+            (_, Some(new)) => {
+                if new.weight > 0 {
+                    events.push(RegistryEvent::ServiceAppeared {
+                        identity,
+                        uri: new.uri.clone(),
+                        weight: new.weight,
+                        actions: new.actions.iter().cloned().collect(),
+                    });
+                }
+                continue;
+            }
+            (Some(old), None) => {
+                if old.weight > 0 {
+                    events.push(RegistryEvent::ServiceGone {
+                        identity,
+                        reason: GoneReason::Expired,
+                    });
+                }
+                continue;
+            }
+            (None, None) => continue,
+        };
+        if old.actions != new.actions {
+            events.push(RegistryEvent::ActionsChanged {
+                identity: identity.clone(),
+                added: new.actions.difference(&old.actions).cloned().collect(),
+                removed: old.actions.difference(&new.actions).cloned().collect(),
+            });
+        }
+        if old.weight != new.weight {
+            events.push(if new.weight == 0 {
+                RegistryEvent::ServiceGone {
+                    identity,
+                    reason: GoneReason::WeightZero,
+                }
+            } else {
+                RegistryEvent::WeightChanged {
+                    identity,
+                    from: old.weight,
+                    to: new.weight,
+                }
+            });
+        }
+    }
+    events
+}
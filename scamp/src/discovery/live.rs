@@ -0,0 +1,210 @@
+//! Background tasks that keep a shared `ServiceRegistry` current.
+//!
+//! Perl ServiceManager.pm re-reads the cache file when it changes and the
+//! Observer injects multicast announcements as they arrive; this runs either
+//! or both, and optionally a relay receiver, against an
+//! `Arc<RwLock<ServiceRegistry>>`. Reloads merge with what the other sources
+//! injected, each service keeping its newest announcement.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::{watch, RwLock};
+use tokio::task::JoinHandle;
+
+use super::observer::{run_observer, ObserverConfig};
+use super::relay::{RelayReceiver, RelayReceiverConfig};
+use super::service_registry::{load_cache, ServiceRegistry};
+use crate::auth::authorized_services::AuthorizedServices;
+use crate::bus_info::BusInfo;
+use crate::config::Config;
+
+/// Which background sources feed a live registry.
+#[derive(Debug, Clone)]
+pub struct LiveDiscoveryConfig {
+    /// Join the multicast group and inject announcements as they arrive.
+    pub observe_multicast: bool,
+    /// Check `discovery.cache_path`, `bus.authorized_services` and
+    /// `discovery.static_services` at this interval, reloading the registry
+    /// when any has changed.
+    pub cache_reload_interval: Option<Duration>,
+    /// Sweep expired services out of the registry at this interval.
+    pub expiry_sweep_interval: Option<Duration>,
+    /// Accept announcements from relay forwarders on `discovery.relay_listen`.
+    pub receive_relay: bool,
+}
+
+impl Default for LiveDiscoveryConfig {
+    fn default() -> Self {
+        LiveDiscoveryConfig {
+            observe_multicast: true,
+            cache_reload_interval: Some(Duration::from_secs(2)),
+            expiry_sweep_interval: Some(Duration::from_secs(1)),
+            receive_relay: false,
+        }
+    }
+}
+
+/// Handle to the running background tasks. Dropping it stops them.
+pub struct LiveDiscovery {
+    shutdown_tx: watch::Sender<bool>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl LiveDiscovery {
+    /// Spawn the configured sources. Must be called from within a tokio runtime.
+    pub fn spawn(config: &Config, registry: Arc<RwLock<ServiceRegistry>>, live_config: &LiveDiscoveryConfig) -> Self {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mut handles = Vec::new();
+        // One copy for every source, so an authorized_services reload
+        // reaches them all.
+        let auth = Arc::new(RwLock::new(AuthorizedServices::from_config(config)));
+
+        if live_config.observe_multicast {
+            let interface = BusInfo::from_config(config)
+                .discovery_addrs
+                .first()
+                .copied()
+                .unwrap_or(std::net::Ipv4Addr::UNSPECIFIED);
+            let obs_config = ObserverConfig::from_config(config, interface);
+            let (registry, auth) = (registry.clone(), auth.clone());
+            let shutdown_rx = shutdown_rx.clone();
+            handles.push(tokio::spawn(async move {
+                if let Err(e) = run_observer(obs_config, registry, auth, shutdown_rx).await {
+                    log::error!("Discovery observer failed: {}", e);
+                }
+            }));
+        }
+
+        if live_config.receive_relay {
+            let relay_config = RelayReceiverConfig::from_config(config);
+            let (registry, auth) = (registry.clone(), auth.clone());
+            let shutdown_rx = shutdown_rx.clone();
+            handles.push(tokio::spawn(async move {
+                let result = async { RelayReceiver::bind(&relay_config?).await?.run(registry, auth, shutdown_rx).await };
+                if let Err(e) = result.await {
+                    log::error!("Discovery relay receiver failed: {:#}", e);
+                }
+            }));
+        }
+
+        if let Some(interval) = live_config.cache_reload_interval {
+            handles.push(tokio::spawn(reload_loop(
+                config.clone(),
+                registry.clone(),
+                auth,
+                interval,
+                shutdown_rx.clone(),
+            )));
+        }
+
+        if let Some(interval) = live_config.expiry_sweep_interval {
+            handles.push(tokio::spawn(expiry_loop(registry, interval, shutdown_rx)));
+        }
+
+        LiveDiscovery { shutdown_tx, handles }
+    }
+}
+
+impl Drop for LiveDiscovery {
+    fn drop(&mut self) {
+        let _ = self.shutdown_tx.send(true);
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+/// The modification time and length of each file a cache reload reads,
+/// where it exists. A reload is due when these differ from the last one.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SourceStamps(Vec<Option<(SystemTime, u64)>>);
+
+impl SourceStamps {
+    pub(crate) fn read(config: &Config) -> Self {
+        let stamp = |key: &str| {
+            let path = config.get::<String>(key).and_then(|r| r.ok())?;
+            let meta = std::fs::metadata(path).ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        };
+        SourceStamps(vec![
+            stamp("discovery.cache_path"),
+            stamp("bus.authorized_services"),
+            stamp("discovery.static_services"),
+        ])
+    }
+}
+
+/// Reload the cache file into the shared registry whenever it, the
+/// authorized_services file or the static services file changes. A reload
+/// that fails is retried once any of them changes again, not every tick.
+/// A changed authorized_services file is also loaded into `auth` and every
+/// indexed service judged by it again, however it reached the registry.
+async fn reload_loop(
+    config: Config,
+    registry: Arc<RwLock<ServiceRegistry>>,
+    auth: Arc<RwLock<AuthorizedServices>>,
+    interval: Duration,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    let mut attempted: Option<SourceStamps> = None;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown_rx.changed() => {
+                if *shutdown_rx.borrow() { break; }
+            }
+        }
+        // Stamped before reading, so a write that lands mid-reload is
+        // picked up next time round.
+        let stamps = SourceStamps::read(&config);
+        if attempted.as_ref() == Some(&stamps) {
+            continue;
+        }
+        attempted = Some(stamps);
+        reauthorize(&registry, &auth).await;
+        // File IO and signature checks are blocking; keep them off the async
+        // workers, and outside the write lock so lookups carry on meanwhile.
+        let signatures = registry.read().await.signatures();
+        let config = config.clone();
+        let result = tokio::task::spawn_blocking(move || load_cache(&config, &signatures)).await;
+        match result {
+            Ok(Ok(contents)) => {
+                registry.write().await.merge_cache(contents);
+                log::debug!("Reloaded discovery cache");
+            }
+            Ok(Err(e)) => log::warn!("Discovery cache reload failed, retrying when its files change: {}", e),
+            Err(e) => log::error!("Discovery cache reload task failed: {}", e),
+        }
+    }
+}
+
+/// Re-read the authorized_services file into `auth` if it has changed, and
+/// if so re-check the registry against it. `auth` is locked before
+/// `registry`, as the observer and relay receiver do.
+async fn reauthorize(registry: &RwLock<ServiceRegistry>, auth: &Arc<RwLock<AuthorizedServices>>) {
+    let mut writable = auth.clone().write_owned().await;
+    let result = tokio::task::spawn_blocking(move || writable.reload_if_changed()).await;
+    match result {
+        Ok(Ok(true)) => {
+            registry.write().await.reauthorize(&*auth.read().await);
+            log::debug!("Reloaded authorized_services");
+        }
+        Ok(Ok(false)) => {}
+        Ok(Err(e)) => log::warn!("authorized_services reload failed, keeping the previous one: {}", e),
+        Err(e) => log::error!("authorized_services reload task failed: {}", e),
+    }
+}
+
+/// Periodically drop services whose announcements have lapsed.
+async fn expiry_loop(registry: Arc<RwLock<ServiceRegistry>>, interval: Duration, mut shutdown_rx: watch::Receiver<bool>) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown_rx.changed() => {
+                if *shutdown_rx.borrow() { break; }
+            }
+        }
+        registry.write().await.expire_stale();
+    }
+}
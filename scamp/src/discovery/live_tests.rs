@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use super::live::{LiveDiscovery, LiveDiscoveryConfig, SourceStamps};
+use super::packet::AnnouncementPacket;
+use super::service_registry::ServiceRegistry;
+use crate::auth::authorized_services::AuthorizedServices;
+use crate::config::Config;
+use crate::test_helpers::{generate_test_keypair, signed_announcement};
+
+const KEY: &str = "main:widget.fetch.v1";
+
+async fn wait_for(registry: &RwLock<ServiceRegistry>, what: &str, done: impl Fn(&ServiceRegistry) -> bool) {
+    for _ in 0..250 {
+        if done(&*registry.read().await) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("timed out waiting for {what}");
+}
+
+#[test]
+fn test_stamps_follow_file_changes() {
+    let cache = tempfile::NamedTempFile::new().unwrap();
+    let config = Config::from_content(&format!("discovery.cache_path = {}", cache.path().display())).unwrap();
+
+    let first = SourceStamps::read(&config);
+    assert_eq!(SourceStamps::read(&config), first, "untouched files need no reload");
+    std::fs::write(cache.path(), "changed").unwrap();
+    assert_ne!(SourceStamps::read(&config), first);
+}
+
+#[tokio::test]
+async fn test_auth_change_reloads_and_keeps_failures() {
+    let keypair = generate_test_keypair();
+    let one = signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"]);
+    let two = signed_announcement(&keypair, "svc:2", 5, &["Widget.fetch"]);
+    let fingerprint = AnnouncementPacket::parse(&one).unwrap().body.info.fingerprint.unwrap();
+    let cache = tempfile::NamedTempFile::new().unwrap();
+    let auth = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(cache.path(), format!("{one}\n%%%\n{two}")).unwrap();
+    let config = Config::from_content(&format!(
+        "discovery.cache_path = {}\nbus.authorized_services = {}\ndiscovery.cache_max_age = 9999",
+        cache.path().display(),
+        auth.path().display()
+    ))
+    .unwrap();
+
+    let registry = Arc::new(RwLock::new(ServiceRegistry::empty()));
+    let live_config = LiveDiscoveryConfig {
+        observe_multicast: false,
+        cache_reload_interval: Some(Duration::from_millis(20)),
+        expiry_sweep_interval: None,
+        ..Default::default()
+    };
+    let _live = LiveDiscovery::spawn(&config, registry.clone(), &live_config);
+
+    wait_for(&registry, "the cache", |r| r.find_actions(KEY).is_some_and(|e| e.len() == 2)).await;
+    assert!(registry.read().await.get_action(KEY).is_none(), "nothing authorized yet");
+    registry.read().await.mark_failed("svc:1");
+
+    // Only the authorized_services file changes.
+    std::fs::write(auth.path(), format!("{fingerprint} main:ALL\n")).unwrap();
+    wait_for(&registry, "the auth reload", |r| r.get_action(KEY).is_some()).await;
+
+    let registry = registry.read().await;
+    for _ in 0..20 {
+        assert_eq!(registry.get_action(KEY).unwrap().service_info.identity, "svc:2");
+    }
+}
+
+#[tokio::test]
+async fn test_auth_change_reaches_announced_services() {
+    let keypair = generate_test_keypair();
+    let announcement = AnnouncementPacket::parse(&signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"])).unwrap();
+    let fingerprint = announcement.body.info.fingerprint.clone().unwrap();
+    let auth = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(auth.path(), format!("{fingerprint} main:ALL\n")).unwrap();
+    let config = Config::from_content(&format!("bus.authorized_services = {}", auth.path().display())).unwrap();
+
+    let registry = Arc::new(RwLock::new(ServiceRegistry::empty()));
+    let live_config = LiveDiscoveryConfig {
+        observe_multicast: false,
+        cache_reload_interval: Some(Duration::from_millis(20)),
+        expiry_sweep_interval: None,
+        ..Default::default()
+    };
+    let _live = LiveDiscovery::spawn(&config, registry.clone(), &live_config);
+
+    // As the observer would, judged by the file as it stood.
+    registry
+        .write()
+        .await
+        .inject_packet(announcement, &AuthorizedServices::from_config(&config));
+    assert!(registry.read().await.get_action(KEY).is_some());
+
+    std::fs::write(auth.path(), "# revoked\n").unwrap();
+    wait_for(&registry, "the revocation", |r| r.get_action(KEY).is_none()).await;
+    assert_eq!(
+        registry.read().await.find_actions(KEY).unwrap().len(),
+        1,
+        "still indexed, just not routable"
+    );
+
+    std::fs::write(auth.path(), format!("{fingerprint} main:widget\n")).unwrap();
+    wait_for(&registry, "the grant", |r| r.get_action(KEY).is_some()).await;
+}
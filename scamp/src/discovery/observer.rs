@@ -1,6 +1,7 @@
 //! Multicast observer: receives announcements from other services (D24).
 //! Perl Observer.pm: joins multicast group, decompresses packets, injects into registry.
 
+use std::future::Future;
 use std::net::{Ipv4Addr, SocketAddrV4};
 use std::sync::Arc;
 
@@ -9,6 +10,7 @@ use tokio::sync::RwLock;
 
 use super::packet::AnnouncementPacket;
 use super::service_registry::ServiceRegistry;
+use super::signatures::SignatureCache;
 use crate::auth::authorized_services::AuthorizedServices;
 use crate::config::Config;
 
@@ -32,13 +34,51 @@ impl ObserverConfig {
 }
 
 /// Run the multicast observer loop — Perl Observer.pm:18-61.
-/// Listens for zlib-compressed announcement packets and injects them into the registry.
+/// Each announcement's signature is checked before the registry write lock
+/// is taken (M8), so lookups aren't stalled behind RSA.
 pub async fn run_observer(
     obs_config: ObserverConfig,
     registry: Arc<RwLock<ServiceRegistry>>,
-    auth: Arc<AuthorizedServices>,
-    mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
+    auth: Arc<RwLock<AuthorizedServices>>,
+    shutdown_rx: tokio::sync::watch::Receiver<bool>,
 ) -> Result<()> {
+    let signatures = registry.read().await.signatures();
+    observe_packets(obs_config, shutdown_rx, |packet| {
+        let (registry, auth, signatures) = (registry.clone(), auth.clone(), signatures.clone());
+        async move { inject_verified(&registry, &auth, signatures, packet).await }
+    })
+    .await
+}
+
+/// Check `packet`'s signature on a blocking thread, then take the write
+/// lock just to inject it. `auth` is locked before `registry`, as the
+/// reload loop does.
+pub(crate) async fn inject_verified(
+    registry: &RwLock<ServiceRegistry>,
+    auth: &RwLock<AuthorizedServices>,
+    signatures: Arc<SignatureCache>,
+    packet: AnnouncementPacket,
+) {
+    match tokio::task::spawn_blocking(move || signatures.check(packet)).await {
+        Ok(checked) => {
+            let auth = auth.read().await;
+            registry.write().await.inject_checked(checked, &auth)
+        }
+        Err(e) => log::error!("Signature check failed: {}", e),
+    }
+}
+
+/// Join the multicast group and hand each announcement received to
+/// `on_packet`, unverified, until shutdown.
+pub async fn observe_packets<F, Fut>(
+    obs_config: ObserverConfig,
+    mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
+    mut on_packet: F,
+) -> Result<()>
+where
+    F: FnMut(AnnouncementPacket) -> Fut,
+    Fut: Future<Output = ()>,
+{
     let socket = create_observer_socket(&obs_config)?;
     let socket = tokio::net::UdpSocket::from_std(socket)?;
     let mut buf = vec![0u8; 65536];
@@ -54,8 +94,9 @@ pub async fn run_observer(
         tokio::select! {
             result = socket.recv_from(&mut buf) => {
                 let (len, _src) = result?;
-                if let Err(e) = process_packet(&buf[..len], &registry, &auth).await {
-                    log::debug!("Observer: failed to process packet: {}", e);
+                match decode_packet(&buf[..len]) {
+                    Ok(packet) => on_packet(packet).await,
+                    Err(e) => log::debug!("Observer: failed to process packet: {}", e),
                 }
             }
             _ = shutdown_rx.changed() => {
@@ -68,8 +109,8 @@ pub async fn run_observer(
     Ok(())
 }
 
-/// Process a single received multicast packet.
-async fn process_packet(data: &[u8], registry: &Arc<RwLock<ServiceRegistry>>, auth: &AuthorizedServices) -> Result<()> {
+/// Decode a single received multicast packet.
+fn decode_packet(data: &[u8]) -> Result<AnnouncementPacket> {
     // Perl Observer.pm:48 — skip 'R' or 'D' prefix if present
     let data = match data.first() {
         Some(b'R') | Some(b'D') => &data[1..],
@@ -80,14 +121,7 @@ async fn process_packet(data: &[u8], registry: &Arc<RwLock<ServiceRegistry>>, au
     let decompressed = zlib_decompress(data)?;
     let text = std::str::from_utf8(&decompressed)?;
 
-    // Parse the announcement packet
-    let packet = AnnouncementPacket::parse(text)?;
-
-    // Inject into registry (holds write lock briefly)
-    let mut reg = registry.write().await;
-    reg.inject_packet(packet, auth);
-
-    Ok(())
+    Ok(AnnouncementPacket::parse(text)?)
 }
 
 /// Decompress zlib data — inverse of multicast.rs zlib_compress.
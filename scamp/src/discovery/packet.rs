@@ -70,6 +70,11 @@ impl AnnouncementPacket {
         })
     }
 
+    /// The announcement as it was sent, for writing back to a cache file.
+    pub fn text(&self) -> String {
+        format!("{}\n\n{}\n\n{}", self.json_blob, self.certificate, self.signature)
+    }
+
     /// Verify the RSA PKCS1v15 SHA256 signature of this announcement.
     /// The signed content is the JSON blob (position 0 of the `\n\n`-split packet).
     /// Matches Perl ServiceInfo.pm:91-108 and Go verify.go:20-37.
@@ -130,12 +135,10 @@ mod tests {
 
         // Find the first valid announcement
         let mut valid_raw = None;
-        for result in CacheFileAnnouncementIterator::new(file) {
-            if let Ok(ann) = result {
-                if ann.signature_is_valid() {
-                    valid_raw = Some(format!("{}\n\n{}\n\n{}", ann.json_blob, ann.certificate, ann.signature));
-                    break;
-                }
+        for ann in CacheFileAnnouncementIterator::new(file).flatten() {
+            if ann.signature_is_valid() {
+                valid_raw = Some(format!("{}\n\n{}\n\n{}", ann.json_blob, ann.certificate, ann.signature));
+                break;
             }
         }
 
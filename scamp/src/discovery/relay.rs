@@ -0,0 +1,295 @@
+//! Unicast discovery relay, for networks that multicast doesn't reach.
+//!
+//! A forwarder observes multicast and pushes each announcement, still
+//! signed, over TLS to a [`RelayReceiver`] elsewhere, which injects it into
+//! its registry. The receiver checks signatures exactly as the observer
+//! does, so the relay can't vouch for anything; TLS keeps the stream private
+//! and lets the forwarder pin the receiver's certificate.
+//!
+//! Each frame is a 4-byte big-endian length, then the announcement as it
+//! appears in the cache file (JSON, certificate and signature). An empty
+//! frame is a keepalive.
+//!
+//! Forwarders are not authenticated: anyone who can reach the receiver's
+//! port can hold connections open and make it verify signatures. Listen on
+//! an interface only forwarders can reach, or firewall the port to them.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch, RwLock};
+use tokio::task::JoinSet;
+
+use super::observer::{inject_verified, observe_packets, ObserverConfig};
+use super::packet::AnnouncementPacket;
+use super::service_registry::ServiceRegistry;
+use crate::auth::authorized_services::AuthorizedServices;
+use crate::config::Config;
+use crate::transport::tls::{self, TlsAcceptor};
+
+/// Largest frame either side will handle.
+pub const MAX_FRAME_LEN: usize = 4 << 20;
+
+/// Announcements held for the receiver while the forwarder is disconnected.
+pub const FORWARD_QUEUE_LEN: usize = 1024;
+
+/// Wait between connection attempts when `discovery.relay_reconnect` is unset.
+pub const DEFAULT_RECONNECT_SECS: u64 = 5;
+
+/// A receiver drops a forwarder silent for this long. Forwarders send a
+/// keepalive after a third of it without announcements.
+pub const IDLE_TIMEOUT_SECS: u64 = 60;
+
+/// Time a receiver allows for a forwarder's TLS handshake.
+pub const HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+
+/// Forwarders a receiver serves at once; any more are turned away.
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// Where a forwarder sends announcements.
+#[derive(Debug, Clone)]
+pub struct RelayForwarderConfig {
+    /// The receiver, as `host:port`.
+    pub target: String,
+    /// SHA1 fingerprint expected of the receiver's certificate. Any
+    /// certificate is accepted without one.
+    pub fingerprint: Option<String>,
+    pub reconnect_delay: Duration,
+}
+
+impl RelayForwarderConfig {
+    /// `discovery.relay_to` (required), `discovery.relay_fingerprint` and
+    /// `discovery.relay_reconnect` in seconds.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let target: String = config
+            .get("discovery.relay_to")
+            .ok_or_else(|| anyhow!("No relay target found"))?
+            .map_err(|e| anyhow!("Failed to get relay target: {}", e))?;
+        let reconnect = config
+            .get::<u64>("discovery.relay_reconnect")
+            .and_then(|r| r.ok())
+            .unwrap_or(DEFAULT_RECONNECT_SECS);
+        Ok(RelayForwarderConfig {
+            target,
+            fingerprint: config.get::<String>("discovery.relay_fingerprint").and_then(|r| r.ok()),
+            reconnect_delay: Duration::from_secs(reconnect.max(1)),
+        })
+    }
+}
+
+/// Where a receiver listens, and the identity it presents.
+#[derive(Debug, Clone)]
+pub struct RelayReceiverConfig {
+    pub listen: SocketAddr,
+    pub key_pem: Vec<u8>,
+    pub cert_pem: Vec<u8>,
+}
+
+impl RelayReceiverConfig {
+    /// `discovery.relay_listen` (required), with the PEM files named by
+    /// `discovery.relay_key` and `discovery.relay_cert`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let get = |key: &str| {
+            config
+                .get::<String>(key)
+                .ok_or_else(|| anyhow!("No {} found", key))?
+                .map_err(|e| anyhow!("Bad {}: {}", key, e))
+        };
+        let read = |key: &str| -> Result<Vec<u8>> {
+            let path = get(key)?;
+            std::fs::read(&path).with_context(|| format!("Failed to read {}", path))
+        };
+        Ok(RelayReceiverConfig {
+            listen: get("discovery.relay_listen")?.parse().context("Bad discovery.relay_listen")?,
+            key_pem: read("discovery.relay_key")?,
+            cert_pem: read("discovery.relay_cert")?,
+        })
+    }
+}
+
+/// Observe multicast and forward every announcement until shutdown.
+pub async fn run_relay_forwarder(
+    config: RelayForwarderConfig,
+    obs_config: ObserverConfig,
+    shutdown_rx: watch::Receiver<bool>,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel(FORWARD_QUEUE_LEN);
+    let observe = observe_packets(obs_config, shutdown_rx.clone(), move |packet| {
+        if tx.try_send(packet).is_err() {
+            log::debug!("Relay queue full, dropping an announcement");
+        }
+        std::future::ready(())
+    });
+    tokio::try_join!(observe, forward_packets(config, rx, shutdown_rx))?;
+    Ok(())
+}
+
+/// Push each packet from `packets` to the receiver, connecting and
+/// reconnecting as needed, until shutdown or the channel closes. Packets
+/// wait in the channel while disconnected.
+pub async fn forward_packets(
+    config: RelayForwarderConfig,
+    mut packets: mpsc::Receiver<AnnouncementPacket>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> Result<()> {
+    loop {
+        let connected = tokio::select! {
+            result = connect(&config) => result,
+            _ = shutdown_rx.wait_for(|stop| *stop) => return Ok(()),
+        };
+        let mut stream = match connected {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Relay: cannot reach {}: {:#}", config.target, e);
+                tokio::select! {
+                    _ = tokio::time::sleep(config.reconnect_delay) => continue,
+                    _ = shutdown_rx.wait_for(|stop| *stop) => return Ok(()),
+                }
+            }
+        };
+        log::info!("Relay: forwarding to {}", config.target);
+        let keepalive = Duration::from_secs(IDLE_TIMEOUT_SECS / 3);
+        loop {
+            let text = tokio::select! {
+                packet = packets.recv() => match packet {
+                    Some(packet) => packet.text(),
+                    None => return Ok(()),
+                },
+                _ = tokio::time::sleep(keepalive) => String::new(),
+                _ = shutdown_rx.wait_for(|stop| *stop) => return Ok(()),
+            };
+            if let Err(e) = write_frame(&mut stream, &text).await {
+                log::warn!("Relay: lost {}: {}", config.target, e);
+                break;
+            }
+        }
+    }
+}
+
+async fn connect(config: &RelayForwarderConfig) -> Result<impl AsyncRead + AsyncWrite + Unpin> {
+    let stream = TcpStream::connect(&config.target).await?;
+    let ip = stream.peer_addr()?.ip();
+    tls::connect(stream, ip, config.fingerprint.as_deref()).await
+}
+
+/// Accepts forwarders and injects what they send into a registry.
+pub struct RelayReceiver {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl RelayReceiver {
+    pub async fn bind(config: &RelayReceiverConfig) -> Result<Self> {
+        let acceptor = TlsAcceptor::from_pem(&config.key_pem, &config.cert_pem)?;
+        let listener = TcpListener::bind(config.listen).await?;
+        Ok(RelayReceiver { listener, acceptor })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve forwarders until shutdown. Every announcement has its
+    /// signature checked before it reaches `registry`.
+    pub async fn run(
+        self,
+        registry: Arc<RwLock<ServiceRegistry>>,
+        auth: Arc<RwLock<AuthorizedServices>>,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) -> Result<()> {
+        log::info!("Relay receiver listening on {}", self.listener.local_addr()?);
+        // Dropping the set on shutdown closes every connection.
+        let mut connections = JoinSet::new();
+        loop {
+            let accepted = tokio::select! {
+                accepted = self.listener.accept() => accepted,
+                _ = shutdown_rx.wait_for(|stop| *stop) => return Ok(()),
+            };
+            let (stream, peer) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::warn!("Relay: accept failed: {}", e);
+                    continue;
+                }
+            };
+            while connections.try_join_next().is_some() {}
+            if connections.len() >= MAX_CONNECTIONS {
+                log::warn!("Relay: turning away {}, already serving {} forwarders", peer, MAX_CONNECTIONS);
+                continue;
+            }
+            let (acceptor, registry, auth) = (self.acceptor.clone(), registry.clone(), auth.clone());
+            connections.spawn(async move {
+                match receive(acceptor, stream, registry, auth).await {
+                    Ok(count) => log::info!("Relay: {} disconnected after {} announcements", peer, count),
+                    Err(e) => log::warn!("Relay: dropped {}: {:#}", peer, e),
+                }
+            });
+        }
+    }
+}
+
+/// Inject announcements from one forwarder until it hangs up or goes
+/// quiet; returns how many.
+async fn receive(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    registry: Arc<RwLock<ServiceRegistry>>,
+    auth: Arc<RwLock<AuthorizedServices>>,
+) -> Result<u64> {
+    let handshake = Duration::from_secs(HANDSHAKE_TIMEOUT_SECS);
+    let mut stream = tokio::time::timeout(handshake, acceptor.accept(stream))
+        .await
+        .map_err(|_| anyhow!("TLS handshake timed out"))??;
+    let signatures = registry.read().await.signatures();
+    let idle = Duration::from_secs(IDLE_TIMEOUT_SECS);
+    let mut count = 0;
+    loop {
+        let frame = tokio::time::timeout(idle, read_frame(&mut stream))
+            .await
+            .map_err(|_| anyhow!("idle for {}s", IDLE_TIMEOUT_SECS))??;
+        let Some(text) = frame else { break };
+        if text.is_empty() {
+            continue;
+        }
+        match AnnouncementPacket::parse(&text) {
+            Ok(packet) => inject_verified(&registry, &auth, signatures.clone(), packet).await,
+            Err(e) => log::debug!("Relay: bad announcement: {}", e),
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub(crate) async fn write_frame(stream: &mut (impl AsyncWrite + Unpin), text: &str) -> Result<()> {
+    if text.len() > MAX_FRAME_LEN {
+        log::debug!("Relay: announcement too large to forward ({} bytes)", text.len());
+        return Ok(());
+    }
+    stream.write_u32(text.len() as u32).await?;
+    stream.write_all(text.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// The next frame, or None if the peer closed cleanly between frames.
+pub(crate) async fn read_frame(stream: &mut (impl AsyncRead + Unpin)) -> Result<Option<String>> {
+    let len = match stream.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_FRAME_LEN {
+        bail!("frame of {} bytes exceeds {}", len, MAX_FRAME_LEN);
+    }
+    // Grown as bytes arrive, so a length prefix alone allocates nothing.
+    let mut buf = Vec::new();
+    stream.take(len as u64).read_to_end(&mut buf).await?;
+    if buf.len() < len {
+        bail!("connection closed mid-frame");
+    }
+    Ok(Some(String::from_utf8(buf)?))
+}
@@ -0,0 +1,122 @@
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch, RwLock};
+
+use super::packet::AnnouncementPacket;
+use super::relay::*;
+use super::service_registry::ServiceRegistry;
+use super::RegistryEvent;
+use crate::auth::authorized_services::AuthorizedServices;
+use crate::test_helpers::{generate_test_keypair, signed_announcement};
+
+#[tokio::test]
+async fn test_relay_delivers_verified_announcements() {
+    let (key_pem, cert_pem) = generate_test_keypair();
+    let receiver = RelayReceiver::bind(&RelayReceiverConfig {
+        listen: (Ipv4Addr::LOCALHOST, 0).into(),
+        key_pem,
+        cert_pem: cert_pem.clone(),
+    })
+    .await
+    .unwrap();
+    let target = receiver.local_addr().unwrap().to_string();
+    let registry = Arc::new(RwLock::new(ServiceRegistry::empty()));
+    let mut events = registry.read().await.subscribe();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let auth = Arc::new(RwLock::new(AuthorizedServices::from_content("")));
+    let receiving = tokio::spawn(receiver.run(registry.clone(), auth, shutdown_rx.clone()));
+
+    let config = RelayForwarderConfig {
+        target,
+        fingerprint: Some(crate::crypto::cert_pem_fingerprint(std::str::from_utf8(&cert_pem).unwrap()).unwrap()),
+        reconnect_delay: Duration::from_millis(50),
+    };
+    let (tx, rx) = mpsc::channel(8);
+    let forwarding = tokio::spawn(forward_packets(config, rx, shutdown_rx));
+
+    let keypair = generate_test_keypair();
+    let good = signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"]);
+    let mut forged = AnnouncementPacket::parse(&signed_announcement(&keypair, "svc:2", 5, &["Widget.fetch"])).unwrap();
+    forged.json_blob = forged.json_blob.replace("Widget", "Gadget");
+    tx.send(AnnouncementPacket::parse(&good).unwrap()).await.unwrap();
+    tx.send(forged).await.unwrap();
+
+    assert!(matches!(
+        tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap(),
+        RegistryEvent::ServiceAppeared { identity, .. } if identity == "svc:1"
+    ));
+    assert!(matches!(
+        tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap(),
+        RegistryEvent::SignatureRejected { identity, .. } if identity == "svc:2"
+    ));
+    assert_eq!(registry.read().await.find_actions("main:widget.fetch.v1").unwrap().len(), 1);
+
+    shutdown_tx.send(true).unwrap();
+    forwarding.await.unwrap().unwrap();
+    receiving.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_frames_round_trip_and_are_bounded() {
+    let (mut near, mut far) = tokio::io::duplex(64);
+    let writer = tokio::spawn(async move {
+        write_frame(&mut near, "one").await.unwrap();
+        write_frame(&mut near, "").await.unwrap();
+        near.write_u32(MAX_FRAME_LEN as u32 + 1).await.unwrap();
+    });
+    assert_eq!(read_frame(&mut far).await.unwrap().as_deref(), Some("one"));
+    assert_eq!(read_frame(&mut far).await.unwrap().as_deref(), Some(""));
+    assert!(read_frame(&mut far).await.is_err());
+    writer.await.unwrap();
+
+    let (near, mut far) = tokio::io::duplex(64);
+    drop(near);
+    assert_eq!(read_frame(&mut far).await.unwrap(), None, "a clean close ends the stream");
+}
+
+async fn start_receiver() -> (std::net::SocketAddr, watch::Sender<bool>) {
+    let (key_pem, cert_pem) = generate_test_keypair();
+    let receiver = RelayReceiver::bind(&RelayReceiverConfig {
+        listen: (Ipv4Addr::LOCALHOST, 0).into(),
+        key_pem,
+        cert_pem,
+    })
+    .await
+    .unwrap();
+    let addr = receiver.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let registry = Arc::new(RwLock::new(ServiceRegistry::empty()));
+    tokio::spawn(receiver.run(registry, Arc::new(RwLock::new(AuthorizedServices::from_content(""))), shutdown_rx));
+    (addr, shutdown_tx)
+}
+
+/// Read until the receiver closes the connection.
+async fn closed(stream: &mut TcpStream) -> bool {
+    let mut buf = [0; 64];
+    matches!(stream.read(&mut buf).await, Ok(0) | Err(_))
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_silent_peer_is_dropped_after_handshake_timeout() {
+    let (addr, _shutdown_tx) = start_receiver().await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let start = tokio::time::Instant::now();
+    assert!(closed(&mut stream).await);
+    assert!(start.elapsed() >= Duration::from_secs(HANDSHAKE_TIMEOUT_SECS));
+}
+
+#[tokio::test]
+async fn test_connections_beyond_the_limit_are_turned_away() {
+    let (addr, _shutdown_tx) = start_receiver().await;
+    let mut held = Vec::new();
+    for _ in 0..MAX_CONNECTIONS {
+        held.push(TcpStream::connect(addr).await.unwrap());
+    }
+    let mut extra = TcpStream::connect(addr).await.unwrap();
+    let turned_away = tokio::time::timeout(Duration::from_secs(HANDSHAKE_TIMEOUT_SECS / 2), closed(&mut extra)).await;
+    assert_eq!(turned_away, Ok(true));
+}
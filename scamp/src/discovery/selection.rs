@@ -0,0 +1,229 @@
+//! Choosing which instance of an action a request goes to.
+//!
+//! The registry narrows an action's entries down to routable candidates
+//! (authorized, weight > 0, healthy where possible) and hands them to a
+//! [`SelectionStrategy`]. [`WeightedRandom`] is the default and matches how
+//! Perl picks: weights set by operators shift traffic proportionally.
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+
+use super::service_registry::ActionEntry;
+use crate::bus_info::{BusInfo, LocalNetwork};
+use crate::config::Config;
+
+/// Picks one instance from the routable candidates for an action.
+pub trait SelectionStrategy: Send + Sync {
+    /// Choose from `candidates`, which all serve the action indexed as `key`
+    /// and are never empty.
+    fn select<'a>(&self, key: &str, candidates: &[&'a ActionEntry]) -> Option<&'a ActionEntry>;
+
+    /// A request has been sent to `identity`.
+    fn request_started(&self, _identity: &str) {}
+
+    /// A request to `identity` has completed, failed or been abandoned.
+    fn request_finished(&self, _identity: &str) {}
+}
+
+impl<S: SelectionStrategy + ?Sized> SelectionStrategy for Arc<S> {
+    fn select<'a>(&self, key: &str, candidates: &[&'a ActionEntry]) -> Option<&'a ActionEntry> {
+        (**self).select(key, candidates)
+    }
+
+    fn request_started(&self, identity: &str) {
+        (**self).request_started(identity)
+    }
+
+    fn request_finished(&self, identity: &str) {
+        (**self).request_finished(identity)
+    }
+}
+
+impl<S: SelectionStrategy + ?Sized> SelectionStrategy for Box<S> {
+    fn select<'a>(&self, key: &str, candidates: &[&'a ActionEntry]) -> Option<&'a ActionEntry> {
+        (**self).select(key, candidates)
+    }
+
+    fn request_started(&self, identity: &str) {
+        (**self).request_started(identity)
+    }
+
+    fn request_finished(&self, identity: &str) {
+        (**self).request_finished(identity)
+    }
+}
+
+/// Build the strategy named by `requester.selection`: `weighted` (default),
+/// `round_robin`, `least_outstanding` or `locality`.
+pub fn strategy_from_config(config: &Config) -> Box<dyn SelectionStrategy> {
+    let name = config.get::<String>("requester.selection").and_then(|r| r.ok());
+    match name.as_deref() {
+        None | Some("weighted") => Box::new(WeightedRandom),
+        Some("round_robin") => Box::new(RoundRobin::default()),
+        Some("least_outstanding") => Box::new(LeastOutstanding::default()),
+        Some("locality") => Box::new(LocalityFirst::from_config(config)),
+        Some(other) => {
+            log::warn!("Unknown requester.selection '{}', using weighted", other);
+            Box::new(WeightedRandom)
+        }
+    }
+}
+
+/// Random, in proportion to each instance's announced weight.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeightedRandom;
+
+impl SelectionStrategy for WeightedRandom {
+    fn select<'a>(&self, _key: &str, candidates: &[&'a ActionEntry]) -> Option<&'a ActionEntry> {
+        let total: u64 = candidates.iter().map(|e| e.announcement_params.weight as u64).sum();
+        if total == 0 {
+            return candidates.first().copied();
+        }
+        let mut point = rand::thread_rng().gen_range(0..total);
+        for entry in candidates {
+            let weight = entry.announcement_params.weight as u64;
+            if point < weight {
+                return Some(entry);
+            }
+            point -= weight;
+        }
+        candidates.last().copied()
+    }
+}
+
+/// Each action's candidates in turn, ignoring weight.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: Mutex<HashMap<String, usize>>,
+}
+
+impl SelectionStrategy for RoundRobin {
+    fn select<'a>(&self, key: &str, candidates: &[&'a ActionEntry]) -> Option<&'a ActionEntry> {
+        let mut next = self.next.lock().unwrap();
+        let turn = next.entry(key.to_string()).or_default();
+        let pick = candidates.get(*turn % candidates.len().max(1)).copied();
+        *turn = turn.wrapping_add(1);
+        pick
+    }
+}
+
+/// The instance with the fewest requests in flight from this Requester;
+/// ties are broken by weight.
+#[derive(Debug, Default)]
+pub struct LeastOutstanding {
+    outstanding: Mutex<HashMap<String, usize>>,
+}
+
+impl LeastOutstanding {
+    /// Requests currently in flight to `identity`.
+    pub fn outstanding(&self, identity: &str) -> usize {
+        self.outstanding.lock().unwrap().get(identity).copied().unwrap_or(0)
+    }
+}
+
+impl SelectionStrategy for LeastOutstanding {
+    fn select<'a>(&self, key: &str, candidates: &[&'a ActionEntry]) -> Option<&'a ActionEntry> {
+        let outstanding = self.outstanding.lock().unwrap();
+        let load = |e: &ActionEntry| outstanding.get(&e.service_info.identity).copied().unwrap_or(0);
+        let least = candidates.iter().map(|e| load(e)).min()?;
+        let idle: Vec<_> = candidates.iter().copied().filter(|e| load(e) == least).collect();
+        drop(outstanding);
+        WeightedRandom.select(key, &idle)
+    }
+
+    fn request_started(&self, identity: &str) {
+        *self.outstanding.lock().unwrap().entry(identity.to_string()).or_default() += 1;
+    }
+
+    fn request_finished(&self, identity: &str) {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        if let Some(count) = outstanding.get_mut(identity) {
+            *count -= 1;
+            if *count == 0 {
+                outstanding.remove(identity);
+            }
+        }
+    }
+}
+
+/// How close an instance is to this host, nearest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Locality {
+    /// Listening on one of this host's own addresses.
+    SameHost,
+    /// On the same subnet as one of this host's interfaces.
+    SameSubnet,
+    Remote,
+}
+
+impl Locality {
+    /// Every tier, nearest first.
+    pub const TIERS: [Locality; 3] = [Locality::SameHost, Locality::SameSubnet, Locality::Remote];
+}
+
+/// Instances on this host first, then on a shared subnet, then anywhere;
+/// by weight within a tier. Each pick is logged at debug with its tier.
+#[derive(Debug)]
+pub struct LocalityFirst {
+    networks: Vec<LocalNetwork>,
+    chosen: Mutex<HashMap<Locality, u64>>,
+}
+
+impl LocalityFirst {
+    /// Judge locality against `networks`, this host's interfaces.
+    pub fn new(networks: Vec<LocalNetwork>) -> Self {
+        LocalityFirst {
+            networks,
+            chosen: Mutex::default(),
+        }
+    }
+
+    /// This host's interfaces from bus_info. Configured bus addresses that
+    /// aren't on an interface (NAT, say) count as this host too.
+    pub fn from_config(config: &Config) -> Self {
+        let bus = BusInfo::from_config(config);
+        let mut networks = bus.local_networks;
+        for addr in bus.service_addrs {
+            if !networks.iter().any(|n| n.addr == addr) {
+                networks.push(LocalNetwork::new(addr, Ipv4Addr::BROADCAST));
+            }
+        }
+        Self::new(networks)
+    }
+
+    /// Which tier `entry` falls in. Unparseable and IPv6 addresses are remote.
+    pub fn locality(&self, entry: &ActionEntry) -> Locality {
+        let ip = match entry.service_info.socket_addr().map(|addr| addr.ip()) {
+            Ok(IpAddr::V4(ip)) => ip,
+            _ => return Locality::Remote,
+        };
+        if ip.is_loopback() || self.networks.iter().any(|n| n.addr == ip) {
+            Locality::SameHost
+        } else if self.networks.iter().any(|n| !n.addr.is_loopback() && n.contains(ip)) {
+            Locality::SameSubnet
+        } else {
+            Locality::Remote
+        }
+    }
+
+    /// How many picks have landed in `tier` so far.
+    pub fn chosen(&self, tier: Locality) -> u64 {
+        self.chosen.lock().unwrap().get(&tier).copied().unwrap_or(0)
+    }
+}
+
+impl SelectionStrategy for LocalityFirst {
+    fn select<'a>(&self, key: &str, candidates: &[&'a ActionEntry]) -> Option<&'a ActionEntry> {
+        for tier in Locality::TIERS {
+            let pool: Vec<_> = candidates.iter().copied().filter(|e| self.locality(e) == tier).collect();
+            if let Some(pick) = WeightedRandom.select(key, &pool) {
+                log::debug!("{}: picked {} ({:?})", key, pick.service_info.identity, tier);
+                *self.chosen.lock().unwrap().entry(tier).or_default() += 1;
+                return Some(pick);
+            }
+        }
+        None
+    }
+}
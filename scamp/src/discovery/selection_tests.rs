@@ -0,0 +1,90 @@
+use super::selection::*;
+use super::ActionEntry;
+use crate::bus_info::LocalNetwork;
+use crate::config::Config;
+use crate::test_helpers::make_action_entry;
+use std::collections::HashMap;
+
+fn instance(identity: &str, ip: &str, weight: u32) -> ActionEntry {
+    let mut entry = make_action_entry("Widget.fetch", 1);
+    entry.service_info.identity = identity.to_string();
+    entry.service_info.uri = format!("beepish+tls://{ip}:30100");
+    entry.announcement_params.weight = weight;
+    entry
+}
+
+fn counts(strategy: &dyn SelectionStrategy, candidates: &[&ActionEntry], rounds: usize) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for _ in 0..rounds {
+        let pick = strategy.select("main:widget.fetch.v1", candidates).unwrap();
+        *counts.entry(pick.service_info.identity.clone()).or_default() += 1;
+    }
+    counts
+}
+
+#[test]
+fn test_weighted_random_follows_weight() {
+    let (light, heavy) = (instance("light", "10.0.0.1", 1), instance("heavy", "10.0.0.2", 9));
+    let counts = counts(&WeightedRandom, &[&light, &heavy], 10_000);
+    // Expect ~1000 vs ~9000; generous bounds keep this from flaking.
+    assert!((700..1300).contains(&counts["light"]), "{counts:?}");
+}
+
+#[test]
+fn test_round_robin_cycles_per_action() {
+    let (a, b, c) = (
+        instance("a", "10.0.0.1", 1),
+        instance("b", "10.0.0.2", 5),
+        instance("c", "10.0.0.3", 1),
+    );
+    let rr = RoundRobin::default();
+    let picks: Vec<_> = (0..6)
+        .map(|_| rr.select("k1", &[&a, &b, &c]).unwrap().service_info.identity.as_str())
+        .collect();
+    assert_eq!(picks, ["a", "b", "c", "a", "b", "c"]);
+    assert_eq!(rr.select("k2", &[&a, &b, &c]).unwrap().service_info.identity, "a");
+}
+
+#[test]
+fn test_least_outstanding_avoids_busy_instance() {
+    let (a, b) = (instance("a", "10.0.0.1", 1), instance("b", "10.0.0.2", 1));
+    let lo = LeastOutstanding::default();
+    lo.request_started("a");
+    assert_eq!(counts(&lo, &[&a, &b], 50).get("b"), Some(&50));
+
+    lo.request_started("b");
+    lo.request_started("b");
+    lo.request_finished("a");
+    assert_eq!(counts(&lo, &[&a, &b], 50).get("a"), Some(&50));
+    assert_eq!((lo.outstanding("a"), lo.outstanding("b")), (0, 2));
+}
+
+#[test]
+fn test_locality_first_prefers_host_then_subnet() {
+    let remote = instance("remote", "10.9.0.1", 100);
+    let subnet = instance("subnet", "10.0.0.7", 10);
+    let host = instance("host", "10.0.0.1", 1);
+    let strategy = LocalityFirst::new(vec![LocalNetwork::new(
+        "10.0.0.1".parse().unwrap(),
+        "255.255.255.0".parse().unwrap(),
+    )]);
+    assert_eq!(strategy.locality(&instance("lo", "127.0.0.1", 1)), Locality::SameHost);
+
+    assert_eq!(counts(&strategy, &[&remote, &subnet, &host], 20).get("host"), Some(&20));
+    assert_eq!(counts(&strategy, &[&remote, &subnet], 10).get("subnet"), Some(&10));
+    assert_eq!(counts(&strategy, &[&remote], 5).get("remote"), Some(&5));
+    assert_eq!(
+        Locality::TIERS.map(|tier| strategy.chosen(tier)),
+        [20, 10, 5],
+        "tiers chosen are reported"
+    );
+}
+
+#[test]
+fn test_strategy_from_config() {
+    let config = Config::from_content("requester.selection = round_robin").unwrap();
+    let strategy = strategy_from_config(&config);
+    let (a, b) = (instance("a", "10.0.0.1", 1), instance("b", "10.0.0.2", 1));
+    assert_eq!(strategy.select("k", &[&a, &b]).unwrap().service_info.identity, "a");
+    assert_eq!(strategy.select("k", &[&a, &b]).unwrap().service_info.identity, "b");
+}
@@ -1,6 +1,7 @@
 //! Service announcement body types and parsing.
 
 mod parse;
+pub(crate) use parse::parse_flag;
 #[cfg(test)]
 mod tests;
 
@@ -44,6 +45,14 @@ pub struct AnnouncementParams {
     pub timestamp: f64,
 }
 
+impl AnnouncementParams {
+    /// Unix time after which these are stale: 2.1 announcement intervals on
+    /// from when they were sent — Perl ServiceManager.pm.
+    pub fn expires_at(&self) -> f64 {
+        self.timestamp + self.interval as f64 / 1000.0 * 2.1
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct AnnouncementBody {
     pub info: ServiceInfo,
@@ -95,6 +104,8 @@ pub enum ServiceInfoParseError {
     RLEValue(&'static str, usize, serde_json::Error),
     RLEChunkLen(&'static str, usize, usize),
     RLERepeatCount(&'static str, usize),
+    RLETooLong(&'static str),
+    TooManyActions(usize),
     InvalidV3Namespace(usize),
     InvalidV3Action(usize, usize, &'static str),
 }
@@ -114,6 +125,8 @@ impl fmt::Display for ServiceInfoParseError {
                 write!(f, "RLE chunk len: {} at {}: {}", name, i, len)
             }
             Self::RLERepeatCount(name, i) => write!(f, "RLE repeat count: {} at {}", name, i),
+            Self::RLETooLong(name) => write!(f, "RLE field expands too far: {}", name),
+            Self::TooManyActions(count) => write!(f, "Too many actions: {}", count),
             Self::InvalidV3Namespace(i) => write!(f, "Invalid v3 namespace at {}", i),
             Self::InvalidV3Action(ns_i, ac_i, reason) => {
                 write!(f, "Invalid v3 action at ns {} ac {} {}", ns_i, ac_i, reason)
@@ -1,5 +1,7 @@
 //! V3 and V4 announcement action parsing, including RLE decoding.
 
+use std::collections::HashSet;
+
 use itertools::izip;
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -8,6 +10,13 @@ use serde_json::{from_value, Map, Value};
 
 use super::{Action, AnnouncementBody, AnnouncementParams, CrudOp, Flag, PacketSection, ServiceInfo, ServiceInfoParseError};
 
+/// Most actions one announcement may declare. Announcements are parsed
+/// before their signature is checked, so RLE input is untrusted.
+const MAX_ACTIONS: usize = 65536;
+
+/// Most an RLE field may expand to, counting a string value as its length.
+const MAX_RLE_EXPANSION: usize = 4 << 20;
+
 pub(super) fn parse_v3_actions(
     obj: &[Value],
     sector: &str,
@@ -65,6 +74,9 @@ pub(super) fn parse_v4_actions(obj: &Map<String, Value>, actions: &mut Vec<Actio
     let namespaces = unrle::<String>(obj, "acns", true, 0)?;
     let names = unrle::<String>(obj, "acname", true, 0)?;
     let len = names.len();
+    if actions.len() + len > MAX_ACTIONS {
+        return Err(ServiceInfoParseError::TooManyActions(actions.len() + len));
+    }
     let envelopess = unrle::<String>(obj, "acenv", true, 0)?;
     let sectors = unrle::<String>(obj, "acsec", true, 0)?;
     let compats = unrle::<u32>(obj, "accompat", false, len)?;
@@ -106,10 +118,15 @@ where
         }
         Some(Value::Array(rle)) => {
             let mut out: Vec<T> = Vec::new();
+            let mut expanded = 0usize;
             for (i, entry) in rle.iter().enumerate() {
                 match entry {
                     Value::Array(arr) if arr.len() == 2 => {
                         let repeat = arr[0].as_u64().ok_or(ServiceInfoParseError::RLERepeatCount(name, i))?;
+                        expanded = expanded.saturating_add(expansion(repeat, &arr[1]));
+                        if expanded > MAX_RLE_EXPANSION {
+                            return Err(ServiceInfoParseError::RLETooLong(name));
+                        }
                         let value: T = from_value(arr[1].clone()).map_err(|e| ServiceInfoParseError::RLEValue(name, i, e))?;
                         out.extend(std::iter::repeat_n(value, repeat as usize));
                     }
@@ -123,12 +140,21 @@ where
             Ok(out)
         }
         Some(value) => {
+            if expansion(len as u64, value) > MAX_RLE_EXPANSION {
+                return Err(ServiceInfoParseError::RLETooLong(name));
+            }
             let value: T = from_value(value.clone()).map_err(|e| ServiceInfoParseError::RLEValue(name, 0, e))?;
             Ok(vec![value; len])
         }
     }
 }
 
+/// What repeating `value` `repeat` times costs against [`MAX_RLE_EXPANSION`].
+fn expansion(repeat: u64, value: &Value) -> usize {
+    let size = value.as_str().map_or(1, |s| s.len().max(1));
+    usize::try_from(repeat).unwrap_or(usize::MAX).saturating_mul(size)
+}
+
 /// Parse an announcement JSON blob into an AnnouncementBody.
 /// F2: Moved from mod.rs — parsing logic belongs in parse.rs.
 pub(super) fn parse_announcement_body(v: &str) -> Result<AnnouncementBody, ServiceInfoParseError> {
@@ -168,7 +194,12 @@ pub(super) fn parse_announcement_body(v: &str) -> Result<AnnouncementBody, Servi
             _ => {}
         }
     }
-    parse_v3_actions(v3_actions, &v3_sector, &v3_envelopes, &mut actions)?;
+    // Announcers that list an action in both sections mean it once.
+    let mut v3_only = Vec::new();
+    parse_v3_actions(v3_actions, &v3_sector, &v3_envelopes, &mut v3_only)?;
+    let in_v4: HashSet<(String, String)> = actions.iter().map(|a| (a.sector.clone(), a.pathver.clone())).collect();
+    v3_only.retain(|a| !in_v4.contains(&(a.sector.to_lowercase(), a.pathver.clone())));
+    actions.extend(v3_only);
 
     Ok(AnnouncementBody {
         info: ServiceInfo {
@@ -186,13 +217,13 @@ pub(super) fn parse_announcement_body(v: &str) -> Result<AnnouncementBody, Servi
 }
 
 /// Parse a flag string into a Flag enum value.
-pub(super) fn parse_flag(v: &str) -> Flag {
+pub(crate) fn parse_flag(v: &str) -> Flag {
     static TIMEOUT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^t(\d+)$").unwrap());
     match v {
         "noauth" => Flag::NoAuth,
         _ => {
-            if let Some(caps) = TIMEOUT_RE.captures(v) {
-                Flag::Timeout(caps[1].parse().unwrap())
+            if let Some(timeout) = TIMEOUT_RE.captures(v).and_then(|caps| caps[1].parse().ok()) {
+                Flag::Timeout(timeout)
             } else if let Some(crud) = parse_crud_op(v) {
                 Flag::CrudOp(crud)
             } else {
@@ -58,3 +58,33 @@ fn test_unrle_bad_repeat_chunk() {
     let result: Result<Vec<u32>, _> = parse::unrle(&obj, "v", true, 0);
     assert!(result.is_err());
 }
+
+#[test]
+fn test_unrle_rejects_runaway_repeat() {
+    let obj: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_value(json!({"v": [[u64::MAX, "x"]], "w": [[1 << 20, "a long repeated value"]]})).unwrap();
+    assert!(matches!(
+        parse::unrle::<String>(&obj, "v", true, 0),
+        Err(ServiceInfoParseError::RLETooLong("v"))
+    ));
+    assert!(parse::unrle::<String>(&obj, "w", true, 0).is_err());
+}
+
+#[test]
+fn test_too_many_v4_actions() {
+    let v4 = json!({"acns": [[70_000, "Ns"]], "acname": [[70_000, "a"]], "acenv": "json", "acsec": "main"});
+    let blob = json!([3, "svc:x", "main", 1, 5000, "beepish+tls://127.0.0.1:1", [v4], [], 1.0]);
+    assert!(matches!(
+        AnnouncementBody::parse(&blob.to_string()),
+        Err(ServiceInfoParseError::TooManyActions(70_000))
+    ));
+}
+
+#[test]
+fn test_parse_flag_timeout_overflow() {
+    assert_eq!(parse::parse_flag("t30"), Flag::Timeout(30));
+    assert_eq!(
+        parse::parse_flag("t99999999999999999999"),
+        Flag::Other("t99999999999999999999".to_string())
+    );
+}
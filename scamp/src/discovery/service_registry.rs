@@ -1,18 +1,29 @@
-use std::collections::{BTreeMap, HashMap};
-use std::fs::File;
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
+use tokio::sync::broadcast;
 
 use crate::auth::authorized_services::AuthorizedServices;
 use crate::config::Config;
 
 use super::{
-    cache_file::CacheFileAnnouncementIterator,
+    events::RegistryEvent,
     packet::AnnouncementPacket,
-    service_info::{Action, AnnouncementParams, CrudOp, Flag, ServiceInfo},
+    selection::{SelectionStrategy, WeightedRandom},
+    service_info::{Action, AnnouncementParams, Flag, ServiceInfo},
+    signatures::{CheckedAnnouncement, SignatureCache},
 };
 
+mod cache;
+mod changes;
+mod index;
+
+pub use cache::{load_cache, CacheContents};
+use changes::ChangeFeed;
+use index::{make_index_key, ActionIndex};
+
+#[derive(Clone)]
 pub struct ActionEntry {
     pub action: Action,
     pub service_info: ServiceInfo,
@@ -32,18 +43,6 @@ impl ActionEntry {
     }
 }
 
-/// Index key format: `sector:namespace.action.vVERSION` (lowercased)
-/// Matches Perl ServiceInfo.pm:188 and JS serviceMgr.js:221
-fn make_index_key(sector: &str, action_path: &str, version: u32) -> String {
-    format!("{}:{}.v{}", sector, action_path, version).to_lowercase()
-}
-
-/// Make CRUD alias key: `sector:namespace._tag.vVERSION`
-/// Perl ServiceInfo.pm:191-192, JS serviceMgr.js:223-225
-fn make_crud_alias_key(sector: &str, namespace: &str, tag: &str, version: u32) -> String {
-    format!("{}:{}._{}.v{}", sector, namespace, tag, version).to_lowercase()
-}
-
 /// D31/D32: Tracks failure state for a service — JS serviceMgr.js:43-52.
 struct ServiceFailureState {
     /// Unix timestamps (secs) of recent failures (pruned to 24h window)
@@ -53,11 +52,11 @@ struct ServiceFailureState {
 }
 
 pub struct ServiceRegistry {
-    actions_by_key: BTreeMap<String, Vec<ActionEntry>>,
-    /// Replay protection: key = `fingerprint identity` — Perl ServiceManager.pm:29
-    seen_timestamps: HashMap<String, f64>,
+    index: ActionIndex,
     /// D31/D32: Failure state per service identity (interior mutability).
     failures: Mutex<HashMap<String, ServiceFailureState>>,
+    changes: ChangeFeed,
+    signatures: Arc<SignatureCache>,
 }
 
 impl ServiceRegistry {
@@ -69,138 +68,136 @@ impl ServiceRegistry {
 
     pub fn empty() -> Self {
         Self {
-            actions_by_key: BTreeMap::new(),
-            seen_timestamps: HashMap::new(),
+            index: ActionIndex::default(),
             failures: Mutex::new(HashMap::new()),
+            changes: ChangeFeed::new(),
+            signatures: Arc::new(SignatureCache::new()),
         }
     }
 
-    /// Inject a single announcement packet into the registry (D24/D25).
-    /// Used by the multicast observer and cache reload.
-    /// Perl ServiceManager.pm:inject()
+    /// Signature outcomes for this registry's announcements. Check packets
+    /// against it before taking the registry lock, then
+    /// [`ServiceRegistry::inject_checked`] them.
+    pub fn signatures(&self) -> Arc<SignatureCache> {
+        self.signatures.clone()
+    }
+
+    /// Inject a single announcement packet into the registry (D24/D25),
+    /// verifying its signature first. Perl ServiceManager.pm:inject()
     pub fn inject_packet(&mut self, packet: AnnouncementPacket, auth: &AuthorizedServices) {
-        if !packet.signature_is_valid() {
-            log::debug!("Skipping announcement with invalid signature: {}", packet.body.info.identity);
-            return;
-        }
-        let body = packet.body;
-        let fingerprint = body.info.fingerprint.as_deref().unwrap_or("");
+        let checked = self.signatures.check(packet);
+        self.inject_checked(checked, auth);
+    }
 
-        // TTL/expiry
-        let now_f = now_secs() as f64;
-        let interval_secs = body.params.interval as f64 / 1000.0;
-        if now_f > body.params.timestamp + interval_secs * 2.1 {
+    /// Inject an announcement whose signature has already been checked.
+    /// Used by the multicast observer, which verifies outside the lock (M8).
+    pub fn inject_checked(&mut self, checked: CheckedAnnouncement, auth: &AuthorizedServices) {
+        let body = checked.packet.body;
+        if !checked.valid {
+            log::debug!("Skipping announcement with invalid signature: {}", body.info.identity);
+            self.changes.send(RegistryEvent::SignatureRejected {
+                identity: body.info.identity,
+                fingerprint: body.info.fingerprint,
+            });
             return;
         }
+        let identity = body.info.identity.clone();
+        let watched = self.changes.watch(&self.index, vec![identity.clone()]);
+        // A service that stops announcing actions is going away, not expiring.
+        let zeroed = body.params.weight == 0;
+        self.index.index_announcement(body, auth);
+        self.changes.publish(watched, &self.index, zeroed.then_some(identity.as_str()));
+    }
 
-        // Replay protection + dedup
-        let dedup_key = format!("{} {}", fingerprint, body.info.identity);
-        let timestamp = body.params.timestamp;
-        if let Some(&prev_ts) = self.seen_timestamps.get(&dedup_key) {
-            if timestamp <= prev_ts {
-                return;
-            }
-            for entries in self.actions_by_key.values_mut() {
-                entries.retain(|e| {
-                    !(e.service_info.identity == body.info.identity && e.service_info.fingerprint.as_deref() == Some(fingerprint))
-                });
-            }
-        }
-        self.seen_timestamps.insert(dedup_key, timestamp);
-
-        for action in &body.actions {
-            let authorized = auth.is_authorized(fingerprint, &action.sector, &action.path);
-            let entry = ActionEntry {
-                service_info: body.info.clone(),
-                announcement_params: body.params.clone(),
-                action: action.clone(),
-                authorized,
-            };
-            let key = make_index_key(&action.sector, &action.path, action.version);
-            self.actions_by_key.entry(key).or_default().push(entry);
-
-            // CRUD aliases
-            let namespace = action.path.rsplit_once('.').map(|(ns, _)| ns).unwrap_or(&action.path);
-            for flag in &action.flags {
-                if let Flag::CrudOp(op) = flag {
-                    let tag = match op {
-                        CrudOp::Create => "create",
-                        CrudOp::Read => "read",
-                        CrudOp::Update => "update",
-                        CrudOp::Delete => "destroy",
-                    };
-                    let alias_key = make_crud_alias_key(&action.sector, namespace, tag, action.version);
-                    let alias_entry = ActionEntry {
-                        service_info: body.info.clone(),
-                        announcement_params: body.params.clone(),
-                        action: action.clone(),
-                        authorized: true,
-                    };
-                    self.actions_by_key.entry(alias_key).or_default().push(alias_entry);
-                }
-            }
+    /// Index one action entry, with its CRUD aliases. Unlike
+    /// [`ServiceRegistry::inject_packet`] nothing is verified: this is for
+    /// registries assembled in code, such as tests.
+    pub fn insert(&mut self, entry: ActionEntry) {
+        let watched = self.changes.watch(&self.index, vec![entry.service_info.identity.clone()]);
+        self.index.index_entry(entry);
+        self.changes.publish(watched, &self.index, None);
+    }
+
+    /// Receive a [`RegistryEvent`] for each change from now on. A receiver
+    /// that falls more than [`EVENT_CAPACITY`](super::events::EVENT_CAPACITY)
+    /// events behind skips ahead and is told how many it missed.
+    pub fn subscribe(&self) -> broadcast::Receiver<RegistryEvent> {
+        self.changes.subscribe()
+    }
+
+    /// Drop every service whose last announcement has expired, along with
+    /// its replay state. A service that stops announcing without a weight-0
+    /// shutdown otherwise stays routable. Returns the entries removed.
+    pub fn expire_stale(&mut self) -> usize {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        self.expire_as_of(now)
+    }
+
+    pub(crate) fn expire_as_of(&mut self, now: f64) -> usize {
+        let stale = self.index.expiring(now);
+        let watched = self.changes.watch(&self.index, stale.clone());
+        let removed = self.index.expire(&stale, now);
+        if removed > 0 {
+            log::debug!("Expired {} stale action entries", removed);
         }
+        self.changes.publish(watched, &self.index, None);
+        removed
     }
 
     /// Reload registry from the cache file (D25).
     /// Perl ServiceManager.pm:66-98.
     pub fn reload_from_cache(&mut self, config: &Config) -> Result<()> {
-        let cache_path: String = config
-            .get("discovery.cache_path")
-            .ok_or_else(|| anyhow::anyhow!("No cache path found"))?
-            .map_err(|e| anyhow::anyhow!("Failed to get cache path: {}", e))?;
-
-        let auth = match config.get::<String>("bus.authorized_services") {
-            Some(Ok(path)) => AuthorizedServices::load(&path).unwrap_or_else(|e| {
-                log::warn!("Failed to load authorized_services: {}", e);
-                AuthorizedServices::empty()
-            }),
-            _ => AuthorizedServices::empty(),
-        };
-
-        let mut file = File::open(&cache_path).map_err(|e| anyhow::anyhow!("Failed to open cache {}: {}", cache_path, e))?;
-
-        // D7: Cache staleness check — Perl ServiceManager.pm:83-88
-        let cache_max_age: u64 = config.get::<u64>("discovery.cache_max_age").and_then(|r| r.ok()).unwrap_or(120);
-        if let Ok(metadata) = file.metadata() {
-            if let Ok(modified) = metadata.modified() {
-                let age = modified.elapsed().unwrap_or_default();
-                if age.as_secs() > cache_max_age {
-                    log::warn!("Discovery cache stale ({:.0}s old, max {}s)", age.as_secs(), cache_max_age);
-                }
-            }
-        }
-
-        self.actions_by_key.clear();
-        self.seen_timestamps.clear();
+        let contents = load_cache(config, &self.signatures)?;
+        self.merge_cache(contents);
+        Ok(())
+    }
 
-        for packet in CacheFileAnnouncementIterator::new(&mut file).flatten() {
-            self.inject_packet(packet, &auth);
+    /// Merge in a loaded cache file in one step, so lookups never see it
+    /// half built. Each service the cache lists takes its entries from it
+    /// unless announced more recently by multicast or a relay; services
+    /// that have left the cache go. Failure tracking, subscribers and the
+    /// signature cache carry over.
+    pub fn merge_cache(&mut self, contents: CacheContents) {
+        let reloading = self.index.reloading(&contents.index);
+        let watched = self.changes.watch(&self.index, reloading.clone());
+        self.index.adopt(contents.index, &reloading);
+        for event in contents.rejected {
+            self.changes.send(event);
         }
-        Ok(())
+        self.changes.publish(watched, &self.index, None);
+    }
+
+    /// Re-check every announced action against a reloaded authorized_services,
+    /// whichever source it came from.
+    pub fn reauthorize(&mut self, auth: &AuthorizedServices) {
+        self.index.reauthorize(auth);
     }
 
     /// Returns an iterator over all actions presently in the registry
     pub fn actions_iter(&self) -> impl Iterator<Item = &ActionEntry> + '_ {
-        self.actions_by_key.values().flat_map(|entries| entries.iter())
+        self.index.entries()
     }
 
     /// Find all action entries matching the key.
     /// Key format: `sector:action.vVERSION` (lowercased)
     pub fn find_actions(&self, key: &str) -> Option<Vec<&ActionEntry>> {
-        self.actions_by_key.get(&key.to_lowercase()).map(|entries| entries.iter().collect())
+        self.index.get(&key.to_lowercase()).map(|entries| entries.iter().collect())
     }
 
-    /// Find a random action entry matching the key.
+    /// Find a random action entry matching the key, weighted by announced weight.
     /// Excludes weight=0 services and unauthorized actions.
     /// D31/D32: Prefers healthy services over failed ones (JS serviceMgr.js:253-260).
     pub fn get_action(&self, key: &str) -> Option<&ActionEntry> {
-        let entries = self.actions_by_key.get(&key.to_lowercase())?;
+        let key = key.to_lowercase();
+        let entries = self.index.get(&key)?;
         let candidates: Vec<_> = entries
             .iter()
             .filter(|e| e.announcement_params.weight > 0 && e.authorized)
             .collect();
-        self.pick_healthy(&candidates)
+        self.pick_healthy(&key, &candidates, &WeightedRandom)
     }
 
     /// Mark a service as failed — D31/D32, JS serviceMgr.js:43-52.
@@ -225,16 +222,30 @@ impl ServiceRegistry {
     /// Find action by sector, name, version, envelope.
     /// Perl ServiceInfo.pm:254. D31/D32: Prefers healthy services.
     pub fn find_action_with_envelope(&self, sector: &str, action: &str, version: u32, envelope: &str) -> Option<&ActionEntry> {
-        let entries = self.actions_by_key.get(&make_index_key(sector, action, version).to_lowercase())?;
+        self.select_action(sector, action, version, envelope, &WeightedRandom)
+    }
+
+    /// Like [`ServiceRegistry::find_action_with_envelope`], with `strategy`
+    /// choosing among the healthy candidates.
+    pub fn select_action(
+        &self,
+        sector: &str,
+        action: &str,
+        version: u32,
+        envelope: &str,
+        strategy: &dyn SelectionStrategy,
+    ) -> Option<&ActionEntry> {
+        let key = make_index_key(sector, action, version);
+        let entries = self.index.get(&key)?;
         let candidates: Vec<_> = entries
             .iter()
             .filter(|e| e.announcement_params.weight > 0 && e.authorized && e.action.envelopes.iter().any(|env| env == envelope))
             .collect();
-        self.pick_healthy(&candidates)
+        self.pick_healthy(&key, &candidates, strategy)
     }
 
-    /// Select a random entry, preferring healthy over failed services.
-    fn pick_healthy<'a>(&self, candidates: &[&'a ActionEntry]) -> Option<&'a ActionEntry> {
+    /// Let `strategy` pick an entry, preferring healthy over failed services.
+    fn pick_healthy<'a>(&self, key: &str, candidates: &[&'a ActionEntry], strategy: &dyn SelectionStrategy) -> Option<&'a ActionEntry> {
         if candidates.is_empty() {
             return None;
         }
@@ -248,7 +259,7 @@ impl ServiceRegistry {
         if pool.is_empty() {
             None
         } else {
-            Some(pool[rand::random::<usize>() % pool.len()])
+            strategy.select(key, pool)
         }
     }
 
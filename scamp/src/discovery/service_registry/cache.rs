@@ -0,0 +1,84 @@
+//! Loading a [`ServiceRegistry`](super::ServiceRegistry) from the discovery cache file and any
+//! static services file.
+
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::index::ActionIndex;
+use crate::auth::authorized_services::AuthorizedServices;
+use crate::config::Config;
+use crate::discovery::cache_file::CacheFileAnnouncementIterator;
+use crate::discovery::events::RegistryEvent;
+use crate::discovery::packet::AnnouncementPacket;
+use crate::discovery::signatures::SignatureCache;
+use crate::discovery::static_services::{load_static_services, static_services_path};
+
+/// A cache file read, verified and indexed without touching any registry;
+/// see [`load_cache`].
+pub struct CacheContents {
+    pub(super) index: ActionIndex,
+    pub(super) rejected: Vec<RegistryEvent>,
+}
+
+/// Read the cache file, check every signature (spreading the RSA work over
+/// threads) and build the index, adding any static services. With static
+/// services configured the cache file is optional. Needs no registry lock;
+/// finish with [`ServiceRegistry::merge_cache`](super::ServiceRegistry::merge_cache).
+pub fn load_cache(config: &Config, signatures: &SignatureCache) -> Result<CacheContents> {
+    let statics = match static_services_path(config) {
+        Some(path) => Some(load_static_services(Path::new(&path))?),
+        None => None,
+    };
+    let packets = match read_cache_file(config) {
+        Ok(packets) => packets,
+        Err(e) if statics.is_some() => {
+            log::debug!("Using static services only: {}", e);
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    let auth = AuthorizedServices::from_config(config);
+
+    let mut index = ActionIndex::default();
+    let mut rejected = Vec::new();
+    for checked in signatures.check_all(packets) {
+        if checked.valid {
+            index.index_announcement(checked.packet.body, &auth);
+        } else {
+            let info = checked.packet.body.info;
+            log::debug!("Skipping announcement with invalid signature: {}", info.identity);
+            rejected.push(RegistryEvent::SignatureRejected {
+                identity: info.identity,
+                fingerprint: info.fingerprint,
+            });
+        }
+    }
+    for entry in statics.into_iter().flatten() {
+        index.index_entry(entry);
+    }
+    Ok(CacheContents { index, rejected })
+}
+
+/// Read every announcement record from `discovery.cache_path`.
+fn read_cache_file(config: &Config) -> Result<Vec<AnnouncementPacket>> {
+    let cache_path: String = config
+        .get("discovery.cache_path")
+        .ok_or_else(|| anyhow::anyhow!("No cache path found"))?
+        .map_err(|e| anyhow::anyhow!("Failed to get cache path: {}", e))?;
+    let mut file = File::open(&cache_path).map_err(|e| anyhow::anyhow!("Failed to open cache {}: {}", cache_path, e))?;
+
+    // D7: Cache staleness check — Perl ServiceManager.pm:83-88
+    let cache_max_age: u64 = config.get::<u64>("discovery.cache_max_age").and_then(|r| r.ok()).unwrap_or(120);
+    if let Ok(metadata) = file.metadata() {
+        if let Ok(modified) = metadata.modified() {
+            let age = modified.elapsed().unwrap_or_default();
+            if age.as_secs() > cache_max_age {
+                log::warn!("Discovery cache stale ({:.0}s old, max {}s)", age.as_secs(), cache_max_age);
+            }
+        }
+    }
+
+    Ok(CacheFileAnnouncementIterator::new(&mut file).flatten().collect())
+}
@@ -0,0 +1,64 @@
+//! Change notification for [`ServiceRegistry`](super::ServiceRegistry).
+
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+
+use super::index::ActionIndex;
+use crate::discovery::events::{self, GoneReason, RegistryEvent, ServiceSummary, EVENT_CAPACITY};
+
+/// The registry's subscribers, and the diffing that decides what they hear.
+pub(super) struct ChangeFeed {
+    events: broadcast::Sender<RegistryEvent>,
+}
+
+impl ChangeFeed {
+    pub fn new() -> Self {
+        ChangeFeed {
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RegistryEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn send(&self, event: RegistryEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Summarise the services a change may touch, if anyone is listening.
+    pub fn watch(&self, index: &ActionIndex, identities: Vec<String>) -> Option<Watched> {
+        (self.events.receiver_count() > 0).then(|| Watched {
+            before: summarize(index, &identities),
+            identities,
+        })
+    }
+
+    /// Send an event for each watched service that changed. A service
+    /// dropped by its own weight-0 announcement (`zeroed`) is reported as
+    /// such rather than as expired.
+    pub fn publish(&self, watched: Option<Watched>, index: &ActionIndex, zeroed: Option<&str>) {
+        let Some(Watched { identities, before }) = watched else {
+            return;
+        };
+        for mut event in events::diff(&before, &summarize(index, &identities)) {
+            if let RegistryEvent::ServiceGone { identity, reason } = &mut event {
+                if zeroed == Some(identity.as_str()) {
+                    *reason = GoneReason::WeightZero;
+                }
+            }
+            self.send(event);
+        }
+    }
+}
+
+/// Services as they were before a change, to diff against after it.
+pub(super) struct Watched {
+    identities: Vec<String>,
+    before: HashMap<String, ServiceSummary>,
+}
+
+fn summarize(index: &ActionIndex, identities: &[String]) -> HashMap<String, ServiceSummary> {
+    events::summarize(identities.iter().flat_map(|identity| index.service_entries(identity)))
+}
@@ -0,0 +1,231 @@
+//! The entries behind a [`ServiceRegistry`](super::ServiceRegistry): action
+//! entries by routing key, and the replay state of the announcements they
+//! came from.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::{now_secs, ActionEntry};
+use crate::auth::authorized_services::AuthorizedServices;
+use crate::discovery::service_info::{AnnouncementBody, AnnouncementParams, CrudOp, Flag};
+
+/// Index key format: `sector:namespace.action.vVERSION` (lowercased)
+/// Matches Perl ServiceInfo.pm:188 and JS serviceMgr.js:221
+pub(super) fn make_index_key(sector: &str, action_path: &str, version: u32) -> String {
+    format!("{}:{}.v{}", sector, action_path, version).to_lowercase()
+}
+
+/// Make CRUD alias key: `sector:namespace._tag.vVERSION`
+/// Perl ServiceInfo.pm:191-192, JS serviceMgr.js:223-225
+fn make_crud_alias_key(sector: &str, namespace: &str, tag: &str, version: u32) -> String {
+    format!("{}:{}._{}.v{}", sector, namespace, tag, version).to_lowercase()
+}
+
+/// Where one service's entries are indexed, and what it announced.
+#[derive(Default)]
+struct IndexedService {
+    /// Index keys, CRUD aliases included, holding any of its entries.
+    keys: BTreeSet<String>,
+    /// Replay protection: the newest announcement's params per fingerprint,
+    /// pruned once they expire — Perl ServiceManager.pm:29.
+    announced: HashMap<String, AnnouncementParams>,
+    /// Taken from the cache file rather than announced to us directly.
+    from_cache: bool,
+}
+
+impl IndexedService {
+    fn newest(&self) -> f64 {
+        self.announced.values().map(|p| p.timestamp).fold(f64::NEG_INFINITY, f64::max)
+    }
+}
+
+#[derive(Default)]
+pub(super) struct ActionIndex {
+    actions_by_key: BTreeMap<String, Vec<ActionEntry>>,
+    /// By identity, so one service's entries are found without a scan.
+    services: HashMap<String, IndexedService>,
+}
+
+impl ActionIndex {
+    pub fn entries(&self) -> impl Iterator<Item = &ActionEntry> + '_ {
+        self.actions_by_key.values().flat_map(|entries| entries.iter())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Vec<ActionEntry>> {
+        self.actions_by_key.get(key)
+    }
+
+    /// The entries of the service `identity`.
+    pub fn service_entries<'a>(&'a self, identity: &'a str) -> impl Iterator<Item = &'a ActionEntry> + 'a {
+        let keys = self.services.get(identity).into_iter().flat_map(|service| service.keys.iter());
+        keys.filter_map(|key| self.actions_by_key.get(key))
+            .flatten()
+            .filter(move |e| e.service_info.identity == identity)
+    }
+
+    /// Index a verified announcement, replacing the entries from the same
+    /// service's previous one. Expired and replayed announcements are ignored.
+    pub fn index_announcement(&mut self, body: AnnouncementBody, auth: &AuthorizedServices) {
+        let fingerprint = body.info.fingerprint.as_deref().unwrap_or("");
+        let identity = &body.info.identity;
+
+        // TTL/expiry
+        if now_secs() as f64 > body.params.expires_at() {
+            return;
+        }
+
+        // Replay protection + dedup
+        let service = self.services.get(identity);
+        if let Some(prev) = service.and_then(|s| s.announced.get(fingerprint)) {
+            if body.params.timestamp <= prev.timestamp {
+                return;
+            }
+            self.remove_entries(identity, |e| e.service_info.fingerprint.as_deref() == Some(fingerprint));
+        }
+        let service = self.services.entry(identity.clone()).or_default();
+        service.announced.insert(fingerprint.to_string(), body.params.clone());
+        service.from_cache = false;
+
+        for action in &body.actions {
+            let authorized = auth.is_authorized(fingerprint, &action.sector, &action.path);
+            self.index_entry(ActionEntry {
+                service_info: body.info.clone(),
+                announcement_params: body.params.clone(),
+                action: action.clone(),
+                authorized,
+            });
+        }
+    }
+
+    /// Index one action entry, with its CRUD aliases.
+    pub fn index_entry(&mut self, entry: ActionEntry) {
+        let action = &entry.action;
+        let key = make_index_key(&action.sector, &action.path, action.version);
+        let service = self.services.entry(entry.service_info.identity.clone()).or_default();
+
+        // CRUD aliases
+        let namespace = action.path.rsplit_once('.').map(|(ns, _)| ns).unwrap_or(&action.path);
+        for flag in &action.flags {
+            if let Flag::CrudOp(op) = flag {
+                let tag = match op {
+                    CrudOp::Create => "create",
+                    CrudOp::Read => "read",
+                    CrudOp::Update => "update",
+                    CrudOp::Delete => "destroy",
+                };
+                let alias_key = make_crud_alias_key(&action.sector, namespace, tag, action.version);
+                let alias_entry = ActionEntry {
+                    authorized: true,
+                    ..entry.clone()
+                };
+                service.keys.insert(alias_key.clone());
+                self.actions_by_key.entry(alias_key).or_default().push(alias_entry);
+            }
+        }
+        service.keys.insert(key.clone());
+        self.actions_by_key.entry(key).or_default().push(entry);
+    }
+
+    /// Judge every announced entry against `auth` afresh. CRUD aliases and
+    /// static services stay authorized, as when they were indexed.
+    pub fn reauthorize(&mut self, auth: &AuthorizedServices) {
+        for (key, entries) in &mut self.actions_by_key {
+            for entry in entries {
+                let action = &entry.action;
+                let announced = self
+                    .services
+                    .get(&entry.service_info.identity)
+                    .is_some_and(|s| !s.announced.is_empty());
+                if announced && *key == make_index_key(&action.sector, &action.path, action.version) {
+                    let fingerprint = entry.service_info.fingerprint.as_deref().unwrap_or("");
+                    entry.authorized = auth.is_authorized(fingerprint, &action.sector, &action.path);
+                }
+            }
+        }
+    }
+
+    /// Services with an entry or replay record that expired before `now`.
+    pub fn expiring(&self, now: f64) -> Vec<String> {
+        let expired = |params: &AnnouncementParams| params.expires_at() < now;
+        self.services
+            .iter()
+            .filter(|(identity, service)| {
+                service.announced.values().any(expired) || self.service_entries(identity).any(|e| expired(&e.announcement_params))
+            })
+            .map(|(identity, _)| identity.clone())
+            .collect()
+    }
+
+    /// Drop the entries and replay records of `identities` that expired
+    /// before `now`. Returns the entries removed.
+    pub fn expire(&mut self, identities: &[String], now: f64) -> usize {
+        let mut removed = 0;
+        for identity in identities {
+            if let Some(service) = self.services.get_mut(identity) {
+                service.announced.retain(|_, params| params.expires_at() >= now);
+            }
+            removed += self.remove_entries(identity, |e| e.announcement_params.expires_at() < now);
+        }
+        removed
+    }
+
+    /// Services a cache reload of `incoming` changes: those it offers,
+    /// unless announced to us more recently than the cache has them, and
+    /// those the last reload brought in that it no longer has.
+    pub fn reloading(&self, incoming: &ActionIndex) -> Vec<String> {
+        let left = self
+            .services
+            .iter()
+            .filter(|(identity, ours)| ours.from_cache && !incoming.services.contains_key(*identity));
+        let arriving = incoming.services.iter().filter(|(identity, theirs)| {
+            self.services
+                .get(*identity)
+                .is_none_or(|ours| ours.from_cache || ours.newest() <= theirs.newest())
+        });
+        left.chain(arriving).map(|(identity, _)| identity.clone()).collect()
+    }
+
+    /// Replace each of `identities` with its entries from `incoming`, or
+    /// drop it if `incoming` has none.
+    pub fn adopt(&mut self, mut incoming: ActionIndex, identities: &[String]) {
+        for identity in identities {
+            self.remove_entries(identity, |_| true);
+            self.services.remove(identity);
+            let Some(mut service) = incoming.services.remove(identity) else {
+                continue;
+            };
+            for key in &service.keys {
+                let entries = incoming.actions_by_key.get(key).into_iter().flatten();
+                let theirs = entries.filter(|e| &e.service_info.identity == identity).cloned();
+                self.actions_by_key.entry(key.clone()).or_default().extend(theirs);
+            }
+            service.from_cache = true;
+            self.services.insert(identity.clone(), service);
+        }
+    }
+
+    /// Remove the entries of `identity` matching `matches`, forgetting the
+    /// service once nothing of it is left. Returns the entries removed.
+    fn remove_entries(&mut self, identity: &str, matches: impl Fn(&ActionEntry) -> bool) -> usize {
+        let Some(service) = self.services.get_mut(identity) else {
+            return 0;
+        };
+        let mut removed = 0;
+        service.keys.retain(|key| {
+            let Some(entries) = self.actions_by_key.get_mut(key) else {
+                return false;
+            };
+            let len = entries.len();
+            entries.retain(|e| !(e.service_info.identity == identity && matches(e)));
+            removed += len - entries.len();
+            let held = entries.iter().any(|e| e.service_info.identity == identity);
+            if entries.is_empty() {
+                self.actions_by_key.remove(key);
+            }
+            held
+        });
+        if service.keys.is_empty() && service.announced.is_empty() {
+            self.services.remove(identity);
+        }
+        removed
+    }
+}
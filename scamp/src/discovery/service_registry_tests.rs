@@ -0,0 +1,238 @@
+use std::io::Write;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::broadcast::Receiver;
+
+use super::packet::AnnouncementPacket;
+use super::{GoneReason, RegistryEvent, ServiceRegistry};
+use crate::auth::authorized_services::AuthorizedServices;
+use crate::config::Config;
+use crate::test_helpers::{generate_test_keypair, signed_announcement};
+
+fn drain(rx: &mut Receiver<RegistryEvent>) -> Vec<RegistryEvent> {
+    let mut events = Vec::new();
+    loop {
+        match rx.try_recv() {
+            Ok(event) => events.push(event),
+            Err(TryRecvError::Empty) => return events,
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+
+fn packet(text: &str) -> AnnouncementPacket {
+    AnnouncementPacket::parse(text).unwrap()
+}
+
+#[test]
+fn test_announcements_raise_events() {
+    let keypair = generate_test_keypair();
+    let auth = AuthorizedServices::from_content("");
+    let mut registry = ServiceRegistry::empty();
+    let mut rx = registry.subscribe();
+
+    registry.inject_packet(packet(&signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"])), &auth);
+    assert_eq!(
+        drain(&mut rx),
+        [RegistryEvent::ServiceAppeared {
+            identity: "svc:1".into(),
+            uri: "beepish+tls://127.0.0.1:30100".into(),
+            weight: 5,
+            actions: vec!["main:widget.fetch.v1".into()],
+        }]
+    );
+
+    // Re-announcing the same thing is not a change.
+    registry.inject_packet(packet(&signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"])), &auth);
+    assert!(drain(&mut rx).is_empty());
+
+    let next = signed_announcement(&keypair, "svc:1", 2, &["Widget.fetch", "Widget.store"]);
+    registry.inject_packet(packet(&next), &auth);
+    assert_eq!(
+        drain(&mut rx),
+        [
+            RegistryEvent::ActionsChanged {
+                identity: "svc:1".into(),
+                added: vec!["main:widget.store.v1".into()],
+                removed: vec![],
+            },
+            RegistryEvent::WeightChanged {
+                identity: "svc:1".into(),
+                from: 5,
+                to: 2,
+            },
+        ]
+    );
+
+    registry.inject_packet(packet(&signed_announcement(&keypair, "svc:1", 0, &[])), &auth);
+    assert_eq!(
+        drain(&mut rx),
+        [RegistryEvent::ServiceGone {
+            identity: "svc:1".into(),
+            reason: GoneReason::WeightZero,
+        }]
+    );
+}
+
+#[test]
+fn test_reannouncement_touches_only_its_service() {
+    let keypair = generate_test_keypair();
+    let auth = AuthorizedServices::from_content("");
+    let mut registry = ServiceRegistry::empty();
+    registry.inject_packet(packet(&signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"])), &auth);
+    registry.inject_packet(packet(&signed_announcement(&keypair, "svc:2", 5, &["Widget.fetch"])), &auth);
+    let mut rx = registry.subscribe();
+
+    registry.inject_packet(packet(&signed_announcement(&keypair, "svc:1", 5, &["Widget.store"])), &auth);
+    assert_eq!(
+        drain(&mut rx),
+        [RegistryEvent::ActionsChanged {
+            identity: "svc:1".into(),
+            added: vec!["main:widget.store.v1".into()],
+            removed: vec!["main:widget.fetch.v1".into()],
+        }]
+    );
+    let fetch = registry.find_actions("main:widget.fetch.v1").unwrap();
+    assert_eq!(fetch.len(), 1);
+    assert_eq!(fetch[0].service_info.identity, "svc:2");
+}
+
+#[test]
+fn test_bad_signature_is_reported() {
+    let keypair = generate_test_keypair();
+    let mut registry = ServiceRegistry::empty();
+    let mut rx = registry.subscribe();
+
+    let mut tampered = packet(&signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"]));
+    tampered.json_blob = tampered.json_blob.replace("Widget", "Gadget");
+    let fingerprint = tampered.body.info.fingerprint.clone();
+    registry.inject_packet(tampered, &AuthorizedServices::from_content(""));
+
+    assert_eq!(
+        drain(&mut rx),
+        [RegistryEvent::SignatureRejected {
+            identity: "svc:1".into(),
+            fingerprint,
+        }]
+    );
+    assert_eq!(registry.actions_iter().count(), 0);
+}
+
+#[test]
+fn test_reload_reports_only_differences() {
+    let keypair = generate_test_keypair();
+    let one = signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"]);
+    let two = signed_announcement(&keypair, "svc:2", 5, &["Widget.fetch"]);
+    let mut cache = tempfile::NamedTempFile::new().unwrap();
+    write!(cache, "{one}\n%%%\n{two}").unwrap();
+    let config = Config::from_content(&format!(
+        "discovery.cache_path = {}\ndiscovery.cache_max_age = 9999",
+        cache.path().display()
+    ))
+    .unwrap();
+
+    let mut registry = ServiceRegistry::new_from_cache(&config).unwrap();
+    let mut rx = registry.subscribe();
+    registry.reload_from_cache(&config).unwrap();
+    assert!(drain(&mut rx).is_empty(), "unchanged cache is silent");
+    assert_eq!(registry.signatures().len(), 2, "unchanged records reuse their signature checks");
+
+    cache.as_file().set_len(0).unwrap();
+    std::fs::write(cache.path(), &one).unwrap();
+    registry.reload_from_cache(&config).unwrap();
+    assert_eq!(
+        drain(&mut rx),
+        [RegistryEvent::ServiceGone {
+            identity: "svc:2".into(),
+            reason: GoneReason::Expired,
+        }]
+    );
+}
+
+#[test]
+fn test_reload_keeps_newer_announcements() {
+    let keypair = generate_test_keypair();
+    let auth = AuthorizedServices::from_content("");
+    let mut cache = tempfile::NamedTempFile::new().unwrap();
+    write!(cache, "{}", signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"])).unwrap();
+    let config = Config::from_content(&format!(
+        "discovery.cache_path = {}\ndiscovery.cache_max_age = 9999",
+        cache.path().display()
+    ))
+    .unwrap();
+    let mut registry = ServiceRegistry::new_from_cache(&config).unwrap();
+
+    // Announced directly since the cache was written.
+    registry.inject_packet(packet(&signed_announcement(&keypair, "svc:1", 2, &["Widget.fetch"])), &auth);
+    registry.inject_packet(packet(&signed_announcement(&keypair, "svc:2", 5, &["Widget.fetch"])), &auth);
+    let mut rx = registry.subscribe();
+    registry.reload_from_cache(&config).unwrap();
+    assert!(drain(&mut rx).is_empty(), "an older cache changes nothing");
+    let weights: Vec<_> = registry
+        .find_actions("main:widget.fetch.v1")
+        .unwrap()
+        .iter()
+        .map(|e| (e.service_info.identity.as_str(), e.announcement_params.weight))
+        .collect();
+    assert_eq!(weights, [("svc:1", 2), ("svc:2", 5)]);
+
+    std::fs::write(cache.path(), signed_announcement(&keypair, "svc:1", 3, &["Widget.fetch"])).unwrap();
+    registry.reload_from_cache(&config).unwrap();
+    assert_eq!(
+        drain(&mut rx),
+        [RegistryEvent::WeightChanged {
+            identity: "svc:1".into(),
+            from: 2,
+            to: 3,
+        }]
+    );
+    assert_eq!(registry.find_actions("main:widget.fetch.v1").unwrap().len(), 2);
+}
+
+#[test]
+fn test_sweep_expires_silent_services() {
+    let keypair = generate_test_keypair();
+    let auth = AuthorizedServices::from_content("");
+    let mut registry = ServiceRegistry::empty();
+    let announcement = signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"]);
+    registry.inject_packet(packet(&announcement), &auth);
+    let sent = packet(&announcement).body.params.timestamp;
+    let mut rx = registry.subscribe();
+
+    // Announced every 5s, so good for 10.5s.
+    assert_eq!(registry.expire_as_of(sent + 10.0), 0);
+    assert!(registry.find_actions("main:widget.fetch.v1").is_some());
+    assert!(drain(&mut rx).is_empty());
+
+    assert_eq!(registry.expire_as_of(sent + 11.0), 1);
+    assert!(registry.find_actions("main:widget.fetch.v1").is_none());
+    assert_eq!(
+        drain(&mut rx),
+        [RegistryEvent::ServiceGone {
+            identity: "svc:1".into(),
+            reason: GoneReason::Expired,
+        }]
+    );
+
+    // Replay state went with it: the same packet would otherwise be refused
+    // as already seen. (It is still fresh by the real clock.)
+    registry.inject_packet(packet(&announcement), &auth);
+    assert!(registry.find_actions("main:widget.fetch.v1").is_some());
+}
+
+#[test]
+fn test_static_services_need_no_cache_and_never_expire() {
+    let dir = tempfile::tempdir().unwrap();
+    let services = dir.path().join("services.json");
+    std::fs::write(
+        &services,
+        r#"{"services": [{"uri": "beepish+tls://10.0.0.5:30100", "fingerprint": "AB:CD", "actions": ["Widget.fetch"]}]}"#,
+    )
+    .unwrap();
+    let config = Config::from_content(&format!("discovery.static_services = {}", services.display())).unwrap();
+
+    let mut registry = ServiceRegistry::new_from_cache(&config).unwrap();
+    assert!(registry.get_action("main:widget.fetch.v1").is_some());
+    assert_eq!(registry.expire_as_of(f64::MAX), 0);
+    registry.reload_from_cache(&config).unwrap();
+    assert_eq!(registry.find_actions("main:widget.fetch.v1").unwrap().len(), 1);
+}
@@ -0,0 +1,164 @@
+//! Announcement signature checks, done before the registry lock is taken (M8).
+//!
+//! RSA verification is the expensive part of injecting an announcement.
+//! [`SignatureCache`] remembers the outcome per (certificate, body,
+//! signature), so records re-read unchanged from the cache file skip it, and
+//! [`SignatureCache::check_all`] spreads a reload's records over threads.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::packet::AnnouncementPacket;
+
+/// Outcomes remembered per generation. Two generations are kept, so an
+/// announcement seen in the last one or two reloads is always a hit.
+pub const SIGNATURE_CACHE_CAPACITY: usize = 4096;
+
+type Digest = [u8; 32];
+
+/// An announcement whose signature has been checked. The registry only
+/// indexes these, so verification can't be skipped by accident.
+pub struct CheckedAnnouncement {
+    pub(crate) packet: AnnouncementPacket,
+    pub(crate) valid: bool,
+}
+
+impl CheckedAnnouncement {
+    pub fn packet(&self) -> &AnnouncementPacket {
+        &self.packet
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+}
+
+/// Remembers which announcements carry a valid signature.
+#[derive(Default)]
+pub struct SignatureCache {
+    generations: Mutex<Generations>,
+}
+
+#[derive(Default)]
+struct Generations {
+    current: HashMap<Digest, bool>,
+    previous: HashMap<Digest, bool>,
+}
+
+impl SignatureCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verify `packet`'s signature, or recall the result from last time.
+    pub fn check(&self, packet: AnnouncementPacket) -> CheckedAnnouncement {
+        let digest = digest(&packet);
+        let cached = {
+            let mut generations = self.generations.lock().unwrap();
+            let hit = generations.current.get(&digest).or(generations.previous.get(&digest)).copied();
+            if let Some(valid) = hit {
+                generations.remember(digest, valid);
+            }
+            hit
+        };
+        let valid = cached.unwrap_or_else(|| {
+            let valid = packet.signature_is_valid();
+            self.generations.lock().unwrap().remember(digest, valid);
+            valid
+        });
+        CheckedAnnouncement { packet, valid }
+    }
+
+    /// [`SignatureCache::check`] every packet, verifying misses on all
+    /// available cores. Order is preserved.
+    pub fn check_all(&self, packets: Vec<AnnouncementPacket>) -> Vec<CheckedAnnouncement> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = packets.len().div_ceil(threads).max(16);
+        if packets.len() <= chunk {
+            return packets.into_iter().map(|p| self.check(p)).collect();
+        }
+        let mut packets = packets.into_iter();
+        let chunks: Vec<Vec<_>> = std::iter::from_fn(|| Some(packets.by_ref().take(chunk).collect::<Vec<_>>()))
+            .take_while(|c| !c.is_empty())
+            .collect();
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| scope.spawn(move || chunk.into_iter().map(|p| self.check(p)).collect::<Vec<_>>()))
+                .collect();
+            workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+        })
+    }
+
+    /// Outcomes currently remembered.
+    pub fn len(&self) -> usize {
+        let generations = self.generations.lock().unwrap();
+        generations.current.len() + generations.previous.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Generations {
+    fn remember(&mut self, digest: Digest, valid: bool) {
+        if self.current.len() >= SIGNATURE_CACHE_CAPACITY {
+            self.previous = std::mem::take(&mut self.current);
+        }
+        self.current.insert(digest, valid);
+    }
+}
+
+/// SHA-256 over everything the signature check reads.
+fn digest(packet: &AnnouncementPacket) -> Digest {
+    use openssl::sha::Sha256;
+    let mut hasher = Sha256::new();
+    for part in [&packet.certificate, &packet.json_blob, &packet.signature] {
+        hasher.update(&(part.len() as u64).to_be_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{generate_test_keypair, signed_announcement};
+
+    #[test]
+    fn test_results_are_cached_per_record() {
+        let keypair = generate_test_keypair();
+        let text = signed_announcement(&keypair, "svc:1", 5, &["Widget.fetch"]);
+        let cache = SignatureCache::new();
+
+        assert!(cache.check(AnnouncementPacket::parse(&text).unwrap()).is_valid());
+        assert!(cache.check(AnnouncementPacket::parse(&text).unwrap()).is_valid());
+        assert_eq!(cache.len(), 1);
+
+        // Same certificate and signature over a different body is a miss, and fails.
+        let mut tampered = AnnouncementPacket::parse(&text).unwrap();
+        tampered.json_blob = tampered.json_blob.replace("Widget", "Gadget");
+        assert!(!cache.check(tampered).is_valid());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_check_all_keeps_order() {
+        let keypair = generate_test_keypair();
+        let packets: Vec<_> = (0..40)
+            .map(|i| {
+                let mut packet = AnnouncementPacket::parse(&signed_announcement(&keypair, &format!("svc:{i}"), 1, &["A.b"])).unwrap();
+                if i % 3 == 0 {
+                    packet.signature = packet.signature.replace('A', "B");
+                }
+                packet
+            })
+            .collect();
+        let checked = SignatureCache::new().check_all(packets);
+        for (i, checked) in checked.iter().enumerate() {
+            assert_eq!(checked.packet().body.info.identity, format!("svc:{i}"));
+            assert_eq!(checked.is_valid(), i % 3 != 0, "svc:{i}");
+        }
+    }
+}
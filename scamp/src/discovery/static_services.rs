@@ -0,0 +1,219 @@
+//! Services listed in a file instead of announced, for networks without
+//! multicast (containers, CI).
+//!
+//! `discovery.static_services` names a JSON file:
+//!
+//! ```json
+//! { "services": [ {
+//!     "uri": "beepish+tls://10.0.0.5:30100",
+//!     "cert": "certs/widget.crt",
+//!     "actions": ["Widget.fetch", "Widget.store~2", {"name": "Widget.purge", "flags": ["t600"]}]
+//! } ] }
+//! ```
+//!
+//! Each service needs a `uri`, and a `fingerprint` or a `cert` to take it
+//! from (relative to the file). `identity` defaults to the URI, `sector` to
+//! `main`, `envelopes` to `["json"]` and `weight` to 1. Listed services are
+//! indexed alongside the cache file's; they never expire, and are
+//! authorized without `bus.authorized_services`, since whoever wrote the
+//! file is the authority for them.
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use super::service_info::{parse_flag, Action, AnnouncementParams, PacketSection, ServiceInfo};
+use super::service_registry::ActionEntry;
+use crate::config::Config;
+
+#[derive(Debug, Deserialize)]
+struct StaticServicesFile {
+    services: Vec<StaticService>,
+}
+
+/// One service as listed in the file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticService {
+    pub uri: String,
+    #[serde(default)]
+    pub identity: Option<String>,
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+    /// PEM certificate to take the fingerprint from.
+    #[serde(default)]
+    pub cert: Option<String>,
+    #[serde(default = "default_sector")]
+    pub sector: String,
+    #[serde(default = "default_envelopes")]
+    pub envelopes: Vec<String>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub actions: Vec<StaticAction>,
+}
+
+/// `"Namespace.action"` or `"Namespace.action~version"`, or an object for
+/// flags.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum StaticAction {
+    Name(String),
+    Full {
+        name: String,
+        #[serde(default = "default_version")]
+        version: u32,
+        #[serde(default)]
+        flags: Vec<String>,
+    },
+}
+
+fn default_sector() -> String {
+    "main".to_string()
+}
+
+fn default_envelopes() -> Vec<String> {
+    vec!["json".to_string()]
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+/// The file named by `discovery.static_services`, if set.
+pub fn static_services_path(config: &Config) -> Option<String> {
+    config.get::<String>("discovery.static_services").and_then(|r| r.ok())
+}
+
+/// Read a static services file into registry entries.
+pub fn load_static_services(path: &Path) -> Result<Vec<ActionEntry>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read static services {}", path.display()))?;
+    let file: StaticServicesFile = serde_json::from_str(&text).with_context(|| format!("Invalid static services {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut entries = Vec::new();
+    for service in file.services {
+        let uri = service.uri.clone();
+        entries.extend(service.entries(base).with_context(|| format!("Static service {}", uri))?);
+    }
+    Ok(entries)
+}
+
+impl StaticService {
+    /// Registry entries for each action, resolving `cert` against `base`.
+    pub fn entries(self, base: &Path) -> Result<Vec<ActionEntry>> {
+        let fingerprint = match (self.fingerprint, &self.cert) {
+            (Some(fingerprint), None) => fingerprint,
+            (None, Some(cert)) => {
+                let pem = std::fs::read_to_string(base.join(cert)).with_context(|| format!("Failed to read cert {}", cert))?;
+                crate::crypto::cert_pem_fingerprint(&pem)?
+            }
+            (Some(_), Some(_)) => bail!("give a fingerprint or a cert, not both"),
+            (None, None) => bail!("no fingerprint or cert"),
+        };
+        let info = ServiceInfo {
+            identity: self.identity.unwrap_or_else(|| self.uri.clone()),
+            uri: self.uri,
+            fingerprint: Some(fingerprint),
+        };
+        info.socket_addr().map_err(|e| anyhow!(e))?;
+        // Never expires, so the expiry sweep leaves it be.
+        let params = AnnouncementParams {
+            weight: self.weight,
+            interval: 0,
+            timestamp: f64::INFINITY,
+        };
+        self.actions
+            .into_iter()
+            .map(|action| {
+                let (name, version, flags) = match action {
+                    StaticAction::Name(name) => match name.split_once('~') {
+                        Some((name, version)) => {
+                            let version = version.parse().map_err(|_| anyhow!("bad version in {}", name))?;
+                            (name.to_string(), version, Vec::new())
+                        }
+                        None => (name, 1, Vec::new()),
+                    },
+                    StaticAction::Full { name, version, flags } => (name, version, flags),
+                };
+                let path = name.to_lowercase().replace('/', ".");
+                Ok(ActionEntry {
+                    action: Action {
+                        pathver: format!("{}~{}", path, version),
+                        path,
+                        version,
+                        flags: flags.iter().map(|f| parse_flag(f)).collect(),
+                        sector: self.sector.clone(),
+                        envelopes: self.envelopes.clone(),
+                        packet_section: PacketSection::V4,
+                    },
+                    service_info: info.clone(),
+                    announcement_params: params.clone(),
+                    authorized: true,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::service_info::Flag;
+    use crate::test_helpers::generate_test_keypair;
+
+    #[test]
+    fn test_load_with_cert_and_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, cert_pem) = generate_test_keypair();
+        std::fs::write(dir.path().join("widget.crt"), &cert_pem).unwrap();
+        let path = dir.path().join("services.json");
+        std::fs::write(
+            &path,
+            r#"{"services": [
+                {"uri": "beepish+tls://10.0.0.5:30100", "cert": "widget.crt",
+                 "actions": ["Widget.fetch", "Widget.store~2", {"name": "Widget.purge", "flags": ["t600", "noauth"]}]},
+                {"uri": "beepish+tls://10.0.0.6:30100", "fingerprint": "AB:CD", "identity": "other",
+                 "sector": "background", "weight": 3, "actions": ["Job.run"]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let entries = load_static_services(&path).unwrap();
+        let pathvers: Vec<_> = entries
+            .iter()
+            .map(|e| format!("{}:{}", e.action.sector, e.action.pathver))
+            .collect();
+        assert_eq!(
+            pathvers,
+            [
+                "main:widget.fetch~1",
+                "main:widget.store~2",
+                "main:widget.purge~1",
+                "background:job.run~1"
+            ]
+        );
+        let fingerprint = crate::crypto::cert_pem_fingerprint(std::str::from_utf8(&cert_pem).unwrap()).unwrap();
+        assert_eq!(entries[0].service_info.fingerprint.as_deref(), Some(fingerprint.as_str()));
+        assert_eq!(entries[0].service_info.identity, "beepish+tls://10.0.0.5:30100");
+        assert_eq!(entries[2].action.flags, [Flag::Timeout(600), Flag::NoAuth]);
+        assert_eq!(entries[3].service_info.identity, "other");
+        assert_eq!(entries[3].announcement_params.weight, 3);
+        assert!(entries.iter().all(|e| e.authorized && e.action.envelopes == ["json"]));
+    }
+
+    #[test]
+    fn test_service_needs_exactly_one_trust_anchor() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.json");
+        std::fs::write(&path, r#"{"services": [{"uri": "beepish+tls://10.0.0.5:30100", "actions": []}]}"#).unwrap();
+        let Err(err) = load_static_services(&path) else {
+            panic!("loaded without a fingerprint")
+        };
+        let err = format!("{err:#}");
+        assert!(err.contains("no fingerprint or cert"), "{err}");
+    }
+}
@@ -4,10 +4,14 @@ pub mod config;
 pub mod crypto;
 pub mod discovery;
 pub mod requester;
+#[cfg(test)]
+mod requester_tests;
 pub mod rpc_support;
 pub mod service;
 #[cfg(test)]
 pub(crate) mod test_helpers;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod transport;
 
 // Re-export the #[rpc] macro and inventory for use by downstream crates
@@ -2,39 +2,131 @@
 //!
 //! Combines discovery lookup + connection pooling + request/response
 //! into a single async call, matching Perl's simple_request().
+//!
+//! The registry sits behind an `Arc<RwLock<_>>` so it can be kept current by
+//! background discovery (see [`Requester::from_config_live`]) or shared with
+//! other components; each lookup sees the registry as it is at that moment.
 
 use anyhow::{anyhow, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio::sync::RwLock;
 
 use crate::config::Config;
-use crate::discovery::service_registry::ServiceRegistry;
+use crate::discovery::live::{LiveDiscovery, LiveDiscoveryConfig};
+use crate::discovery::selection::{strategy_from_config, SelectionStrategy};
+use crate::discovery::service_registry::{ActionEntry, ServiceRegistry};
 use crate::transport::beepish::proto::EnvelopeFormat;
-use crate::transport::beepish::{BeepishClient, ScampResponse};
+use crate::transport::beepish::{BeepishClient, PoolStats, ReplyStream, ScampResponse};
+use crate::transport::{Transport, TransportRequest};
 
 /// Default per-request (RPC) timeout — Perl ServiceInfo.pm:257
 const DEFAULT_RPC_TIMEOUT_SECS: u64 = 75;
 
 /// High-level SCAMP requester: lookup action → connect → send → receive.
-pub struct Requester {
-    client: BeepishClient,
-    registry: ServiceRegistry,
+///
+/// Requests travel over BEEPish unless another [`Transport`] is supplied
+/// with [`Requester::with_transport`].
+pub struct Requester<T: Transport = BeepishClient> {
+    transport: T,
+    registry: Arc<RwLock<ServiceRegistry>>,
+    /// Picks among an action's instances; `requester.selection` in config.
+    selection: Box<dyn SelectionStrategy>,
     default_sector: String,
+    /// Background discovery tasks; stopped when the Requester is dropped.
+    _live: Option<LiveDiscovery>,
 }
 
 impl Requester {
-    /// Create a Requester from config, loading the discovery cache.
+    /// Create a Requester from config, loading the discovery cache once.
     pub fn from_config(config: &Config) -> Result<Self> {
         let registry = ServiceRegistry::new_from_cache(config)?;
-        let client = BeepishClient::new(config);
+        Ok(Self::from_shared_registry(config, Arc::new(RwLock::new(registry))))
+    }
+
+    /// Create a Requester whose registry is kept current in the background by
+    /// the multicast observer and/or periodic cache reloads.
+    /// A missing cache file is not fatal when the observer is enabled.
+    pub fn from_config_live(config: &Config, live_config: LiveDiscoveryConfig) -> Result<Self> {
+        let registry = match ServiceRegistry::new_from_cache(config) {
+            Ok(registry) => registry,
+            Err(e) if live_config.observe_multicast => {
+                log::warn!("Starting with empty registry, waiting for announcements: {}", e);
+                ServiceRegistry::empty()
+            }
+            Err(e) => return Err(e),
+        };
+        let registry = Arc::new(RwLock::new(registry));
+        let live = LiveDiscovery::spawn(config, registry.clone(), &live_config);
+        Ok(Requester {
+            _live: Some(live),
+            ..Self::from_shared_registry(config, registry)
+        })
+    }
+
+    /// Create a Requester that looks up actions in a registry maintained elsewhere.
+    pub fn from_shared_registry(config: &Config, registry: Arc<RwLock<ServiceRegistry>>) -> Self {
+        Self::with_transport(config, registry, BeepishClient::new(config))
+    }
+
+    /// What the underlying connection pool currently holds.
+    pub async fn pool_stats(&self) -> PoolStats {
+        self.transport.pool_stats().await
+    }
+
+    /// Like [`Requester::request_with_opts`], but the body is read from `body`
+    /// (`opts.body` is ignored) and the reply is returned as a stream.
+    /// There is no dispatch_failure retry: the body can only be read once.
+    pub async fn request_stream(&self, opts: RequestOpts<'_>, body: impl AsyncRead + Unpin + Send + 'static) -> Result<ReplyStream> {
+        let (entry, timeout_secs) = self.resolve(&opts).await?;
+        self.transport
+            .request_stream(
+                &entry.service_info,
+                opts.action,
+                opts.version as i32,
+                opts.envelope.clone(),
+                opts.ticket,
+                0,
+                body,
+                Some(timeout_secs),
+            )
+            .await
+    }
+}
+
+impl<T: Transport> Requester<T> {
+    /// Create a Requester that sends requests over `transport`, e.g. a
+    /// [`MockClient`](crate::transport::mock::MockClient) in tests.
+    pub fn with_transport(config: &Config, registry: Arc<RwLock<ServiceRegistry>>, transport: T) -> Self {
         let default_sector = config
             .get::<String>("bus.default_sector")
             .and_then(|r| r.ok())
             .unwrap_or_else(|| "main".to_string());
 
-        Ok(Requester {
-            client,
+        Requester {
+            transport,
             registry,
+            selection: strategy_from_config(config),
             default_sector,
-        })
+            _live: None,
+        }
+    }
+
+    /// Choose instances with `selection` instead of the configured strategy.
+    pub fn with_selection(mut self, selection: impl SelectionStrategy + 'static) -> Self {
+        self.selection = Box::new(selection);
+        self
+    }
+
+    /// The registry this Requester routes with.
+    pub fn registry(&self) -> Arc<RwLock<ServiceRegistry>> {
+        self.registry.clone()
+    }
+
+    /// The transport requests are sent over.
+    pub fn transport(&self) -> &T {
+        &self.transport
     }
 
     /// Send a request to a discovered service action.
@@ -55,7 +147,7 @@ impl Requester {
     /// Send a request with full control over parameters.
     /// D31: Retries once on dispatch_failure with a different service instance.
     pub async fn request_with_opts(&self, opts: RequestOpts<'_>) -> Result<ScampResponse> {
-        let resp = self.dispatch_once(&opts).await?;
+        let (identity, resp) = self.dispatch_once(&opts).await?;
 
         // D31: If dispatch_failure, mark service failed and retry once
         // JS requester.js:50-58: checks error_data.dispatch_failure
@@ -68,49 +160,80 @@ impl Requester {
             .unwrap_or(false)
             || resp.header.error_code.as_deref() == Some("dispatch_failure");
         if is_dispatch_failure {
-            if let Some(entry) =
-                self.registry
-                    .find_action_with_envelope(opts.sector, opts.action, opts.version, &envelope_str(&opts.envelope))
-            {
-                self.registry.mark_failed(&entry.service_info.identity);
-            }
-            log::debug!("dispatch_failure, retrying with different service");
-            return self.dispatch_once(&opts).await;
+            self.registry.read().await.mark_failed(&identity);
+            log::debug!("dispatch_failure from {}, retrying with different service", identity);
+            return Ok(self.dispatch_once(&opts).await?.1);
         }
 
         Ok(resp)
     }
 
-    async fn dispatch_once(&self, opts: &RequestOpts<'_>) -> Result<ScampResponse> {
+    /// Send to one service instance; returns its identity with the reply.
+    async fn dispatch_once(&self, opts: &RequestOpts<'_>) -> Result<(String, ScampResponse)> {
+        let (entry, timeout_secs) = self.resolve(opts).await?;
+        let _outstanding = Outstanding::new(&*self.selection, &entry.service_info.identity);
+        let request = TransportRequest {
+            action: opts.action,
+            version: opts.version as i32,
+            envelope: opts.envelope.clone(),
+            ticket: opts.ticket,
+            client_id: 0,
+            body: opts.body.clone(),
+            timeout: Duration::from_secs(timeout_secs),
+        };
+        let resp = self.transport.request(&entry, request).await?;
+
+        if let Some(err) = &resp.error {
+            return Err(anyhow!("Transport error: {}", err));
+        }
+
+        Ok((entry.service_info.identity, resp))
+    }
+
+    /// Pick a service for the action and work out the request timeout.
+    async fn resolve(&self, opts: &RequestOpts<'_>) -> Result<(ActionEntry, u64)> {
+        // Clone the entry out so the read lock isn't held across the network call.
         let entry = self
             .registry
-            .find_action_with_envelope(opts.sector, opts.action, opts.version, &envelope_str(&opts.envelope))
+            .read()
+            .await
+            .select_action(
+                opts.sector,
+                opts.action,
+                opts.version,
+                &envelope_str(&opts.envelope),
+                &*self.selection,
+            )
+            .cloned()
             .ok_or_else(|| anyhow!("Action not found: {}:{}.v{}", opts.sector, opts.action, opts.version))?;
 
         let timeout_secs = opts
             .timeout_secs
             .or_else(|| entry.timeout_secs())
             .unwrap_or(DEFAULT_RPC_TIMEOUT_SECS);
+        Ok((entry, timeout_secs))
+    }
+}
 
-        let resp = self
-            .client
-            .request(
-                &entry.service_info,
-                opts.action,
-                opts.version as i32,
-                opts.envelope.clone(),
-                opts.ticket,
-                0,
-                opts.body.clone(),
-                Some(timeout_secs),
-            )
-            .await?;
+/// Counts a request against its instance for the selection strategy until dropped.
+struct Outstanding<'a> {
+    selection: &'a dyn SelectionStrategy,
+    identity: String,
+}
 
-        if let Some(err) = &resp.error {
-            return Err(anyhow!("Transport error: {}", err));
+impl<'a> Outstanding<'a> {
+    fn new(selection: &'a dyn SelectionStrategy, identity: &str) -> Self {
+        selection.request_started(identity);
+        Outstanding {
+            selection,
+            identity: identity.to_string(),
         }
+    }
+}
 
-        Ok(resp)
+impl Drop for Outstanding<'_> {
+    fn drop(&mut self) {
+        self.selection.request_finished(&self.identity);
     }
 }
 
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::discovery::service_registry::ServiceRegistry;
+use crate::requester::*;
+use crate::test_helpers::make_action_entry;
+use crate::transport::mock::{Expectation, MockClient};
+use crate::transport::Transport;
+
+fn mock_requester(entries: &[(&str, u32)]) -> Requester<Arc<MockClient>> {
+    let mut registry = ServiceRegistry::empty();
+    for (path, version) in entries {
+        registry.insert(make_action_entry(path, *version));
+    }
+    let config = Config::from_content("").unwrap();
+    Requester::with_transport(&config, Arc::new(RwLock::new(registry)), Arc::new(MockClient::new()))
+}
+
+#[tokio::test]
+async fn test_request_goes_through_transport() {
+    let requester = mock_requester(&[("Widget.fetch", 2)]);
+    requester.transport().expect(
+        Expectation::action("Widget.fetch")
+            .version(2)
+            .body(br#"{"id":7}"#.to_vec())
+            .reply(br#"{"name":"sprocket"}"#.to_vec()),
+    );
+
+    let resp = requester.request("widget.fetch", 2, br#"{"id":7}"#.to_vec()).await.unwrap();
+    assert_eq!(resp.body, br#"{"name":"sprocket"}"#);
+    assert!(requester.transport().expectations_met());
+}
+
+#[tokio::test]
+async fn test_unknown_action_never_reaches_transport() {
+    let requester = mock_requester(&[("Widget.fetch", 1)]);
+    let err = requester.request("Widget.fetch", 2, vec![]).await.unwrap_err();
+    assert!(err.to_string().contains("Action not found"), "{err}");
+}
+
+#[tokio::test]
+async fn test_selection_strategy_is_per_requester() {
+    use crate::discovery::selection::{LeastOutstanding, RoundRobin};
+
+    let mut registry = ServiceRegistry::empty();
+    for identity in ["svc:one", "svc:two"] {
+        let mut entry = make_action_entry("Widget.fetch", 1);
+        entry.service_info.identity = identity.to_string();
+        registry.insert(entry);
+    }
+    let registry = Arc::new(RwLock::new(registry));
+    let config = Config::from_content("").unwrap();
+    let mock = Arc::new(MockClient::new());
+    mock.expect(Expectation::action("Widget.fetch").repeatedly());
+
+    let round_robin = Requester::with_transport(&config, registry.clone(), mock.clone()).with_selection(RoundRobin::default());
+    for _ in 0..4 {
+        round_robin.request("Widget.fetch", 1, vec![]).await.unwrap();
+    }
+    let services: Vec<_> = mock.calls().into_iter().map(|c| c.service).collect();
+    assert_eq!(services, ["svc:one", "svc:two", "svc:one", "svc:two"]);
+
+    let least = Arc::new(LeastOutstanding::default());
+    let requester = Requester::with_transport(&config, registry, mock.clone()).with_selection(least.clone());
+    requester.request("Widget.fetch", 1, vec![]).await.unwrap();
+    assert_eq!((least.outstanding("svc:one"), least.outstanding("svc:two")), (0, 0));
+}
+
+#[tokio::test]
+async fn test_requester_over_trait_object() {
+    let mock = Arc::new(MockClient::new());
+    mock.expect(Expectation::action("Widget.fetch").reply("ok"));
+    let mut registry = ServiceRegistry::empty();
+    registry.insert(make_action_entry("Widget.fetch", 1));
+    let transport: Box<dyn Transport> = Box::new(mock.clone());
+    let config = Config::from_content("").unwrap();
+    let requester = Requester::with_transport(&config, Arc::new(RwLock::new(registry)), transport);
+
+    assert_eq!(requester.request("Widget.fetch", 1, vec![]).await.unwrap().body, b"ok");
+    assert!(mock.expectations_met());
+}
@@ -0,0 +1,75 @@
+//! Accept loop for a bound service: TLS handshake per connection, then
+//! drain on shutdown. Matches Perl Transport::BEEPish::Server.pm.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use super::handler::RegisteredAction;
+use super::server_connection;
+use crate::auth::authz::AuthzChecker;
+use crate::transport::tls::TlsAcceptor;
+
+/// Accept connections until `shutdown_rx` fires, then wait up to 30s for
+/// the open ones to finish. JS service.js:78-91.
+pub(super) async fn accept_connections(
+    listener: TcpListener,
+    tls_acceptor: TlsAcceptor,
+    actions: Arc<HashMap<String, RegisteredAction>>,
+    authz: Option<Arc<AuthzChecker>>,
+    max_in_flight: usize,
+    mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
+) -> Result<()> {
+    let active_connections = Arc::new(AtomicU64::new(0));
+
+    // Accept connections until shutdown
+    loop {
+        tokio::select! {
+            result = listener.accept() => {
+                let (stream, peer_addr) = result?;
+                stream.set_nodelay(true)?;
+                let tls_acceptor = tls_acceptor.clone();
+                let actions = actions.clone();
+                let authz = authz.clone();
+                let active = active_connections.clone();
+                active.fetch_add(1, Ordering::Relaxed);
+
+                tokio::spawn(async move {
+                    match tls_acceptor.accept(stream).await {
+                        Ok(tls_stream) => {
+                            log::debug!("Accepted connection from {}", peer_addr);
+                            server_connection::handle_connection(tls_stream, actions, authz, max_in_flight).await;
+                        }
+                        Err(e) => {
+                            log::error!("TLS accept failed from {}: {}", peer_addr, e);
+                        }
+                    }
+                    active.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            _ = shutdown_rx.changed() => {
+                if *shutdown_rx.borrow() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Drain active connections (30s timeout)
+    let active = active_connections.load(Ordering::Relaxed);
+    if active > 0 {
+        log::info!("Draining {} active connection(s)...", active);
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(30);
+        while active_connections.load(Ordering::Relaxed) > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let remaining = active_connections.load(Ordering::Relaxed);
+        if remaining > 0 {
+            log::warn!("Shutdown timeout: {} connections still active", remaining);
+        }
+    }
+
+    Ok(())
+}
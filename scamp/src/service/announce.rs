@@ -2,7 +2,11 @@
 //!
 //! Generates v3+v4 announcement packets matching Perl Announcer.pm `_build_packet`.
 //! The v3 wrapper array includes a v4 extension hash with RLE-encoded action vectors
-//! appended to the envelopes array (Perl Announcer.pm:187).
+//! appended to the envelopes array (Perl Announcer.pm:187). Every action is in the
+//! v4 vectors; those using the service's own sector and envelopes are repeated in
+//! v3 for readers that only know v3. Perl instead puts each action in exactly one
+//! section, so readers that parse both must skip v3 actions the v4 vectors already
+//! list, as this crate's does; one that doesn't indexes those actions twice.
 
 use anyhow::Result;
 use serde_json::{json, Value};
@@ -13,13 +17,32 @@ use super::handler::ActionInfo;
 /// Perl Announcer.pm:103 — only these flags appear in announcements.
 const ANNOUNCEABLE: &[&str] = &["create", "destroy", "noauth", "read", "secret", "update"];
 
-/// Build a v3+v4 announcement packet (signed, ready for zlib + multicast).
-///
-/// Format: `json_blob\n\ncert_pem\nbase64_sig(76-char wrapped)\n`
-/// JSON: `[3, ident, sector, weight, interval_ms, uri, [envelopes..., v4_hash], v3_actions, ts]`
-///
-/// Perl Announcer.pm:122-204
-pub fn build_announcement_packet(
+/// Everything needed to (re)build a signed announcement. Owned, so the announcer
+/// task can rebuild the packet every interval without borrowing the service.
+#[derive(Clone)]
+pub(crate) struct AnnouncementSource {
+    pub identity: String,
+    pub sector: String,
+    pub envelopes: Vec<String>,
+    pub uri: String,
+    pub actions: Vec<ActionInfo>,
+    pub key_pem: Vec<u8>,
+    pub cert_pem: Vec<u8>,
+    pub weight: u32,
+    pub interval_secs: u32,
+}
+
+impl AnnouncementSource {
+    pub fn build_packet(&self, active: bool) -> Result<Vec<u8>> {
+        build_announcement_packet(self, active)
+    }
+}
+
+/// Build a raw announcement packet from action info, at weight 1 announced
+/// every 5 seconds. Kept for existing callers; services announce through
+/// their builder's settings instead.
+#[allow(clippy::too_many_arguments)]
+pub fn announce_raw(
     identity: &str,
     sector: &str,
     envelopes: &[String],
@@ -27,21 +50,52 @@ pub fn build_announcement_packet(
     actions: &[ActionInfo],
     key_pem: &[u8],
     cert_pem: &[u8],
-    weight: u32,
-    interval_secs: u32,
     active: bool,
 ) -> Result<Vec<u8>> {
+    let source = AnnouncementSource {
+        identity: identity.to_string(),
+        sector: sector.to_string(),
+        envelopes: envelopes.to_vec(),
+        uri: uri.to_string(),
+        actions: actions.to_vec(),
+        key_pem: key_pem.to_vec(),
+        cert_pem: cert_pem.to_vec(),
+        weight: 1,
+        interval_secs: 5,
+    };
+    build_announcement_packet(&source, active)
+}
+
+/// Build a v3+v4 announcement packet (signed, ready for zlib + multicast).
+///
+/// Format: `json_blob\n\ncert_pem\nbase64_sig(76-char wrapped)\n`
+/// JSON: `[3, ident, sector, weight, interval_ms, uri, [envelopes..., v4_hash], v3_actions, ts]`
+///
+/// Perl Announcer.pm:122-204
+pub(crate) fn build_announcement_packet(source: &AnnouncementSource, active: bool) -> Result<Vec<u8>> {
+    let AnnouncementSource {
+        identity,
+        sector,
+        envelopes,
+        uri,
+        actions,
+        key_pem,
+        cert_pem,
+        weight,
+        interval_secs,
+    } = source;
     let cert_pem_str = std::str::from_utf8(cert_pem)?;
 
     // Build v3 action classes and v4 extension vectors
     // Perl Announcer.pm:127-157
     let mut v3_class_map: BTreeMap<String, Vec<Value>> = BTreeMap::new();
-    let v4_acns: Vec<String> = Vec::new();
-    let v4_acname: Vec<String> = Vec::new();
-    let v4_acver: Vec<u32> = Vec::new();
-    let v4_acflag: Vec<String> = Vec::new();
-    let v4_acsec: Vec<String> = Vec::new();
-    let v4_acenv: Vec<String> = Vec::new();
+    let mut v4_acns: Vec<String> = Vec::new();
+    let mut v4_acname: Vec<String> = Vec::new();
+    let mut v4_acver: Vec<u32> = Vec::new();
+    let mut v4_acflag: Vec<String> = Vec::new();
+    let mut v4_acsec: Vec<String> = Vec::new();
+    let mut v4_acenv: Vec<String> = Vec::new();
+    let mut v4_accompat: Vec<u32> = Vec::new();
 
     if active {
         for action in actions {
@@ -50,9 +104,8 @@ pub fn build_announcement_packet(
                 None => continue,
             };
 
-            // Filter flags to announceable set, sorted alphabetically
-            // Perl Announcer.pm:137-139
-            // Perl Announcer.pm:137-139 — filter to announceable set + timeout flags
+            // Perl Announcer.pm:137-139 — filter to announceable set + timeout flags,
+            // sorted alphabetically
             let mut ann_flags: Vec<String> = action
                 .flags
                 .iter()
@@ -62,12 +115,33 @@ pub fn build_announcement_packet(
             ann_flags.sort();
             let flags = ann_flags.join(",");
 
-            // All actions go into v3 compat zone (no custom sector/envelopes yet)
-            // Perl Announcer.pm:141-148
+            // Registration refuses negative versions.
+            let Ok(version) = u32::try_from(action.version) else {
+                log::warn!("Not announcing {}~{}: negative version", action.name, action.version);
+                continue;
+            };
+
+            // Perl Announcer.pm:141-156
+            let (action_sector, action_envelopes) = match &action.zone {
+                Some(zone) => (&zone.sector, &zone.envelopes),
+                None => (sector, envelopes),
+            };
+            v4_acns.push(namespace.to_string());
+            v4_acname.push(method.to_string());
+            v4_acver.push(version);
+            v4_acflag.push(flags.clone());
+            v4_acsec.push(action_sector.clone());
+            v4_acenv.push(action_envelopes.join(","));
+            v4_accompat.push(1);
+
+            // v3 can only say the service's own sector and envelopes.
+            if action_sector != sector || action_envelopes != envelopes {
+                continue;
+            }
             let cls = v3_class_map.entry(namespace.to_string()).or_default();
             let mut action_arr = vec![Value::String(method.to_string()), Value::String(flags)];
-            if action.version != 1 {
-                action_arr.push(json!(action.version));
+            if version != 1 {
+                action_arr.push(json!(version));
             }
             cls.push(Value::Array(action_arr));
         }
@@ -85,8 +159,8 @@ pub fn build_announcement_packet(
         .unwrap_or_default()
         .as_secs_f64();
 
-    let effective_weight = if active { weight } else { 0 };
-    let interval_ms = interval_secs as u64 * 1000;
+    let effective_weight = if active { *weight } else { 0 };
+    let interval_ms = *interval_secs as u64 * 1000;
 
     // Build v4 extension hash — Perl Announcer.pm:159-175
     let v4_hash = json!({
@@ -98,6 +172,7 @@ pub fn build_announcement_packet(
         "acsec": rle_encode_strings(&v4_acsec),
         "acenv": rle_encode_strings(&v4_acenv),
         "acver": rle_encode_numbers(&v4_acver),
+        "accompat": rle_encode_numbers(&v4_accompat),
     });
 
     // Position [6]: envelopes + v4 extension hash
@@ -114,11 +189,9 @@ pub fn build_announcement_packet(
         interval_ms,
         uri,
         env_plus_v4,
-        if v3_classes.is_empty() {
-            Value::Null
-        } else {
-            Value::Array(v3_classes)
-        },
+        // Always an array, as in Perl: parsers reject null here, which
+        // would lose the weight-0 announcement sent on shutdown.
+        Value::Array(v3_classes),
         timestamp,
     ]);
 
@@ -143,7 +216,7 @@ pub fn build_announcement_packet(
 
 /// RLE-encode a string vector. Perl Announcer.pm `__torle($list)`.
 /// Single occurrence → bare value, repeated → [count, value].
-fn rle_encode_strings(items: &[String]) -> Vec<Value> {
+pub(super) fn rle_encode_strings(items: &[String]) -> Vec<Value> {
     let mut out = Vec::new();
     let mut i = 0;
     while i < items.len() {
@@ -163,7 +236,7 @@ fn rle_encode_strings(items: &[String]) -> Vec<Value> {
 }
 
 /// RLE-encode a numeric vector. Perl Announcer.pm `__torle($list, 1)`.
-fn rle_encode_numbers(items: &[u32]) -> Vec<Value> {
+pub(super) fn rle_encode_numbers(items: &[u32]) -> Vec<Value> {
     let mut out = Vec::new();
     let mut i = 0;
     while i < items.len() {
@@ -184,7 +257,7 @@ fn rle_encode_numbers(items: &[u32]) -> Vec<Value> {
 
 /// Base64 encode with line wrapping at `width` characters.
 /// Matches Perl MIME::Base64::encode_base64 default behavior.
-fn base64_encode_wrapped(data: &[u8], width: usize) -> String {
+pub(super) fn base64_encode_wrapped(data: &[u8], width: usize) -> String {
     let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, data);
     let mut result = String::with_capacity(encoded.len() + encoded.len() / width + 1);
     for (i, ch) in encoded.chars().enumerate() {
@@ -196,94 +269,3 @@ fn base64_encode_wrapped(data: &[u8], width: usize) -> String {
     result.push('\n');
     result
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_rle_encode_strings() {
-        let items = vec!["a".into(), "a".into(), "b".into(), "c".into(), "c".into(), "c".into()];
-        let rle = rle_encode_strings(&items);
-        assert_eq!(rle, vec![json!([2, "a"]), json!("b"), json!([3, "c"])]);
-    }
-
-    #[test]
-    fn test_rle_encode_strings_empty() {
-        let rle = rle_encode_strings(&[]);
-        assert!(rle.is_empty());
-    }
-
-    #[test]
-    fn test_rle_encode_numbers() {
-        let items = vec![1, 1, 1, 2, 3, 3];
-        let rle = rle_encode_numbers(&items);
-        assert_eq!(rle, vec![json!([3, 1]), json!(2), json!([2, 3])]);
-    }
-
-    #[test]
-    fn test_base64_wrapped() {
-        // 256 bytes → 344 base64 chars → should wrap at 76
-        let data = vec![0xABu8; 256];
-        let encoded = base64_encode_wrapped(&data, 76);
-        for line in encoded.trim_end().split('\n') {
-            assert!(line.len() <= 76, "line too long: {} chars", line.len());
-        }
-        assert!(encoded.ends_with('\n'));
-    }
-
-    /// Build a packet and verify it can be parsed by our own announcement parser.
-    #[test]
-    #[ignore] // requires dev keypair
-    fn test_roundtrip_announcement() {
-        let home = std::env::var("HOME").unwrap_or_default();
-        let key_pem = std::fs::read(format!("{}/GT/backplane/devkeys/dev.key", home)).unwrap();
-        let cert_pem = std::fs::read(format!("{}/GT/backplane/devkeys/dev.crt", home)).unwrap();
-
-        let actions = vec![
-            ActionInfo {
-                name: "ScampRsTest.echo".into(),
-                version: 1,
-                flags: vec![],
-            },
-            ActionInfo {
-                name: "ScampRsTest.health_check".into(),
-                version: 1,
-                flags: vec![],
-            },
-        ];
-
-        let packet = build_announcement_packet(
-            "scamp-rs-test:abc123",
-            "main",
-            &["json".to_string()],
-            "beepish+tls://10.0.0.1:30100",
-            &actions,
-            &key_pem,
-            &cert_pem,
-            1,
-            5,
-            true,
-        )
-        .unwrap();
-
-        let packet_str = String::from_utf8(packet).unwrap();
-
-        // Parse with our announcement parser
-        let ann = crate::discovery::packet::AnnouncementPacket::parse(&packet_str).unwrap();
-        assert_eq!(ann.body.info.identity, "scamp-rs-test:abc123");
-        assert_eq!(ann.body.info.uri, "beepish+tls://10.0.0.1:30100");
-        assert_eq!(ann.body.params.weight, 1);
-        assert_eq!(ann.body.params.interval, 5000);
-        assert!(!ann.body.actions.is_empty());
-
-        // Verify signature
-        assert!(ann.signature_is_valid(), "Signature should be valid");
-
-        // Verify v4 extension hash is present
-        // The envelopes array should contain "json" and a v4 hash object
-        let json_val: serde_json::Value = serde_json::from_str(&ann.json_blob).unwrap();
-        let env_array = json_val.as_array().unwrap()[6].as_array().unwrap();
-        assert!(env_array.iter().any(|v| v.is_object()), "Should contain v4 extension hash");
-    }
-}
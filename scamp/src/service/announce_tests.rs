@@ -0,0 +1,284 @@
+use super::announce::*;
+use super::handler::{ActionInfo, ScampReply};
+use serde_json::json;
+
+#[test]
+fn test_rle_encode_strings() {
+    let items = vec!["a".into(), "a".into(), "b".into(), "c".into(), "c".into(), "c".into()];
+    let rle = rle_encode_strings(&items);
+    assert_eq!(rle, vec![json!([2, "a"]), json!("b"), json!([3, "c"])]);
+}
+
+#[test]
+fn test_rle_encode_strings_empty() {
+    let rle = rle_encode_strings(&[]);
+    assert!(rle.is_empty());
+}
+
+#[test]
+fn test_rle_encode_numbers() {
+    let items = vec![1, 1, 1, 2, 3, 3];
+    let rle = rle_encode_numbers(&items);
+    assert_eq!(rle, vec![json!([3, 1]), json!(2), json!([2, 3])]);
+}
+
+#[test]
+fn test_base64_wrapped() {
+    // 256 bytes → 344 base64 chars → should wrap at 76
+    let data = vec![0xABu8; 256];
+    let encoded = base64_encode_wrapped(&data, 76);
+    for line in encoded.trim_end().split('\n') {
+        assert!(line.len() <= 76, "line too long: {} chars", line.len());
+    }
+    assert!(encoded.ends_with('\n'));
+}
+
+/// Build a packet and verify it can be parsed by our own announcement parser.
+#[test]
+#[ignore] // requires dev keypair
+fn test_roundtrip_announcement() {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let key_pem = std::fs::read(format!("{}/GT/backplane/devkeys/dev.key", home)).unwrap();
+    let cert_pem = std::fs::read(format!("{}/GT/backplane/devkeys/dev.crt", home)).unwrap();
+
+    let actions = vec![
+        ActionInfo {
+            name: "ScampRsTest.echo".into(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+        },
+        ActionInfo {
+            name: "ScampRsTest.health_check".into(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+        },
+    ];
+
+    let source = AnnouncementSource {
+        identity: "scamp-rs-test:abc123".into(),
+        sector: "main".into(),
+        envelopes: vec!["json".to_string()],
+        uri: "beepish+tls://10.0.0.1:30100".into(),
+        actions,
+        key_pem,
+        cert_pem,
+        weight: 1,
+        interval_secs: 5,
+    };
+    let packet = source.build_packet(true).unwrap();
+
+    let packet_str = String::from_utf8(packet).unwrap();
+
+    // Parse with our announcement parser
+    let ann = crate::discovery::packet::AnnouncementPacket::parse(&packet_str).unwrap();
+    assert_eq!(ann.body.info.identity, "scamp-rs-test:abc123");
+    assert_eq!(ann.body.info.uri, "beepish+tls://10.0.0.1:30100");
+    assert_eq!(ann.body.params.weight, 1);
+    assert_eq!(ann.body.params.interval, 5000);
+    assert!(!ann.body.actions.is_empty());
+
+    // Verify signature
+    assert!(ann.signature_is_valid(), "Signature should be valid");
+
+    // Verify v4 extension hash is present
+    // The envelopes array should contain "json" and a v4 hash object
+    let json_val: serde_json::Value = serde_json::from_str(&ann.json_blob).unwrap();
+    let env_array = json_val.as_array().unwrap()[6].as_array().unwrap();
+    assert!(env_array.iter().any(|v| v.is_object()), "Should contain v4 extension hash");
+}
+
+/// Random v4 action vectors survive RLE encoding and the discovery parser.
+#[test]
+fn test_rle_vectors_round_trip_through_parser() {
+    use crate::discovery::{AnnouncementBody, PacketSection};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    for seed in 0..64 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let len = rng.gen_range(0..40);
+        // Few distinct values, so runs are common.
+        let mut pick =
+            |choices: &[&str]| -> Vec<String> { (0..len).map(|_| choices[rng.gen_range(0..choices.len())].to_string()).collect() };
+        let acns = pick(&["Ns.One", "Ns.Two", "Three"]);
+        let acname = pick(&["fetch", "update", "list"]);
+        let acsec = pick(&["main", "web"]);
+        let acenv = pick(&["json", "json,jsonstore"]);
+        let acflag = pick(&["", "noauth", "read,t30"]);
+        let acver: Vec<u32> = (0..len).map(|_| rng.gen_range(1..3)).collect();
+
+        let v4 = json!({
+            "vmaj": 4,
+            "vmin": 0,
+            "acns": rle_encode_strings(&acns),
+            "acname": rle_encode_strings(&acname),
+            "acsec": rle_encode_strings(&acsec),
+            "acenv": rle_encode_strings(&acenv),
+            "acflag": rle_encode_strings(&acflag),
+            "acver": rle_encode_numbers(&acver),
+            "accompat": rle_encode_numbers(&vec![1; len]),
+        });
+        let blob = json!([3, "svc:x", "main", 1, 5000, "beepish+tls://127.0.0.1:1", ["json", v4], [], 1.0]);
+        let body = AnnouncementBody::parse(&blob.to_string()).unwrap();
+
+        assert_eq!(body.actions.len(), len, "seed {seed}");
+        for (i, action) in body.actions.iter().enumerate() {
+            assert_eq!(action.path, format!("{}.{}", acns[i], acname[i]).to_lowercase(), "seed {seed}");
+            assert_eq!(action.version, acver[i], "seed {seed}");
+            assert_eq!(action.sector, acsec[i], "seed {seed}");
+            assert_eq!(action.envelopes.join(","), acenv[i], "seed {seed}");
+            assert_eq!(
+                action.flags.len(),
+                acflag[i].split(',').filter(|f| !f.is_empty()).count(),
+                "seed {seed}"
+            );
+            assert_eq!(action.packet_section, PacketSection::V4);
+        }
+    }
+}
+
+/// The shutdown announcement (weight 0, no actions) must still parse, or
+/// observers never see the service leave.
+#[test]
+fn test_inactive_announcement_parses() {
+    let keypair = crate::test_helpers::generate_test_keypair();
+    let text = crate::test_helpers::signed_announcement(&keypair, "svc:x", 0, &["Widget.fetch"]);
+    let packet = crate::discovery::packet::AnnouncementPacket::parse(&text).unwrap();
+    assert!(packet.signature_is_valid());
+    assert_eq!(packet.body.params.weight, 0);
+    assert!(packet.body.actions.is_empty());
+}
+
+/// Every action is announced in the v4 vectors, those in the service's own
+/// sector and envelopes in v3 as well, and all parse back exactly once.
+#[test]
+fn test_v4_actions_round_trip_through_parser() {
+    use super::handler::ActionZone;
+    use crate::discovery::{CrudOp, Flag, PacketSection};
+
+    let zone = |sector: &str, envelopes: &[&str]| {
+        Some(ActionZone {
+            sector: sector.to_string(),
+            envelopes: envelopes.iter().map(|e| e.to_string()).collect(),
+        })
+    };
+    let action = |name: &str, version, flags: &[&str], zone| ActionInfo {
+        name: name.to_string(),
+        version,
+        flags: flags.iter().map(|f| f.to_string()).collect(),
+        zone,
+    };
+    let keypair = crate::test_helpers::generate_test_keypair();
+    let source = AnnouncementSource {
+        identity: "svc:x".into(),
+        sector: "main".into(),
+        envelopes: vec!["json".to_string()],
+        uri: "beepish+tls://127.0.0.1:30100".into(),
+        actions: vec![
+            action("Widget.fetch", 1, &[], None),
+            action("Widget.store", 2, &["t30", "bogus", "noauth"], None),
+            action("Widget.list", 1, &[], zone("main", &["json"])),
+            action("Report.build", 1, &["read"], zone("background", &["json"])),
+            action("Report.build", 2, &["read"], zone("background", &["json"])),
+            action("Report.stream", 3, &[], zone("main", &["json", "jsonstore"])),
+        ],
+        key_pem: keypair.0,
+        cert_pem: keypair.1,
+        weight: 1,
+        interval_secs: 5,
+    };
+    let text = String::from_utf8(source.build_packet(true).unwrap()).unwrap();
+    let packet = crate::discovery::packet::AnnouncementPacket::parse(&text).unwrap();
+    assert!(packet.signature_is_valid());
+
+    let mut parsed: Vec<_> = packet
+        .body
+        .actions
+        .iter()
+        .map(|a| {
+            (
+                a.sector.as_str(),
+                a.pathver.as_str(),
+                a.envelopes.join(","),
+                a.flags.clone(),
+                a.packet_section.clone(),
+            )
+        })
+        .collect();
+    parsed.sort_by(|a, b| a.1.cmp(b.1));
+    let read = || vec![Flag::CrudOp(CrudOp::Read)];
+    assert_eq!(
+        parsed,
+        [
+            ("background", "report.build~1", "json".to_string(), read(), PacketSection::V4),
+            ("background", "report.build~2", "json".to_string(), read(), PacketSection::V4),
+            ("main", "report.stream~3", "json,jsonstore".to_string(), vec![], PacketSection::V4),
+            ("main", "widget.fetch~1", "json".to_string(), vec![], PacketSection::V4),
+            ("main", "widget.list~1", "json".to_string(), vec![], PacketSection::V4),
+            (
+                "main",
+                "widget.store~2",
+                "json".to_string(),
+                vec![Flag::NoAuth, Flag::Timeout(30)],
+                PacketSection::V4
+            ),
+        ]
+    );
+
+    // v3-only readers still see the actions v3 can describe.
+    let json_val: serde_json::Value = serde_json::from_str(&packet.json_blob).unwrap();
+    assert_eq!(
+        json_val[7],
+        json!([["Widget", ["fetch", ""], ["store", "noauth,t30", 2], ["list", ""]]])
+    );
+}
+
+#[test]
+fn test_announce_in_needs_a_registered_action() {
+    let mut service = super::ScampService::new("svc", "main");
+    service.register("Report.build", 1, |req| async move { ScampReply::ok(req.body) });
+    assert!(service.announce_in("Report.build", 2, "background", &["json"]).is_err());
+    assert!(service.announce_in("Report.build", -1, "background", &["json"]).is_err());
+    service.announce_in("Report.build", 1, "background", &["json"]).unwrap();
+    let zone = service.actions_snapshot().remove(0).zone.unwrap();
+    assert_eq!(
+        (zone.sector.as_str(), zone.envelopes.as_slice()),
+        ("background", &["json".to_string()][..])
+    );
+}
+
+#[test]
+fn test_negative_versions_are_not_registered() {
+    let mut service = super::ScampService::new("svc", "main");
+    service.register("Report.build", -1, |req| async move { ScampReply::ok(req.body) });
+    assert!(service.actions_snapshot().is_empty());
+}
+
+#[test]
+fn test_announce_raw_still_builds_a_signed_packet() {
+    let keypair = crate::test_helpers::generate_test_keypair();
+    let actions = [ActionInfo {
+        name: "Widget.fetch".into(),
+        version: 1,
+        flags: vec![],
+        zone: None,
+    }];
+    let envelopes = ["json".to_string()];
+    let raw = super::announce_raw(
+        "svc:x",
+        "main",
+        &envelopes,
+        "beepish+tls://127.0.0.1:30100",
+        &actions,
+        &keypair.0,
+        &keypair.1,
+        true,
+    )
+    .unwrap();
+    let packet = crate::discovery::packet::AnnouncementPacket::parse(&String::from_utf8(raw).unwrap()).unwrap();
+    assert!(packet.signature_is_valid());
+    assert_eq!((packet.body.params.weight, packet.body.params.interval), (1, 5000));
+    assert_eq!(packet.body.actions[0].pathver, "widget.fetch~1");
+}
@@ -0,0 +1,197 @@
+//! Config-driven construction of a [`ScampService`].
+//!
+//! Resolves bind and multicast addresses from bus_info (Perl Config.pm:59-112),
+//! loads the service key/cert named in config, and records what
+//! [`ScampService::serve`] needs to bind, announce and drain on its own.
+
+use anyhow::{anyhow, Context, Result};
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use super::listener::ScampService;
+use super::multicast::{MulticastConfig, DEFAULT_INTERVAL_SECS};
+use super::server_connection::DEFAULT_MAX_IN_FLIGHT;
+use crate::auth::authz::AuthzChecker;
+use crate::bus_info::BusInfo;
+use crate::config::Config;
+
+/// What `serve()` needs beyond the service itself.
+pub(super) struct ServeOptions {
+    pub key_pem: Vec<u8>,
+    pub cert_pem: Vec<u8>,
+    pub bind_ip: Ipv4Addr,
+    /// None when announcing is disabled.
+    pub multicast: Option<MulticastConfig>,
+}
+
+/// Builder returned by [`ScampService::builder`].
+pub struct ScampServiceBuilder {
+    config: Config,
+    name: Option<String>,
+    sector: Option<String>,
+    key_pem: Option<Vec<u8>>,
+    cert_pem: Option<Vec<u8>>,
+    bind_ip: Option<Ipv4Addr>,
+    announce_ip: Option<String>,
+    announce: bool,
+    interval_secs: u32,
+    max_in_flight: usize,
+    authz: Option<Arc<AuthzChecker>>,
+}
+
+impl ScampServiceBuilder {
+    pub(super) fn new(config: &Config) -> Self {
+        ScampServiceBuilder {
+            config: config.clone(),
+            name: None,
+            sector: None,
+            key_pem: None,
+            cert_pem: None,
+            bind_ip: None,
+            announce_ip: None,
+            announce: true,
+            interval_secs: DEFAULT_INTERVAL_SECS,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            authz: None,
+        }
+    }
+
+    /// Service name, used in the identity and for the `<name>.key` / `<name>.cert` config keys.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Sector to announce in. Defaults to `bus.default_sector`, then "main".
+    pub fn sector(mut self, sector: &str) -> Self {
+        self.sector = Some(sector.to_string());
+        self
+    }
+
+    /// Use this key/cert instead of the files named in config.
+    pub fn tls_pem(mut self, key_pem: &[u8], cert_pem: &[u8]) -> Self {
+        self.key_pem = Some(key_pem.to_vec());
+        self.cert_pem = Some(cert_pem.to_vec());
+        self
+    }
+
+    /// Override the bind address resolved from `bus.address`.
+    pub fn bind_ip(mut self, ip: Ipv4Addr) -> Self {
+        self.bind_ip = Some(ip);
+        self
+    }
+
+    /// Address to put in the announced URI (defaults to the bind address).
+    pub fn announce_ip(mut self, ip: &str) -> Self {
+        self.announce_ip = Some(ip.to_string());
+        self
+    }
+
+    /// Disable multicast announcing (e.g. networks without multicast).
+    pub fn announce(mut self, announce: bool) -> Self {
+        self.announce = announce;
+        self
+    }
+
+    /// Seconds between announcements — Perl Announcer.pm:40.
+    pub fn announce_interval_secs(mut self, secs: u32) -> Self {
+        self.interval_secs = secs;
+        self
+    }
+
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    pub fn authz(mut self, authz: Arc<AuthzChecker>) -> Self {
+        self.authz = Some(authz);
+        self
+    }
+
+    /// Resolve addresses and load TLS material. The returned service is not yet
+    /// bound; register actions on it, then call [`ScampService::serve`].
+    pub fn build(self) -> Result<ScampService> {
+        let name = self.name.ok_or_else(|| anyhow!("ScampService::builder() requires a name"))?;
+        let sector = self.sector.unwrap_or_else(|| {
+            self.config
+                .get::<String>("bus.default_sector")
+                .and_then(|r| r.ok())
+                .unwrap_or_else(|| "main".to_string())
+        });
+
+        let (key_pem, cert_pem) = match (self.key_pem, self.cert_pem) {
+            (Some(key), Some(cert)) => (key, cert),
+            _ => (
+                read_pem_from_config(&self.config, &format!("{}.key", name))?,
+                read_pem_from_config(&self.config, &format!("{}.cert", name))?,
+            ),
+        };
+
+        let bus_info = BusInfo::from_config(&self.config);
+        let bind_ip = self.bind_ip.unwrap_or_else(|| bus_info.service_addr());
+        let multicast = self.announce.then(|| {
+            let interface = multicast_interface(self.announce_ip.as_deref(), &bus_info, bind_ip);
+            let mut mcast_config = MulticastConfig::from_config(&self.config, interface);
+            mcast_config.interval_secs = self.interval_secs;
+            mcast_config
+        });
+
+        let mut service = ScampService::new(&name, &sector);
+        if let Some(ip) = &self.announce_ip {
+            service.set_announce_ip(ip);
+        }
+        if let Some(authz) = self.authz {
+            service.set_authz(authz);
+        }
+        service.set_max_in_flight(self.max_in_flight);
+        service.set_serve_options(ServeOptions {
+            key_pem,
+            cert_pem,
+            bind_ip,
+            multicast,
+        });
+        Ok(service)
+    }
+}
+
+/// Interface to announce on: the announce address if it is one, then the
+/// discovery interface (Perl Announcer.pm:62-69), then the bind address.
+fn multicast_interface(announce_ip: Option<&str>, bus_info: &BusInfo, bind_ip: Ipv4Addr) -> Ipv4Addr {
+    announce_ip
+        .and_then(|ip| ip.parse().ok())
+        .or_else(|| bus_info.discovery_addrs.first().copied())
+        .unwrap_or(bind_ip)
+}
+
+fn read_pem_from_config(config: &Config, key: &str) -> Result<Vec<u8>> {
+    let path: String = config
+        .get(key)
+        .ok_or_else(|| anyhow!("No {} in config", key))?
+        .map_err(|e| anyhow!("Bad {} in config: {:?}", key, e))?;
+    std::fs::read(&path).with_context(|| format!("Failed to read {} from {}", key, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multicast_interface_precedence() {
+        let bind_ip = Ipv4Addr::new(10, 0, 0, 1);
+        let mut bus_info = BusInfo::from_config(&Config::from_content("").unwrap());
+        bus_info.discovery_addrs = vec![Ipv4Addr::new(10, 0, 0, 2)];
+
+        assert_eq!(
+            multicast_interface(Some("10.0.0.3"), &bus_info, bind_ip),
+            Ipv4Addr::new(10, 0, 0, 3)
+        );
+        assert_eq!(
+            multicast_interface(Some("host.local"), &bus_info, bind_ip),
+            Ipv4Addr::new(10, 0, 0, 2)
+        );
+        assert_eq!(multicast_interface(None, &bus_info, bind_ip), Ipv4Addr::new(10, 0, 0, 2));
+        bus_info.discovery_addrs.clear();
+        assert_eq!(multicast_interface(None, &bus_info, bind_ip), bind_ip);
+    }
+}
@@ -0,0 +1,137 @@
+//! Per-connection request dispatch: authorization, handler invocation, reply.
+//!
+//! Each completed request runs as its own task (JS connection.js dispatches
+//! on 'message' without waiting for earlier replies). A semaphore caps how many
+//! handlers a single connection may have running at once; the read loop takes
+//! a slot before spawning, so requests beyond the cap wait in the socket.
+//!
+//! Streaming actions are dispatched when their HEADER arrives, with the body
+//! still being received; everything else is dispatched at EOF.
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+
+use super::handler::{ActionHandler, RegisteredAction, ScampReply, ScampRequest, StreamingReply};
+use super::server_connection::ServerWriter;
+use super::server_reply::{send_reply, OutgoingReplies};
+use super::streaming::RequestBody;
+use crate::auth::authz::AuthzChecker;
+use crate::transport::beepish::proto::PacketHeader;
+
+/// Request body as handed over by the read loop.
+pub(super) enum RequestPayload {
+    Buffered(Vec<u8>),
+    Streaming(RequestBody),
+}
+
+/// Shared by the read loop and every dispatch task on one connection.
+pub(crate) struct Dispatcher {
+    actions: Arc<HashMap<String, RegisteredAction>>,
+    authz: Option<Arc<AuthzChecker>>,
+    pub(super) writer: ServerWriter,
+    pub(super) outgoing: OutgoingReplies,
+    next_outgoing_msg_no: AtomicU64,
+    in_flight: Arc<Semaphore>,
+}
+
+impl Dispatcher {
+    pub(super) fn new(
+        actions: Arc<HashMap<String, RegisteredAction>>,
+        authz: Option<Arc<AuthzChecker>>,
+        writer: ServerWriter,
+        max_in_flight: usize,
+    ) -> Self {
+        Dispatcher {
+            actions,
+            authz,
+            writer,
+            outgoing: OutgoingReplies::default(),
+            next_outgoing_msg_no: AtomicU64::new(0),
+            in_flight: Arc::new(Semaphore::new(max_in_flight.max(1))),
+        }
+    }
+
+    /// Whether requests for this action should be dispatched before their body has arrived.
+    pub(super) fn is_streaming(&self, header: &PacketHeader) -> bool {
+        self.actions
+            .get(&action_key(header))
+            .is_some_and(|a| matches!(a.handler, ActionHandler::Streaming(_)))
+    }
+
+    /// Wait for one of the connection's in-flight slots.
+    pub(super) async fn slot(&self) -> Option<OwnedSemaphorePermit> {
+        self.in_flight.clone().acquire_owned().await.ok()
+    }
+
+    /// Run a request on its own task, holding `slot` until it has replied.
+    pub(super) fn spawn(self: &Arc<Self>, header: PacketHeader, body: RequestPayload, slot: OwnedSemaphorePermit, tasks: &mut JoinSet<()>) {
+        let this = self.clone();
+        tasks.spawn(async move {
+            this.dispatch_and_reply(header, body).await;
+            drop(slot);
+        });
+    }
+
+    async fn dispatch_and_reply(&self, header: PacketHeader, body: RequestPayload) {
+        let request_id = header.request_id;
+        let reply = self.dispatch(header, body).await;
+        send_reply(reply, request_id, &self.next_outgoing_msg_no, &self.outgoing, &self.writer).await;
+    }
+
+    async fn dispatch(&self, header: PacketHeader, body: RequestPayload) -> StreamingReply {
+        let action_key = action_key(&header);
+
+        // C1: Check ticket privileges before dispatch — JS ticket.js:71-93
+        // Skip for actions with "noauth" flag, or if no AuthzChecker configured.
+        let noauth = self
+            .actions
+            .get(&action_key)
+            .map(|a| a.flags.iter().any(|f| f == "noauth"))
+            .unwrap_or(false);
+        if let Some(checker) = &self.authz {
+            if !noauth {
+                // M5: Empty ticket on a non-noauth action must be denied
+                if header.ticket.is_empty() {
+                    log::warn!("No ticket for non-noauth action {}", action_key);
+                    return ScampReply::error("Authentication required".to_string(), "unauthorized".to_string()).into();
+                }
+                if let Err(e) = checker.check_access(&header.action, &header.ticket).await {
+                    log::warn!("Authorization denied for {}: {}", action_key, e);
+                    return ScampReply::error(e.to_string(), "unauthorized".to_string()).into();
+                }
+            }
+        }
+
+        let request = ScampRequest {
+            action: header.action,
+            version: header.version,
+            envelope: header.envelope,
+            request_id: header.request_id,
+            client_id: header.client_id,
+            ticket: header.ticket,
+            identifying_token: header.identifying_token,
+            body: vec![],
+        };
+
+        let Some(registered) = self.actions.get(&action_key) else {
+            return ScampReply::error(format!("No such action: {}", action_key), "not_found".to_string()).into();
+        };
+        match (&registered.handler, body) {
+            (ActionHandler::Buffered(handler), RequestPayload::Buffered(body)) => handler(ScampRequest { body, ..request }).await.into(),
+            (ActionHandler::Buffered(handler), RequestPayload::Streaming(body)) => match body.read_to_end().await {
+                Ok(body) => handler(ScampRequest { body, ..request }).await.into(),
+                Err(e) => ScampReply::error(e.to_string(), "transport".to_string()).into(),
+            },
+            (ActionHandler::Streaming(handler), RequestPayload::Buffered(body)) => handler(request, RequestBody::buffered(body)).await,
+            (ActionHandler::Streaming(handler), RequestPayload::Streaming(body)) => handler(request, body).await,
+        }
+    }
+}
+
+/// Registry key for the action a request header names, e.g. `"echo.v1"`.
+fn action_key(header: &PacketHeader) -> String {
+    format!("{}.v{}", header.action.to_lowercase(), header.version)
+}
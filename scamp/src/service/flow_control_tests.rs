@@ -0,0 +1,104 @@
+use super::handler::{ActionHandler, RegisteredAction, ScampReply};
+use super::server_connection::{handle_connection, DEFAULT_MAX_IN_FLIGHT};
+use crate::test_helpers::{parse_all_packets, write_request};
+use crate::transport::beepish::proto::{Packet, PacketType, FLOW_CONTROL_WATERMARK};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf};
+
+const REPLY_SIZE: usize = 200_000;
+
+/// `big.v1` replies with REPLY_SIZE bytes regardless of the request.
+fn big_reply_actions() -> Arc<HashMap<String, RegisteredAction>> {
+    let mut actions = HashMap::new();
+    actions.insert(
+        "big.v1".to_string(),
+        RegisteredAction {
+            name: "big".to_string(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+            handler: ActionHandler::Buffered(Arc::new(|_req| Box::pin(async move { ScampReply::ok(vec![0x5A; REPLY_SIZE]) }))),
+        },
+    );
+    Arc::new(actions)
+}
+
+/// Read until the server has gone quiet; returns every packet received so far.
+async fn read_until_quiet(reader: &mut ReadHalf<DuplexStream>, buf: &mut Vec<u8>) -> Vec<Packet> {
+    let mut tmp = [0u8; 8192];
+    while let Ok(n) = tokio::time::timeout(Duration::from_millis(200), reader.read(&mut tmp)).await {
+        let n = n.unwrap();
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&tmp[..n]);
+    }
+    parse_all_packets(buf)
+}
+
+fn data_bytes(packets: &[Packet]) -> u64 {
+    packets
+        .iter()
+        .filter(|p| p.packet_type == PacketType::Data)
+        .map(|p| p.body.len() as u64)
+        .sum()
+}
+
+#[tokio::test]
+async fn test_reply_pauses_at_watermark_until_acked() {
+    let (client, server) = tokio::io::duplex(1 << 20);
+    let _server = tokio::spawn(handle_connection(server, big_reply_actions(), None, DEFAULT_MAX_IN_FLIGHT));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+    write_request(&mut client_write, 0, "big", 1, 1, b"{}").await;
+
+    let mut buf = Vec::new();
+    let packets = read_until_quiet(&mut client_read, &mut buf).await;
+    assert_eq!(data_bytes(&packets), FLOW_CONTROL_WATERMARK);
+
+    // Each ACK releases another window's worth, until the reply is complete.
+    let mut received = data_bytes(&packets);
+    let mut rounds = 0;
+    loop {
+        Packet {
+            packet_type: PacketType::Ack,
+            msg_no: 0,
+            packet_header: None,
+            body: received.to_string().into(),
+        }
+        .write(&mut client_write)
+        .await
+        .unwrap();
+        client_write.flush().await.unwrap();
+
+        let packets = read_until_quiet(&mut client_read, &mut buf).await;
+        let total = data_bytes(&packets);
+        assert!(total - received <= FLOW_CONTROL_WATERMARK);
+        received = total;
+        rounds += 1;
+        if packets.iter().any(|p| p.packet_type == PacketType::Eof) {
+            break;
+        }
+        assert!(rounds < 10, "reply did not complete");
+    }
+    assert_eq!(received, REPLY_SIZE as u64);
+}
+
+#[tokio::test]
+async fn test_reply_completes_after_peer_stops_sending() {
+    // A peer that half-closes can no longer ACK; the reply must not stall.
+    let (client, server) = tokio::io::duplex(1 << 20);
+    let server_handle = tokio::spawn(handle_connection(server, big_reply_actions(), None, DEFAULT_MAX_IN_FLIGHT));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+    write_request(&mut client_write, 0, "big", 1, 1, b"{}").await;
+    client_write.shutdown().await.unwrap();
+
+    let mut response_data = Vec::new();
+    client_read.read_to_end(&mut response_data).await.unwrap();
+    server_handle.await.unwrap();
+    let packets = parse_all_packets(&response_data);
+
+    assert_eq!(data_bytes(&packets), REPLY_SIZE as u64);
+    assert!(packets.iter().any(|p| p.packet_type == PacketType::Eof));
+}
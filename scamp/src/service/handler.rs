@@ -2,6 +2,7 @@
 
 use std::sync::Arc;
 
+use super::streaming::{self, ReplyChunks, ReplySender, RequestBody};
 use crate::transport::beepish::proto::{EnvelopeFormat, FlexInt};
 
 /// A request received by the service.
@@ -56,16 +57,64 @@ impl ScampReply {
     }
 }
 
+/// What a streaming action returns: a [`ScampReply`], optionally followed by
+/// body chunks written through a [`ReplySender`] after the handler returns.
+pub struct StreamingReply {
+    pub(crate) reply: ScampReply,
+    pub(crate) chunks: Option<ReplyChunks>,
+}
+
+impl StreamingReply {
+    /// A successful reply whose body is written through the returned sender.
+    /// Dropping the sender ends the reply.
+    pub fn channel() -> (Self, ReplySender) {
+        let (sender, chunks) = streaming::reply_channel();
+        let reply = StreamingReply {
+            reply: ScampReply::ok(vec![]),
+            chunks: Some(chunks),
+        };
+        (reply, sender)
+    }
+}
+
+impl From<ScampReply> for StreamingReply {
+    fn from(reply: ScampReply) -> Self {
+        StreamingReply { reply, chunks: None }
+    }
+}
+
 /// Handler function type for registered actions.
 pub type ActionHandlerFn =
     Arc<dyn Fn(ScampRequest) -> std::pin::Pin<Box<dyn std::future::Future<Output = ScampReply> + Send>> + Send + Sync>;
 
+/// Handler function type for streaming actions: `ScampRequest.body` is empty
+/// and the request body arrives through the [`RequestBody`].
+pub type StreamingHandlerFn =
+    Arc<dyn Fn(ScampRequest, RequestBody) -> std::pin::Pin<Box<dyn std::future::Future<Output = StreamingReply> + Send>> + Send + Sync>;
+
+pub(crate) enum ActionHandler {
+    /// Runs once the whole request body has arrived.
+    Buffered(ActionHandlerFn),
+    /// Runs as soon as the request HEADER arrives.
+    Streaming(StreamingHandlerFn),
+}
+
 /// A registered action with its handler.
 pub(crate) struct RegisteredAction {
     pub name: String,
     pub version: i32,
     pub flags: Vec<String>,
-    pub handler: ActionHandlerFn,
+    /// Where the action is announced, if not the service's own sector and envelopes.
+    pub zone: Option<ActionZone>,
+    pub handler: ActionHandler,
+}
+
+/// A sector and envelopes an action is announced under in place of the
+/// service's own. Perl Announcer.pm:140-156.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionZone {
+    pub sector: String,
+    pub envelopes: Vec<String>,
 }
 
 /// Lightweight action info for announcement building (no handler).
@@ -74,6 +123,7 @@ pub struct ActionInfo {
     pub name: String,
     pub version: i32,
     pub flags: Vec<String>,
+    pub zone: Option<ActionZone>,
 }
 
 impl From<&RegisteredAction> for ActionInfo {
@@ -82,6 +132,7 @@ impl From<&RegisteredAction> for ActionInfo {
             name: ra.name.clone(),
             version: ra.version,
             flags: ra.flags.clone(),
+            zone: ra.zone.clone(),
         }
     }
 }
@@ -3,19 +3,24 @@
 //! Matches Perl Transport::BEEPish::Server.pm.
 
 use anyhow::{anyhow, Result};
-use log;
-use std::collections::HashMap;
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tokio_native_tls::native_tls;
-use tokio_native_tls::TlsAcceptor;
 
-use super::announce;
-use super::handler::{ActionHandlerFn, ActionInfo, RegisteredAction};
+use super::accept::accept_connections;
+use super::announce::AnnouncementSource;
+use super::builder::{ScampServiceBuilder, ServeOptions};
+use super::handler::{ActionInfo, ActionZone, ScampReply, ScampRequest, StreamingReply};
+use super::multicast::{self, DEFAULT_INTERVAL_SECS};
+use super::register::{self, ActionTable};
 use super::server_connection;
+use super::streaming::RequestBody;
 use crate::auth::authz::AuthzChecker;
+use crate::config::Config;
+use crate::transport::tls::TlsAcceptor;
+
+const NOT_BUILT: &str = "bind()/serve() require a service built with ScampService::builder()";
 
 /// SCAMP service that listens for incoming connections and dispatches requests.
 pub struct ScampService {
@@ -24,7 +29,7 @@ pub struct ScampService {
     identity: String,
     sector: String,
     envelopes: Vec<String>,
-    actions: HashMap<String, RegisteredAction>,
+    actions: ActionTable,
     listener: Option<TcpListener>,
     tls_acceptor: Option<TlsAcceptor>,
     address: Option<SocketAddr>,
@@ -32,6 +37,8 @@ pub struct ScampService {
     cert_pem: Option<Vec<u8>>,
     announce_ip: Option<String>,
     authz: Option<Arc<AuthzChecker>>,
+    max_in_flight: usize,
+    serve_opts: Option<ServeOptions>,
 }
 
 impl ScampService {
@@ -44,7 +51,7 @@ impl ScampService {
             identity: format!("{}:{}", name, identity_suffix),
             sector: sector.to_string(),
             envelopes: vec!["json".to_string()],
-            actions: HashMap::new(),
+            actions: ActionTable::default(),
             listener: None,
             tls_acceptor: None,
             address: None,
@@ -52,9 +59,21 @@ impl ScampService {
             cert_pem: None,
             announce_ip: None,
             authz: None,
+            max_in_flight: server_connection::DEFAULT_MAX_IN_FLIGHT,
+            serve_opts: None,
         }
     }
 
+    /// Start building a service from scamp config; finish with
+    /// [`ScampServiceBuilder::build`] and run it with [`ScampService::serve`].
+    pub fn builder(config: &Config) -> ScampServiceBuilder {
+        ScampServiceBuilder::new(config)
+    }
+
+    pub(super) fn set_serve_options(&mut self, opts: ServeOptions) {
+        self.serve_opts = Some(opts);
+    }
+
     pub fn identity(&self) -> &str {
         &self.identity
     }
@@ -80,15 +99,21 @@ impl ScampService {
         self.authz = Some(authz);
     }
 
+    /// Cap on requests executing concurrently on a single connection.
+    /// Requests beyond the cap wait for a slot; they are not rejected.
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        self.max_in_flight = max_in_flight;
+    }
+
     /// Snapshot of registered action info for use by the announcer task.
     pub fn actions_snapshot(&self) -> Vec<ActionInfo> {
-        self.actions.values().map(ActionInfo::from).collect()
+        self.actions.snapshot()
     }
 
     pub fn register<F, Fut>(&mut self, action: &str, version: i32, handler: F)
     where
         F: Fn(ScampRequest) -> Fut + Send + Sync + 'static,
-        Fut: std::future::Future<Output = ScampReply> + Send + 'static,
+        Fut: Future<Output = ScampReply> + Send + 'static,
     {
         self.register_with_flags(action, version, &[], handler);
     }
@@ -98,19 +123,33 @@ impl ScampService {
     pub fn register_with_flags<F, Fut>(&mut self, action: &str, version: i32, flags: &[&str], handler: F)
     where
         F: Fn(ScampRequest) -> Fut + Send + Sync + 'static,
-        Fut: std::future::Future<Output = ScampReply> + Send + 'static,
+        Fut: Future<Output = ScampReply> + Send + 'static,
+    {
+        self.actions.insert(action, version, flags, register::buffered(handler));
+    }
+
+    /// Register an action whose handler starts as soon as the request HEADER
+    /// arrives and reads the body from a [`RequestBody`] as DATA comes in.
+    /// `ScampRequest.body` is empty for these handlers, which may return a
+    /// [`ScampReply`] or stream one with [`StreamingReply::channel`].
+    pub fn register_streaming<F, Fut, R>(&mut self, action: &str, version: i32, flags: &[&str], handler: F)
+    where
+        F: Fn(ScampRequest, RequestBody) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: Into<StreamingReply>,
     {
-        let key = format!("{}.v{}", action.to_lowercase(), version);
-        let handler: ActionHandlerFn = Arc::new(move |req| Box::pin(handler(req)));
-        self.actions.insert(
-            key,
-            RegisteredAction {
-                name: action.to_string(),
-                version,
-                flags: flags.iter().map(|s| s.to_string()).collect(),
-                handler,
-            },
-        );
+        self.actions.insert(action, version, flags, register::streaming(handler));
+    }
+
+    /// Announce a registered action in `sector` with `envelopes` instead of
+    /// the service's own. Such actions go out in the announcement's v4
+    /// section, which v3-only readers don't see.
+    pub fn announce_in(&mut self, action: &str, version: i32, sector: &str, envelopes: &[&str]) -> Result<()> {
+        let zone = ActionZone {
+            sector: sector.to_string(),
+            envelopes: envelopes.iter().map(|s| s.to_string()).collect(),
+        };
+        self.actions.set_zone(action, version, zone)
     }
 
     /// Bind with TLS using specified bind address.
@@ -119,8 +158,7 @@ impl ScampService {
         self.key_pem = Some(key_pem.to_vec());
         self.cert_pem = Some(cert_pem.to_vec());
 
-        let key = native_tls::Identity::from_pkcs8(cert_pem, key_pem)?;
-        let tls = native_tls::TlsAcceptor::builder(key).build()?;
+        let tls = TlsAcceptor::from_pem(key_pem, cert_pem)?;
 
         // Perl Server.pm:27-29
         let first_port: u16 = 30100;
@@ -145,92 +183,80 @@ impl ScampService {
         log::info!("Bound to beepish+tls://{}:{}", addr.ip(), addr.port());
 
         self.listener = Some(listener);
-        self.tls_acceptor = Some(TlsAcceptor::from(tls));
+        self.tls_acceptor = Some(tls);
         self.address = Some(addr);
         Ok(())
     }
 
+    /// Bind with the key/cert and address resolved by the builder. Optional:
+    /// `serve()` binds on its own; call this first to learn the address.
+    pub async fn bind(&mut self) -> Result<()> {
+        let opts = self.serve_opts.as_ref().ok_or_else(|| anyhow!(NOT_BUILT))?;
+        let (key_pem, cert_pem, bind_ip) = (opts.key_pem.clone(), opts.cert_pem.clone(), opts.bind_ip);
+        self.bind_pem(&key_pem, &cert_pem, bind_ip).await
+    }
+
     /// Build a signed announcement packet (uncompressed bytes).
     /// Perl Announcer.pm:122-204
     pub fn build_announcement_packet(&self, active: bool) -> Result<Vec<u8>> {
-        let key_pem = self.key_pem.as_ref().ok_or_else(|| anyhow!("No key"))?;
-        let cert_pem = self.cert_pem.as_ref().ok_or_else(|| anyhow!("No cert"))?;
-        let uri = self.uri().ok_or_else(|| anyhow!("Not bound"))?;
-        let action_infos: Vec<ActionInfo> = self.actions.values().map(ActionInfo::from).collect();
-
-        announce::build_announcement_packet(
-            &self.identity,
-            &self.sector,
-            &self.envelopes,
-            &uri,
-            &action_infos,
-            key_pem,
-            cert_pem,
-            1, // weight
-            5, // interval_secs
-            active,
-        )
+        self.announcement_source(DEFAULT_INTERVAL_SECS)?.build_packet(active)
     }
 
-    /// Run the service: accept connections until shutdown signal.
-    /// JS service.js:78-91: suspend announcer, drain active requests, then exit.
-    pub async fn run(self, mut shutdown_rx: tokio::sync::watch::Receiver<bool>) -> Result<()> {
-        let listener = self.listener.ok_or_else(|| anyhow!("Not bound — call bind_pem() first"))?;
-        let tls_acceptor = self.tls_acceptor.ok_or_else(|| anyhow!("Not bound — call bind_pem() first"))?;
-        let actions = Arc::new(self.actions);
-        let authz = self.authz;
-        let active_connections = Arc::new(AtomicU64::new(0));
-
-        // Accept connections until shutdown
-        loop {
-            tokio::select! {
-                result = listener.accept() => {
-                    let (stream, peer_addr) = result?;
-                    stream.set_nodelay(true)?;
-                    let tls_acceptor = tls_acceptor.clone();
-                    let actions = actions.clone();
-                    let authz = authz.clone();
-                    let active = active_connections.clone();
-                    active.fetch_add(1, Ordering::Relaxed);
-
-                    tokio::spawn(async move {
-                        match tls_acceptor.accept(stream).await {
-                            Ok(tls_stream) => {
-                                log::debug!("Accepted connection from {}", peer_addr);
-                                server_connection::handle_connection(tls_stream, actions, authz).await;
-                            }
-                            Err(e) => {
-                                log::error!("TLS accept failed from {}: {}", peer_addr, e);
-                            }
-                        }
-                        active.fetch_sub(1, Ordering::Relaxed);
-                    });
-                }
-                _ = shutdown_rx.changed() => {
-                    if *shutdown_rx.borrow() {
-                        break;
-                    }
-                }
-            }
+    fn announcement_source(&self, interval_secs: u32) -> Result<AnnouncementSource> {
+        Ok(AnnouncementSource {
+            identity: self.identity.clone(),
+            sector: self.sector.clone(),
+            envelopes: self.envelopes.clone(),
+            uri: self.uri().ok_or_else(|| anyhow!("Not bound"))?,
+            actions: self.actions_snapshot(),
+            key_pem: self.key_pem.clone().ok_or_else(|| anyhow!("No key"))?,
+            cert_pem: self.cert_pem.clone().ok_or_else(|| anyhow!("No cert"))?,
+            weight: 1,
+            interval_secs,
+        })
+    }
+
+    /// Bind, announce and serve until `shutdown_rx` fires. On shutdown the
+    /// announcer sends its weight=0 rounds while connections drain; returns
+    /// once both are done. Requires a service from [`ScampService::builder`].
+    pub async fn serve(mut self, shutdown_rx: tokio::sync::watch::Receiver<bool>) -> Result<()> {
+        if self.listener.is_none() {
+            self.bind().await?;
         }
+        let opts = self.serve_opts.take().ok_or_else(|| anyhow!(NOT_BUILT))?;
 
-        // Drain active connections (30s timeout)
-        let active = active_connections.load(Ordering::Relaxed);
-        if active > 0 {
-            log::info!("Draining {} active connection(s)...", active);
-            let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(30);
-            while active_connections.load(Ordering::Relaxed) > 0 && tokio::time::Instant::now() < deadline {
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let announcer = match opts.multicast {
+            Some(mcast_config) => {
+                let source = self.announcement_source(mcast_config.interval_secs)?;
+                let announcer_shutdown_rx = shutdown_rx.clone();
+                Some(tokio::spawn(async move {
+                    let build_packet = |active: bool| source.build_packet(active);
+                    if let Err(e) = multicast::run_announcer(mcast_config, build_packet, announcer_shutdown_rx).await {
+                        log::error!("Announcer failed: {}", e);
+                    }
+                }))
             }
-            let remaining = active_connections.load(Ordering::Relaxed);
-            if remaining > 0 {
-                log::warn!("Shutdown timeout: {} connections still active", remaining);
+            None => None,
+        };
+
+        let result = self.run(shutdown_rx).await;
+        if let Some(announcer) = announcer {
+            // A failed listener means no shutdown signal may ever arrive.
+            if result.is_ok() {
+                let _ = announcer.await;
+            } else {
+                announcer.abort();
             }
         }
+        result
+    }
 
-        Ok(())
+    /// Run the service: accept connections until shutdown signal.
+    /// JS service.js:78-91: suspend announcer, drain active requests, then exit.
+    pub async fn run(self, shutdown_rx: tokio::sync::watch::Receiver<bool>) -> Result<()> {
+        let listener = self.listener.ok_or_else(|| anyhow!("Not bound — call bind_pem() first"))?;
+        let tls_acceptor = self.tls_acceptor.ok_or_else(|| anyhow!("Not bound — call bind_pem() first"))?;
+        let actions = Arc::new(self.actions.into_map());
+        accept_connections(listener, tls_acceptor, actions, self.authz, self.max_in_flight, shutdown_rx).await
     }
 }
-
-// Re-export for use by register() callers
-use super::handler::{ScampReply, ScampRequest};
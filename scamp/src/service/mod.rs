@@ -3,29 +3,30 @@
 //! Implements the server side of the SCAMP protocol, matching
 //! Perl Transport::BEEPish::Server and JS actor/service.js.
 
-mod announce;
+mod accept;
+pub(crate) mod announce;
+#[cfg(test)]
+mod announce_tests;
+mod builder;
+mod dispatch;
+#[cfg(test)]
+mod flow_control_tests;
 pub(crate) mod handler;
 mod listener;
 pub mod multicast;
+mod register;
 pub(crate) mod server_connection;
 #[cfg(test)]
 mod server_connection_tests;
 mod server_reply;
+mod streaming;
+#[cfg(test)]
+mod streaming_tests;
 
-pub use handler::{ActionHandlerFn, ActionInfo, ScampReply, ScampRequest};
+pub use announce::announce_raw;
+pub use builder::ScampServiceBuilder;
+pub use handler::{ActionHandlerFn, ActionInfo, ActionZone, ScampReply, ScampRequest, StreamingHandlerFn, StreamingReply};
 pub use listener::ScampService;
 pub use multicast::MulticastConfig;
-
-/// Build a raw announcement packet from action info (for use by announcer task).
-pub fn announce_raw(
-    identity: &str,
-    sector: &str,
-    envelopes: &[String],
-    uri: &str,
-    actions: &[ActionInfo],
-    key_pem: &[u8],
-    cert_pem: &[u8],
-    active: bool,
-) -> anyhow::Result<Vec<u8>> {
-    announce::build_announcement_packet(identity, sector, envelopes, uri, actions, key_pem, cert_pem, 1, 5, active)
-}
+pub use server_connection::DEFAULT_MAX_IN_FLIGHT;
+pub use streaming::{ReplySender, RequestBody};
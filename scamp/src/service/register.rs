@@ -0,0 +1,86 @@
+//! The table of actions a [`ScampService`](super::ScampService) serves,
+//! keyed by lowercased name and version.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+
+use super::handler::{
+    ActionHandler, ActionHandlerFn, ActionInfo, ActionZone, RegisteredAction, ScampReply, ScampRequest, StreamingHandlerFn, StreamingReply,
+};
+use super::streaming::RequestBody;
+
+#[derive(Default)]
+pub(super) struct ActionTable {
+    actions: HashMap<String, RegisteredAction>,
+}
+
+impl ActionTable {
+    pub fn snapshot(&self) -> Vec<ActionInfo> {
+        self.actions.values().map(ActionInfo::from).collect()
+    }
+
+    /// Register `action`, unless its version is negative, which can't be
+    /// announced.
+    pub fn insert(&mut self, action: &str, version: i32, flags: &[&str], handler: ActionHandler) {
+        if version < 0 {
+            log::error!("Not registering {}~{}: versions can't be negative", action, version);
+            return;
+        }
+        self.actions.insert(
+            action_key(action, version),
+            RegisteredAction {
+                name: action.to_string(),
+                version,
+                flags: flags.iter().map(|s| s.to_string()).collect(),
+                zone: None,
+                handler,
+            },
+        );
+    }
+
+    /// Announce a registered action under `zone` instead of the service's
+    /// own sector and envelopes.
+    pub fn set_zone(&mut self, action: &str, version: i32, zone: ActionZone) -> Result<()> {
+        if version < 0 {
+            bail!("{}~{}: versions can't be negative", action, version);
+        }
+        let Some(registered) = self.actions.get_mut(&action_key(action, version)) else {
+            bail!("{}~{} is not registered", action, version);
+        };
+        registered.zone = Some(zone);
+        Ok(())
+    }
+
+    pub fn into_map(self) -> HashMap<String, RegisteredAction> {
+        self.actions
+    }
+}
+
+pub(super) fn buffered<F, Fut>(handler: F) -> ActionHandler
+where
+    F: Fn(ScampRequest) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ScampReply> + Send + 'static,
+{
+    let handler: ActionHandlerFn = Arc::new(move |req| Box::pin(handler(req)));
+    ActionHandler::Buffered(handler)
+}
+
+pub(super) fn streaming<F, Fut, R>(handler: F) -> ActionHandler
+where
+    F: Fn(ScampRequest, RequestBody) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = R> + Send + 'static,
+    R: Into<StreamingReply>,
+{
+    let handler: StreamingHandlerFn = Arc::new(move |req, body| {
+        let reply = handler(req, body);
+        Box::pin(async move { reply.await.into() })
+    });
+    ActionHandler::Streaming(handler)
+}
+
+fn action_key(action: &str, version: i32) -> String {
+    format!("{}.v{}", action.to_lowercase(), version)
+}
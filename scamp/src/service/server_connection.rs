@@ -1,166 +1,206 @@
-//! Server-side connection handling and request dispatch.
+//! Server-side connection handling: read loop and packet routing.
 //! Matches Perl Transport::BEEPish::Server.pm connection handling.
+//!
+//! Completed requests are handed to a [`Dispatcher`], which runs each one as
+//! its own task so a slow handler doesn't stall other requests on the connection.
 
+use bytes::Bytes;
+use futures_util::StreamExt;
 use std::collections::HashMap;
-use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-use tokio::sync::Mutex;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinSet;
+use tokio_util::codec::FramedRead;
 
-use super::handler::{RegisteredAction, ScampReply, ScampRequest};
-use super::server_reply::send_reply;
+use super::dispatch::{Dispatcher, RequestPayload};
+use super::handler::RegisteredAction;
+use super::streaming::{ChunkResult, RequestBody};
 use crate::auth::authz::AuthzChecker;
-use crate::transport::beepish::proto::{Packet, PacketHeader, PacketType, ParseResult, MAX_PACKET_SIZE};
+use crate::transport::beepish::proto::{Packet, PacketCodec, PacketHeader, PacketType};
 
 /// Server connection idle timeout — Perl Server.pm:58, Connection.pm:131-135
 const DEFAULT_SERVER_TIMEOUT_SECS: u64 = 120;
 
+/// Default number of requests a single connection may have executing at once.
+/// Once they are all busy the read loop stops taking new requests, holding
+/// the client back at the TCP level, though it still handles ACKs, PINGs and
+/// DATA for requests already running.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
 struct IncomingRequest {
-    header: PacketHeader,
-    body: Vec<u8>,
+    body: IncomingBody,
     received: usize,
 }
 
-/// Writer half, boxed for testability (allows in-memory streams in tests).
-pub(crate) type ServerWriter = Arc<Mutex<Box<dyn AsyncWrite + Unpin + Send>>>;
+enum IncomingBody {
+    /// Collected here and dispatched at EOF.
+    Buffered { header: Box<PacketHeader>, body: Vec<u8> },
+    /// Already dispatched; DATA is forwarded to the handler's `RequestBody`.
+    Streaming(mpsc::UnboundedSender<ChunkResult>),
+}
 
-/// Tracks bytes sent/acknowledged for outgoing replies.
-/// Used for ACK validation (monotonic, not past end) and is_busy detection.
-/// Server-side does NOT pause on watermark — matches Perl, which sends replies unbounded.
-#[derive(Debug, Default)]
-pub(crate) struct OutgoingReplyState {
-    pub(crate) sent: u64,
-    pub(crate) acknowledged: u64,
+/// What the read loop does after routing a packet.
+enum Routed {
+    Continue,
+    /// Run this request once an in-flight slot is free.
+    Dispatch(Box<PacketHeader>, RequestPayload),
+    Close,
 }
 
+/// Writer half, boxed for testability (allows in-memory streams in tests).
+pub(crate) type ServerWriter = Arc<Mutex<Box<dyn AsyncWrite + Unpin + Send>>>;
+
 /// Handle a single server connection: read packets, dispatch requests, send replies.
 /// Accepts any async stream for testability (production passes TLS streams).
 pub(crate) async fn handle_connection(
     stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
     actions: Arc<HashMap<String, RegisteredAction>>,
     authz: Option<Arc<AuthzChecker>>,
+    max_in_flight: usize,
 ) {
-    let (mut reader, writer) = tokio::io::split(stream);
+    let (reader, writer) = tokio::io::split(stream);
+    let mut frames = FramedRead::new(reader, PacketCodec);
     let writer: ServerWriter = Arc::new(Mutex::new(Box::new(writer)));
-    let mut buf = Vec::with_capacity(8192);
+    let dispatcher = Arc::new(Dispatcher::new(actions, authz, writer, max_in_flight));
+    let mut tasks: JoinSet<()> = JoinSet::new();
     let mut incoming: HashMap<u64, IncomingRequest> = HashMap::new();
-    let mut outgoing: HashMap<u64, OutgoingReplyState> = HashMap::new();
     let mut next_incoming_msg_no: u64 = 0;
-    let next_outgoing_msg_no = AtomicU64::new(0);
+    // A request waiting for an in-flight slot, and a new request's packet read
+    // meanwhile, which waits with reading stopped until that one is running.
+    let mut ready: Option<(Box<PacketHeader>, RequestPayload)> = None;
+    let mut parked: Option<Packet> = None;
 
     loop {
-        // Read more data from the stream.
+        // Reap finished dispatch tasks so `tasks` only holds requests still in flight.
+        while tasks.try_join_next().is_some() {}
+
+        if let Some(packet) = parked.take_if(|_| ready.is_none()) {
+            match route_packet(packet, &mut incoming, &mut next_incoming_msg_no, &dispatcher).await {
+                Routed::Continue => {}
+                Routed::Dispatch(header, body) => ready = Some((header, body)),
+                Routed::Close => return,
+            }
+            continue;
+        }
+
+        // Read the next packet.
         // Perl Connection.pm:131-135 — no timeout when busy, idle timeout otherwise.
-        let is_busy = !incoming.is_empty() || !outgoing.is_empty();
-        let mut tmp = [0u8; 4096];
-        let n = if is_busy {
-            match reader.read(&mut tmp).await {
-                Ok(0) => break,
-                Ok(n) => n,
-                Err(e) => {
-                    log::debug!("Read error: {}", e);
-                    break;
+        // A finishing request wakes the loop so the idle timeout starts then.
+        // The codec rejects oversized packets, capping the read buffer (M1).
+        let is_busy = !incoming.is_empty() || !tasks.is_empty() || ready.is_some();
+        let idle_timeout = std::time::Duration::from_secs(DEFAULT_SERVER_TIMEOUT_SECS);
+        let frame = tokio::select! {
+            Some(slot) = dispatcher.slot(), if ready.is_some() => {
+                if let Some((header, body)) = ready.take() {
+                    dispatcher.spawn(*header, body, slot, &mut tasks);
                 }
+                continue;
             }
-        } else {
-            let idle_timeout = std::time::Duration::from_secs(DEFAULT_SERVER_TIMEOUT_SECS);
-            match tokio::time::timeout(idle_timeout, reader.read(&mut tmp)).await {
-                Ok(Ok(0)) => break,
-                Ok(Ok(n)) => n,
-                Ok(Err(e)) => {
-                    log::debug!("Read error: {}", e);
-                    break;
-                }
-                Err(_) => {
-                    log::debug!("Idle timeout ({}s)", idle_timeout.as_secs());
-                    break;
-                }
+            frame = frames.next(), if parked.is_none() => frame,
+            Some(_) = tasks.join_next(), if !tasks.is_empty() => continue,
+            _ = tokio::time::sleep(idle_timeout), if !is_busy => {
+                log::debug!("Idle timeout ({}s)", idle_timeout.as_secs());
+                break;
+            }
+            else => break,
+        };
+        let packet = match frame {
+            None => break,
+            Some(Ok(packet)) => packet,
+            Some(Err(e)) if e.is::<std::io::Error>() => {
+                log::debug!("Read error: {}", e);
+                break;
+            }
+            Some(Err(e)) => {
+                log::error!("Fatal protocol error: {}", e);
+                return;
             }
         };
-        buf.extend_from_slice(&tmp[..n]);
-        // M1: Cap buffer to prevent OOM from adversarial streams
-        if buf.len() > MAX_PACKET_SIZE + 100 {
-            log::error!("Read buffer exceeded max packet size, closing");
-            return;
+        if ready.is_some() && matches!(packet.packet_type, PacketType::Header | PacketType::Eof) {
+            parked = Some(packet);
+            continue;
         }
+        match route_packet(packet, &mut incoming, &mut next_incoming_msg_no, &dispatcher).await {
+            Routed::Continue => {}
+            Routed::Dispatch(header, body) => ready = Some((header, body)),
+            Routed::Close => return,
+        }
+    }
 
-        // Parse all complete packets from the buffer.
-        let mut consumed = 0;
-        while consumed < buf.len() {
-            match Packet::parse(&buf[consumed..]) {
-                ParseResult::TooShort | ParseResult::NeedBytes { .. } => break,
-                ParseResult::Drop { bytes_used } => consumed += bytes_used,
-                ParseResult::Success { packet, bytes_used } => {
-                    consumed += bytes_used;
-                    let ok = route_packet(
-                        packet,
-                        &mut incoming,
-                        &mut outgoing,
-                        &mut next_incoming_msg_no,
-                        &next_outgoing_msg_no,
-                        &writer,
-                        &actions,
-                        &authz,
-                    )
-                    .await;
-                    if !ok {
-                        return;
-                    }
-                }
-                ParseResult::Fatal(err) => {
-                    log::error!("Fatal protocol error: {}", err);
-                    return;
-                }
-            }
+    // Streaming bodies the peer never finished won't get any more DATA.
+    for (_, request) in incoming.drain() {
+        if let IncomingBody::Streaming(tx) = request.body {
+            let _ = tx.send(Err("Connection closed before request body finished".to_string()));
+        }
+    }
+
+    // Peer finished sending: let in-flight requests write their replies
+    // before the connection is torn down.
+    if let Some((header, body)) = ready {
+        if let Some(slot) = dispatcher.slot().await {
+            dispatcher.spawn(*header, body, slot, &mut tasks);
         }
-        buf.drain(..consumed);
     }
+    dispatcher.outgoing.finish_reading();
+    while tasks.join_next().await.is_some() {}
 }
 
-/// Returns false if the connection should be closed.
 async fn route_packet(
     packet: Packet,
     incoming: &mut HashMap<u64, IncomingRequest>,
-    outgoing: &mut HashMap<u64, OutgoingReplyState>,
     next_incoming_msg_no: &mut u64,
-    next_outgoing_msg_no: &AtomicU64,
-    writer: &ServerWriter,
-    actions: &Arc<HashMap<String, RegisteredAction>>,
-    authz: &Option<Arc<AuthzChecker>>,
-) -> bool {
+    dispatcher: &Arc<Dispatcher>,
+) -> Routed {
     match packet.packet_type {
         PacketType::Header => {
             // Perl Connection.pm:140 — out-of-sequence HEADER is fatal, close connection
             if packet.msg_no != *next_incoming_msg_no {
                 log::error!("Out of sequence: expected {} got {}, closing", *next_incoming_msg_no, packet.msg_no);
-                return false;
+                return Routed::Close;
             }
             *next_incoming_msg_no += 1;
             if let Some(header) = packet.packet_header {
-                incoming.insert(
-                    packet.msg_no,
-                    IncomingRequest {
-                        header,
-                        body: Vec::new(),
+                if dispatcher.is_streaming(&header) {
+                    let (tx, body) = RequestBody::channel(packet.msg_no, dispatcher.writer.clone());
+                    let request = IncomingRequest {
+                        body: IncomingBody::Streaming(tx),
                         received: 0,
-                    },
-                );
+                    };
+                    incoming.insert(packet.msg_no, request);
+                    return Routed::Dispatch(Box::new(header), RequestPayload::Streaming(body));
+                }
+                let body = IncomingBody::Buffered {
+                    header: Box::new(header),
+                    body: Vec::new(),
+                };
+                incoming.insert(packet.msg_no, IncomingRequest { body, received: 0 });
             }
         }
         PacketType::Data => {
             if let Some(msg) = incoming.get_mut(&packet.msg_no) {
-                msg.body.extend_from_slice(&packet.body);
                 msg.received += packet.body.len();
+                match &mut msg.body {
+                    IncomingBody::Buffered { body, .. } => body.extend_from_slice(&packet.body),
+                    IncomingBody::Streaming(_) if packet.body.is_empty() => return Routed::Continue,
+                    // The handler ACKs as it reads. Once it has dropped the body,
+                    // ACK here so the client isn't left stalled at its watermark.
+                    IncomingBody::Streaming(tx) => {
+                        if tx.send(Ok(packet.body)).is_ok() {
+                            return Routed::Continue;
+                        }
+                    }
+                }
                 let ack = Packet {
                     packet_type: PacketType::Ack,
                     msg_no: packet.msg_no,
                     packet_header: None,
-                    body: msg.received.to_string().into_bytes(),
+                    body: msg.received.to_string().into(),
                 };
-                let mut w = writer.lock().await;
+                let mut w = dispatcher.writer.lock().await;
                 if let Err(e) = ack.write(&mut *w).await {
                     log::error!("Failed to write ACK: {}", e);
-                    return false;
+                    return Routed::Close;
                 }
                 if let Err(e) = w.flush().await {
                     log::error!("Flush failed: {}", e);
@@ -171,10 +211,15 @@ async fn route_packet(
             // Perl Connection.pm:162 — EOF body must be empty
             if !packet.body.is_empty() {
                 log::error!("EOF packet has non-empty body ({} bytes)", packet.body.len());
-                return true;
+                return Routed::Continue;
             }
-            if let Some(msg) = incoming.remove(&packet.msg_no) {
-                dispatch_and_reply(msg, next_outgoing_msg_no, outgoing, writer, actions, authz).await;
+            // Dropping a streaming request's sender ends its RequestBody.
+            if let Some(IncomingRequest {
+                body: IncomingBody::Buffered { header, body },
+                ..
+            }) = incoming.remove(&packet.msg_no)
+            {
+                return Routed::Dispatch(header, RequestPayload::Buffered(body));
             }
         }
         PacketType::Txerr => {
@@ -182,9 +227,15 @@ async fn route_packet(
             let body_str = String::from_utf8_lossy(&packet.body);
             if body_str.is_empty() || body_str == "0" {
                 log::error!("TXERR with empty/zero body for msgno {}", packet.msg_no);
-                return true; // protocol error but not fatal to connection
+                return Routed::Continue; // protocol error but not fatal to connection
+            }
+            if let Some(IncomingRequest {
+                body: IncomingBody::Streaming(tx),
+                ..
+            }) = incoming.remove(&packet.msg_no)
+            {
+                let _ = tx.send(Err(body_str.to_string()));
             }
-            incoming.remove(&packet.msg_no);
         }
         PacketType::Ack => {
             // Perl Connection.pm:177-183 — validate ACK value
@@ -193,19 +244,21 @@ async fn route_packet(
                 Ok(v) if v > 0 => v,
                 _ => {
                     log::error!("Malformed ACK body: {:?}", body_str);
-                    return true;
+                    return Routed::Continue;
                 }
             };
-            if let Some(state) = outgoing.get_mut(&packet.msg_no) {
+            if let Some(state) = dispatcher.outgoing.states.lock().await.get_mut(&packet.msg_no) {
                 if ack_val <= state.acknowledged {
                     log::error!("ACK pointer moved backward: {} <= {}", ack_val, state.acknowledged);
-                    return true;
+                    return Routed::Continue;
                 }
                 if ack_val > state.sent {
                     log::error!("ACK pointer past end: {} > sent {}", ack_val, state.sent);
-                    return true;
+                    return Routed::Continue;
                 }
                 state.acknowledged = ack_val;
+                // Wake replies paused on the flow control watermark
+                dispatcher.outgoing.ack_notify.notify_waiters();
             }
         }
         PacketType::Ping => {
@@ -213,12 +266,12 @@ async fn route_packet(
                 packet_type: PacketType::Pong,
                 msg_no: packet.msg_no,
                 packet_header: None,
-                body: vec![],
+                body: Bytes::new(),
             };
-            let mut w = writer.lock().await;
+            let mut w = dispatcher.writer.lock().await;
             if let Err(e) = pong.write(&mut *w).await {
                 log::error!("Failed to write PONG: {}", e);
-                return false;
+                return Routed::Close;
             }
             if let Err(e) = w.flush().await {
                 log::error!("Flush failed: {}", e);
@@ -226,60 +279,5 @@ async fn route_packet(
         }
         PacketType::Pong => {}
     }
-    true
-}
-
-async fn dispatch_and_reply(
-    msg: IncomingRequest,
-    next_outgoing_msg_no: &AtomicU64,
-    outgoing: &mut HashMap<u64, OutgoingReplyState>,
-    writer: &ServerWriter,
-    actions: &Arc<HashMap<String, RegisteredAction>>,
-    authz: &Option<Arc<AuthzChecker>>,
-) {
-    let request_id = msg.header.request_id;
-    let action_key = format!("{}.v{}", msg.header.action.to_lowercase(), msg.header.version);
-
-    // C1: Check ticket privileges before dispatch — JS ticket.js:71-93
-    // Skip for actions with "noauth" flag, or if no AuthzChecker configured.
-    let noauth = actions
-        .get(&action_key)
-        .map(|a| a.flags.iter().any(|f| f == "noauth"))
-        .unwrap_or(false);
-    if let Some(checker) = authz {
-        if !noauth {
-            // M5: Empty ticket on a non-noauth action must be denied
-            if msg.header.ticket.is_empty() {
-                log::warn!("No ticket for non-noauth action {}", action_key);
-                let reply = ScampReply::error("Authentication required".to_string(), "unauthorized".to_string());
-                send_reply(reply, request_id, next_outgoing_msg_no, outgoing, writer).await;
-                return;
-            }
-            if let Err(e) = checker.check_access(&msg.header.action, &msg.header.ticket).await {
-                log::warn!("Authorization denied for {}: {}", action_key, e);
-                let reply = ScampReply::error(e.to_string(), "unauthorized".to_string());
-                send_reply(reply, request_id, next_outgoing_msg_no, outgoing, writer).await;
-                return;
-            }
-        }
-    }
-
-    let request = ScampRequest {
-        action: msg.header.action,
-        version: msg.header.version,
-        envelope: msg.header.envelope,
-        request_id: msg.header.request_id,
-        client_id: msg.header.client_id,
-        ticket: msg.header.ticket,
-        identifying_token: msg.header.identifying_token,
-        body: msg.body,
-    };
-
-    let reply = if let Some(registered) = actions.get(&action_key) {
-        (registered.handler)(request).await
-    } else {
-        ScampReply::error(format!("No such action: {}", action_key), "not_found".to_string())
-    };
-
-    send_reply(reply, request_id, next_outgoing_msg_no, outgoing, writer).await;
+    Routed::Continue
 }
@@ -1,7 +1,8 @@
-use super::server_connection::handle_connection;
-use crate::service::handler::RegisteredAction;
+use super::server_connection::{handle_connection, DEFAULT_MAX_IN_FLIGHT};
+use crate::service::handler::{ActionHandler, RegisteredAction, ScampReply};
 use crate::test_helpers::{echo_actions, parse_all_packets, write_request};
 use crate::transport::beepish::proto::{MessageType, Packet, PacketType};
+use bytes::Bytes;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -9,7 +10,7 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 /// Send a single request and collect all response packets.
 async fn roundtrip(actions: Arc<HashMap<String, RegisteredAction>>, action: &str, version: i32, body: &[u8]) -> Vec<Packet> {
     let (client, server) = tokio::io::duplex(65536);
-    let server_handle = tokio::spawn(handle_connection(server, actions, None));
+    let server_handle = tokio::spawn(handle_connection(server, actions, None, DEFAULT_MAX_IN_FLIGHT));
     let (mut client_read, mut client_write) = tokio::io::split(client);
 
     write_request(&mut client_write, 0, action, version, 1, body).await;
@@ -80,14 +81,14 @@ async fn test_empty_body_request() {
 #[tokio::test]
 async fn test_ping_pong() {
     let (client, server) = tokio::io::duplex(65536);
-    let server_handle = tokio::spawn(handle_connection(server, echo_actions(), None));
+    let server_handle = tokio::spawn(handle_connection(server, echo_actions(), None, DEFAULT_MAX_IN_FLIGHT));
     let (mut client_read, mut client_write) = tokio::io::split(client);
 
     Packet {
         packet_type: PacketType::Ping,
         msg_no: 0,
         packet_header: None,
-        body: vec![],
+        body: Bytes::new(),
     }
     .write(&mut client_write)
     .await
@@ -141,3 +142,167 @@ async fn test_multi_chunk_roundtrip() {
         .count();
     assert_eq!(data_count, 3);
 }
+
+/// Actions where `slow` sleeps before echoing and `fast` echoes immediately.
+fn slow_and_fast_actions() -> Arc<HashMap<String, RegisteredAction>> {
+    let mut actions = HashMap::new();
+    actions.insert(
+        "slow.v1".to_string(),
+        RegisteredAction {
+            name: "slow".to_string(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+            handler: ActionHandler::Buffered(Arc::new(|req| {
+                Box::pin(async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                    ScampReply::ok(req.body)
+                })
+            })),
+        },
+    );
+    actions.insert(
+        "fast.v1".to_string(),
+        RegisteredAction {
+            name: "fast".to_string(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+            handler: ActionHandler::Buffered(Arc::new(|req| Box::pin(async move { ScampReply::ok(req.body) }))),
+        },
+    );
+    Arc::new(actions)
+}
+
+/// Send a slow request followed by a fast one; return reply request_ids in wire order.
+async fn slow_then_fast(max_in_flight: usize) -> Vec<(u64, i64)> {
+    let (client, server) = tokio::io::duplex(65536);
+    let server_handle = tokio::spawn(handle_connection(server, slow_and_fast_actions(), None, max_in_flight));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+
+    write_request(&mut client_write, 0, "slow", 1, 1, b"slow").await;
+    write_request(&mut client_write, 1, "fast", 1, 2, b"fast").await;
+    client_write.shutdown().await.unwrap();
+
+    let mut response_data = Vec::new();
+    client_read.read_to_end(&mut response_data).await.unwrap();
+    server_handle.await.unwrap();
+    parse_all_packets(&response_data)
+        .iter()
+        .filter(|p| p.packet_type == PacketType::Header)
+        .map(|p| (p.msg_no, p.packet_header.as_ref().unwrap().request_id.0))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_slow_handler_does_not_block_connection() {
+    let replies = slow_then_fast(DEFAULT_MAX_IN_FLIGHT).await;
+    // Fast reply goes out first; reply msgnos stay sequential on the wire.
+    assert_eq!(replies, vec![(0, 2), (1, 1)]);
+}
+
+#[tokio::test]
+async fn test_max_in_flight_serializes_dispatch() {
+    let replies = slow_then_fast(1).await;
+    assert_eq!(replies, vec![(0, 1), (1, 2)]);
+}
+
+#[tokio::test]
+async fn test_concurrent_replies_interleave_cleanly() {
+    let (client, server) = tokio::io::duplex(65536);
+    let server_handle = tokio::spawn(handle_connection(server, echo_actions(), None, DEFAULT_MAX_IN_FLIGHT));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+
+    let bodies: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 5000 + i as usize]).collect();
+    for (i, body) in bodies.iter().enumerate() {
+        write_request(&mut client_write, i as u64, "echo", 1, i as i64 + 1, body).await;
+    }
+    client_write.shutdown().await.unwrap();
+
+    let mut response_data = Vec::new();
+    client_read.read_to_end(&mut response_data).await.unwrap();
+    server_handle.await.unwrap();
+    let packets = parse_all_packets(&response_data);
+
+    for reply_hdr in packets.iter().filter(|p| p.packet_type == PacketType::Header) {
+        let request_id = reply_hdr.packet_header.as_ref().unwrap().request_id.0;
+        let reply_body: Vec<u8> = packets
+            .iter()
+            .filter(|p| p.packet_type == PacketType::Data && p.msg_no == reply_hdr.msg_no)
+            .flat_map(|p| p.body.iter().cloned())
+            .collect();
+        assert_eq!(reply_body, bodies[request_id as usize - 1]);
+    }
+    let eof_count = packets.iter().filter(|p| p.packet_type == PacketType::Eof).count();
+    assert_eq!(eof_count, bodies.len());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_idle_timeout_after_request_completes() {
+    let (client, server) = tokio::io::duplex(65536);
+    let server_handle = tokio::spawn(handle_connection(server, echo_actions(), None, DEFAULT_MAX_IN_FLIGHT));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+
+    // The client keeps its write half open, as a pooled connection would.
+    write_request(&mut client_write, 0, "echo", 1, 1, b"hello").await;
+    let started = tokio::time::Instant::now();
+    let mut response_data = Vec::new();
+    client_read.read_to_end(&mut response_data).await.unwrap();
+    server_handle.await.unwrap();
+
+    assert!(started.elapsed() >= std::time::Duration::from_secs(120));
+    assert!(parse_all_packets(&response_data).iter().any(|p| p.packet_type == PacketType::Eof));
+}
+
+#[tokio::test]
+async fn test_requests_beyond_max_in_flight_wait_in_the_socket() {
+    // Handlers block until the gate opens.
+    let gate = Arc::new(tokio::sync::Semaphore::new(0));
+    let handler_gate = gate.clone();
+    let mut actions = HashMap::new();
+    actions.insert(
+        "gated.v1".to_string(),
+        RegisteredAction {
+            name: "gated".to_string(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+            handler: ActionHandler::Buffered(Arc::new(move |req| {
+                let gate = handler_gate.clone();
+                Box::pin(async move {
+                    let _ = gate.acquire().await;
+                    ScampReply::ok(req.body)
+                })
+            })),
+        },
+    );
+    let (client, server) = tokio::io::duplex(4096);
+    let server_handle = tokio::spawn(handle_connection(server, Arc::new(actions), None, 1));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+
+    // Replies are read as they come, as a client would.
+    let reading = tokio::spawn(async move {
+        let mut response_data = Vec::new();
+        client_read.read_to_end(&mut response_data).await.unwrap();
+        response_data
+    });
+    const REQUESTS: u64 = 64;
+    let writing = tokio::spawn(async move {
+        for i in 0..REQUESTS {
+            write_request(&mut client_write, i, "gated", 1, i as i64 + 1, &[0; 1000]).await;
+        }
+        client_write.shutdown().await.unwrap();
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(!writing.is_finished(), "the server should stop reading while its slot is busy");
+
+    gate.add_permits(REQUESTS as usize);
+    writing.await.unwrap();
+    let response_data = reading.await.unwrap();
+    server_handle.await.unwrap();
+    let eofs = parse_all_packets(&response_data)
+        .iter()
+        .filter(|p| p.packet_type == PacketType::Eof)
+        .count();
+    assert_eq!(eofs, REQUESTS as usize);
+}
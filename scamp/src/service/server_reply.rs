@@ -1,22 +1,92 @@
 //! Reply writing for server connections.
 //! Extracted from server_connection.rs to stay under 300-line limit.
+//!
+//! The writer lock is taken per packet, so replies from concurrently running
+//! handlers interleave on the connection at packet boundaries.
+//!
+//! H2: Perl sends replies unbounded. Here a reply pauses once
+//! FLOW_CONTROL_WATERMARK bytes are un-ACKed, like the client does for
+//! requests, so a slow consumer can't balloon service memory.
 
+use bytes::Bytes;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, Notify};
 
-use super::handler::ScampReply;
-use super::server_connection::{OutgoingReplyState, ServerWriter};
-use crate::transport::beepish::proto::{EnvelopeFormat, FlexInt, MessageType, Packet, PacketHeader, PacketType, DATA_CHUNK_SIZE};
+use super::handler::StreamingReply;
+use super::server_connection::ServerWriter;
+use crate::transport::beepish::proto::{
+    data_chunks, EnvelopeFormat, FlexInt, MessageType, Packet, PacketHeader, PacketType, FLOW_CONTROL_WATERMARK,
+};
+
+/// Tracks bytes sent/acknowledged for an outgoing reply.
+/// Used for ACK validation (monotonic, not past end) and flow control.
+#[derive(Debug, Default)]
+pub(crate) struct OutgoingReplyState {
+    pub(crate) sent: u64,
+    pub(crate) acknowledged: u64,
+}
+
+/// Outgoing reply states keyed by msgno, shared between the read loop (ACKs)
+/// and the dispatch tasks writing replies.
+#[derive(Default)]
+pub(crate) struct OutgoingReplies {
+    pub(crate) states: Mutex<HashMap<u64, OutgoingReplyState>>,
+    /// Signalled when an ACK advances, and when the peer stops sending.
+    pub(crate) ack_notify: Notify,
+    /// Set once the read side has ended: no further ACKs can arrive, so
+    /// replies still being written are no longer held at the watermark.
+    pub(crate) peer_done: AtomicBool,
+}
+
+impl OutgoingReplies {
+    /// The read side has ended; release any replies paused on flow control.
+    pub(crate) fn finish_reading(&self) {
+        self.peer_done.store(true, Ordering::Relaxed);
+        self.ack_notify.notify_waiters();
+    }
+
+    /// Wait until reply `msg_no` is below the watermark. Flushes what has been
+    /// written so far before pausing, so the client can see and ACK it.
+    async fn wait_for_window(&self, msg_no: u64, writer: &ServerWriter) {
+        let mut flushed = false;
+        loop {
+            // Register for the wakeup before checking, so an ACK landing in
+            // between isn't missed.
+            let notified = self.ack_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.peer_done.load(Ordering::Relaxed) {
+                return;
+            }
+            let over_watermark = self
+                .states
+                .lock()
+                .await
+                .get(&msg_no)
+                .is_some_and(|s| s.sent.saturating_sub(s.acknowledged) >= FLOW_CONTROL_WATERMARK);
+            if !over_watermark {
+                return;
+            }
+            if !flushed {
+                let _ = writer.lock().await.flush().await;
+                flushed = true;
+            }
+            notified.await;
+        }
+    }
+}
 
 pub(crate) async fn send_reply(
-    reply: ScampReply,
+    reply: StreamingReply,
     request_id: FlexInt,
     next_outgoing_msg_no: &AtomicU64,
-    outgoing: &mut HashMap<u64, OutgoingReplyState>,
+    outgoing: &OutgoingReplies,
     writer: &ServerWriter,
 ) {
-    let reply_msg_no = next_outgoing_msg_no.fetch_add(1, Ordering::Relaxed);
+    let StreamingReply { reply, chunks } = reply;
     let reply_header = PacketHeader {
         action: String::new(),
         envelope: EnvelopeFormat::Json,
@@ -31,54 +101,90 @@ pub(crate) async fn send_reply(
         version: 0,
     };
 
-    outgoing.insert(reply_msg_no, OutgoingReplyState::default());
+    // Allocate the msgno while holding the writer lock so reply HEADERs reach
+    // the wire in msgno order — Perl Connection.pm:140 rejects out-of-sequence HEADERs.
+    let reply_msg_no = {
+        let mut w = writer.lock().await;
+        let msg_no = next_outgoing_msg_no.fetch_add(1, Ordering::Relaxed);
+        outgoing.states.lock().await.insert(msg_no, OutgoingReplyState::default());
+        let header_pkt = Packet {
+            packet_type: PacketType::Header,
+            msg_no,
+            packet_header: Some(reply_header),
+            body: Bytes::new(),
+        };
+        if let Err(e) = header_pkt.write(&mut *w).await {
+            log::error!("Failed to write reply HEADER: {}", e);
+            outgoing.states.lock().await.remove(&msg_no);
+            return;
+        }
+        msg_no
+    };
+
+    if !write_data(reply.body.into(), reply_msg_no, outgoing, writer).await {
+        outgoing.states.lock().await.remove(&reply_msg_no);
+        return;
+    }
 
-    let mut w = writer.lock().await;
-    let header_pkt = Packet {
-        packet_type: PacketType::Header,
+    // Streamed body: forward chunks as the handler produces them, flushing
+    // each so the client sees data before the reply is complete.
+    let mut end_type = PacketType::Eof;
+    let mut end_body = Bytes::new();
+    if let Some(mut chunks) = chunks {
+        while let Some(item) = chunks.recv().await {
+            match item {
+                Ok(chunk) => {
+                    if !write_data(chunk, reply_msg_no, outgoing, writer).await {
+                        outgoing.states.lock().await.remove(&reply_msg_no);
+                        return;
+                    }
+                    let _ = writer.lock().await.flush().await;
+                }
+                Err(error) => {
+                    end_type = PacketType::Txerr;
+                    end_body = error.into();
+                    break;
+                }
+            }
+        }
+    }
+
+    let end_pkt = Packet {
+        packet_type: end_type,
         msg_no: reply_msg_no,
-        packet_header: Some(reply_header),
-        body: vec![],
+        packet_header: None,
+        body: end_body,
     };
-    if let Err(e) = header_pkt.write(&mut *w).await {
-        log::error!("Failed to write reply HEADER: {}", e);
-        outgoing.remove(&reply_msg_no);
-        return;
+    {
+        let mut w = writer.lock().await;
+        if let Err(e) = end_pkt.write(&mut *w).await {
+            log::error!("Failed to write reply {:?}: {}", end_type, e);
+        }
+        if let Err(e) = w.flush().await {
+            log::error!("Reply flush failed: {}", e);
+        }
     }
 
-    let mut offset = 0;
-    while offset < reply.body.len() {
-        let end = (offset + DATA_CHUNK_SIZE).min(reply.body.len());
-        let chunk_len = (end - offset) as u64;
+    outgoing.states.lock().await.remove(&reply_msg_no);
+}
+
+/// Write `data` as DATA packets. Returns false if the connection failed.
+async fn write_data(data: Bytes, msg_no: u64, outgoing: &OutgoingReplies, writer: &ServerWriter) -> bool {
+    for chunk in data_chunks(data) {
+        outgoing.wait_for_window(msg_no, writer).await;
         let data_pkt = Packet {
             packet_type: PacketType::Data,
-            msg_no: reply_msg_no,
+            msg_no,
             packet_header: None,
-            body: reply.body[offset..end].to_vec(),
+            body: chunk.clone(),
         };
-        if let Err(e) = data_pkt.write(&mut *w).await {
+        if let Err(e) = data_pkt.write(&mut *writer.lock().await).await {
             log::error!("Failed to write reply DATA: {}", e);
-            outgoing.remove(&reply_msg_no);
-            return;
+            return false;
         }
-        if let Some(state) = outgoing.get_mut(&reply_msg_no) {
-            state.sent += chunk_len;
+        if let Some(state) = outgoing.states.lock().await.get_mut(&msg_no) {
+            state.sent += chunk.len() as u64;
         }
-        offset = end;
-    }
-
-    let eof_pkt = Packet {
-        packet_type: PacketType::Eof,
-        msg_no: reply_msg_no,
-        packet_header: None,
-        body: vec![],
-    };
-    if let Err(e) = eof_pkt.write(&mut *w).await {
-        log::error!("Failed to write reply EOF: {}", e);
     }
-    if let Err(e) = w.flush().await {
-        log::error!("Reply flush failed: {}", e);
-    }
-
-    outgoing.remove(&reply_msg_no);
+    true
 }
@@ -0,0 +1,136 @@
+//! Incremental request and reply bodies for service handlers.
+//!
+//! A streaming action (see [`ScampService::register_streaming`]) is dispatched
+//! as soon as its HEADER arrives and reads DATA through a [`RequestBody`]. Each
+//! chunk is ACKed when the handler takes it rather than when it arrives, so a
+//! handler that reads slowly holds the client at its flow-control watermark
+//! instead of the upload piling up in service memory.
+//!
+//! A streaming action can also stream its reply through the [`ReplySender`]
+//! half of [`StreamingReply::channel`](super::StreamingReply::channel).
+//!
+//! [`ScampService::register_streaming`]: super::ScampService::register_streaming
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use super::server_connection::ServerWriter;
+use crate::transport::beepish::proto::{Packet, PacketType};
+
+/// A DATA payload, or the TXERR text that aborted the message.
+pub(crate) type ChunkResult = std::result::Result<Bytes, String>;
+
+/// Reply chunks queued by a [`ReplySender`], drained by the connection.
+pub(crate) type ReplyChunks = mpsc::Receiver<ChunkResult>;
+
+/// Chunks a [`ReplySender`] may queue before `send` waits for the connection.
+const REPLY_QUEUE_CHUNKS: usize = 16;
+
+/// Request body of a streaming action, read chunk by chunk as DATA arrives.
+pub struct RequestBody {
+    chunks: mpsc::UnboundedReceiver<ChunkResult>,
+    /// Where to ACK consumed bytes; None for a body that was already buffered.
+    ack: Option<(u64, ServerWriter)>,
+    consumed: u64,
+}
+
+impl RequestBody {
+    /// Body fed by the read loop for request `msg_no`; ACKs go out on `writer`.
+    pub(crate) fn channel(msg_no: u64, writer: ServerWriter) -> (mpsc::UnboundedSender<ChunkResult>, Self) {
+        let (tx, chunks) = mpsc::unbounded_channel();
+        let body = RequestBody {
+            chunks,
+            ack: Some((msg_no, writer)),
+            consumed: 0,
+        };
+        (tx, body)
+    }
+
+    /// Body whose bytes have all arrived already.
+    pub(crate) fn buffered(body: Vec<u8>) -> Self {
+        let (tx, chunks) = mpsc::unbounded_channel();
+        if !body.is_empty() {
+            let _ = tx.send(Ok(body.into()));
+        }
+        RequestBody {
+            chunks,
+            ack: None,
+            consumed: 0,
+        }
+    }
+
+    /// Next chunk of the body, or None once the client has sent EOF.
+    /// Errors if the client aborted the request with TXERR.
+    pub async fn chunk(&mut self) -> Option<Result<Bytes>> {
+        match self.chunks.recv().await? {
+            Ok(chunk) => {
+                self.consumed += chunk.len() as u64;
+                self.send_ack().await;
+                Some(Ok(chunk))
+            }
+            Err(error) => Some(Err(anyhow!("Request aborted by client: {}", error))),
+        }
+    }
+
+    /// Collect the rest of the body.
+    pub async fn read_to_end(mut self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.chunk().await {
+            body.extend_from_slice(&chunk?);
+        }
+        Ok(body)
+    }
+
+    /// ACK: cumulative bytes as decimal string (Perl Connection.pm:153)
+    async fn send_ack(&self) {
+        let Some((msg_no, writer)) = &self.ack else {
+            return;
+        };
+        let ack = Packet {
+            packet_type: PacketType::Ack,
+            msg_no: *msg_no,
+            packet_header: None,
+            body: self.consumed.to_string().into(),
+        };
+        let mut w = writer.lock().await;
+        // A dead connection is noticed by the read loop; nothing to do here.
+        if ack.write(&mut *w).await.is_ok() {
+            let _ = w.flush().await;
+        }
+    }
+}
+
+/// Writes the body of a reply created with
+/// [`StreamingReply::channel`](super::StreamingReply::channel).
+pub struct ReplySender {
+    tx: mpsc::Sender<ChunkResult>,
+}
+
+pub(crate) fn reply_channel() -> (ReplySender, ReplyChunks) {
+    let (tx, rx) = mpsc::channel(REPLY_QUEUE_CHUNKS);
+    (ReplySender { tx }, rx)
+}
+
+impl ReplySender {
+    /// Queue a chunk of the reply body. Waits while the connection is behind;
+    /// errors once the connection is gone.
+    pub async fn send(&self, chunk: impl Into<Bytes>) -> Result<()> {
+        let chunk = chunk.into();
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        self.tx
+            .send(Ok(chunk))
+            .await
+            .map_err(|_| anyhow!("Reply stream closed: connection is gone"))
+    }
+
+    /// End the reply with TXERR instead of EOF, telling the client the body is incomplete.
+    pub async fn abort(self, error: &str) {
+        // JS connection.js:229 — an empty TXERR body is invalid
+        let error = if error.is_empty() { "aborted" } else { error };
+        let _ = self.tx.send(Err(error.to_string())).await;
+    }
+}
@@ -0,0 +1,217 @@
+use super::handler::{ActionHandler, RegisteredAction, ScampReply, StreamingReply};
+use super::server_connection::{handle_connection, DEFAULT_MAX_IN_FLIGHT};
+use super::streaming::RequestBody;
+use crate::test_helpers::{parse_all_packets, streaming_echo_actions, write_request};
+use crate::transport::beepish::proto::{Packet, PacketType};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Notify;
+
+fn single_action(name: &str, handler: ActionHandler) -> Arc<HashMap<String, RegisteredAction>> {
+    let mut actions = HashMap::new();
+    actions.insert(
+        format!("{}.v1", name),
+        RegisteredAction {
+            name: name.to_string(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+            handler,
+        },
+    );
+    Arc::new(actions)
+}
+
+fn reply_body(packets: &[Packet], msg_no: u64) -> Vec<u8> {
+    packets
+        .iter()
+        .filter(|p| p.packet_type == PacketType::Data && p.msg_no == msg_no)
+        .flat_map(|p| p.body.iter().cloned())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_streaming_action_echoes_large_body() {
+    let (client, server) = tokio::io::duplex(1 << 20);
+    let server_handle = tokio::spawn(handle_connection(server, streaming_echo_actions(), None, DEFAULT_MAX_IN_FLIGHT));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+
+    let body: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    write_request(&mut client_write, 0, "stream_echo", 1, 1, &body).await;
+    client_write.shutdown().await.unwrap();
+
+    let mut response_data = Vec::new();
+    client_read.read_to_end(&mut response_data).await.unwrap();
+    server_handle.await.unwrap();
+    let packets = parse_all_packets(&response_data);
+
+    assert_eq!(reply_body(&packets, 0), body);
+    assert!(packets.iter().any(|p| p.packet_type == PacketType::Eof));
+    // Every request byte was ACKed once the handler had read it.
+    let last_ack = packets.iter().rfind(|p| p.packet_type == PacketType::Ack).unwrap();
+    assert_eq!(last_ack.body, body.len().to_string().into_bytes());
+}
+
+#[tokio::test]
+async fn test_streaming_request_acked_only_when_read() {
+    let gate = Arc::new(Notify::new());
+    let handler_gate = gate.clone();
+    let actions = single_action(
+        "upload",
+        ActionHandler::Streaming(Arc::new(move |_req, body: RequestBody| {
+            let gate = handler_gate.clone();
+            Box::pin(async move {
+                gate.notified().await;
+                let body = body.read_to_end().await.unwrap();
+                ScampReply::ok(body.len().to_string().into_bytes()).into()
+            })
+        })),
+    );
+    let (client, server) = tokio::io::duplex(1 << 20);
+    let server_handle = tokio::spawn(handle_connection(server, actions, None, DEFAULT_MAX_IN_FLIGHT));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+
+    write_request(&mut client_write, 0, "upload", 1, 1, &[7u8; 10_000]).await;
+
+    // Nothing has been consumed yet, so nothing is ACKed.
+    let mut buf = [0u8; 64];
+    let early = tokio::time::timeout(Duration::from_millis(200), client_read.read(&mut buf)).await;
+    assert!(early.is_err(), "server ACKed before the handler read the body");
+
+    gate.notify_one();
+    client_write.shutdown().await.unwrap();
+    let mut response_data = Vec::new();
+    client_read.read_to_end(&mut response_data).await.unwrap();
+    server_handle.await.unwrap();
+    let packets = parse_all_packets(&response_data);
+
+    let acks: Vec<_> = packets.iter().filter(|p| p.packet_type == PacketType::Ack).collect();
+    assert!(!acks.is_empty());
+    assert_eq!(&acks.last().unwrap().body[..], b"10000");
+    assert_eq!(reply_body(&packets, 0), b"10000");
+}
+
+#[tokio::test]
+async fn test_aborted_reply_stream_sends_txerr() {
+    let actions = single_action(
+        "export",
+        ActionHandler::Streaming(Arc::new(|_req, _body| {
+            Box::pin(async move {
+                let (reply, sender) = StreamingReply::channel();
+                tokio::spawn(async move {
+                    sender.send(b"partial".to_vec()).await.unwrap();
+                    sender.abort("disk full").await;
+                });
+                reply
+            })
+        })),
+    );
+    let (client, server) = tokio::io::duplex(65536);
+    let server_handle = tokio::spawn(handle_connection(server, actions, None, DEFAULT_MAX_IN_FLIGHT));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+
+    write_request(&mut client_write, 0, "export", 1, 1, b"{}").await;
+    client_write.shutdown().await.unwrap();
+
+    let mut response_data = Vec::new();
+    client_read.read_to_end(&mut response_data).await.unwrap();
+    server_handle.await.unwrap();
+    let packets = parse_all_packets(&response_data);
+
+    assert_eq!(reply_body(&packets, 0), b"partial");
+    let txerr = packets.iter().find(|p| p.packet_type == PacketType::Txerr).expect("no TXERR");
+    assert_eq!(&txerr.body[..], b"disk full");
+    assert!(!packets.iter().any(|p| p.packet_type == PacketType::Eof));
+}
+
+#[tokio::test]
+async fn test_streaming_request_sees_client_txerr() {
+    let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+    let result_tx = Arc::new(std::sync::Mutex::new(Some(result_tx)));
+    let actions = single_action(
+        "upload",
+        ActionHandler::Streaming(Arc::new(move |_req, body: RequestBody| {
+            let result_tx = result_tx.clone();
+            Box::pin(async move {
+                let result = body.read_to_end().await;
+                let _ = result_tx.lock().unwrap().take().unwrap().send(result.map_err(|e| e.to_string()));
+                ScampReply::ok(vec![]).into()
+            })
+        })),
+    );
+    let (client, server) = tokio::io::duplex(65536);
+    let _server = tokio::spawn(handle_connection(server, actions, None, DEFAULT_MAX_IN_FLIGHT));
+    let (_client_read, mut client_write) = tokio::io::split(client);
+
+    let packets = [
+        Packet {
+            packet_type: PacketType::Header,
+            msg_no: 0,
+            packet_header: Some(crate::test_helpers::make_request_header("upload", 1, 1)),
+            body: Bytes::new(),
+        },
+        Packet {
+            packet_type: PacketType::Data,
+            msg_no: 0,
+            packet_header: None,
+            body: Bytes::from_static(b"half a file"),
+        },
+        Packet {
+            packet_type: PacketType::Txerr,
+            msg_no: 0,
+            packet_header: None,
+            body: Bytes::from_static(b"source went away"),
+        },
+    ];
+    for packet in packets {
+        packet.write(&mut client_write).await.unwrap();
+    }
+    client_write.flush().await.unwrap();
+
+    let err = result_rx.await.unwrap().unwrap_err();
+    assert!(err.contains("source went away"), "got: {}", err);
+}
+
+#[tokio::test]
+async fn test_streaming_request_ends_when_client_disconnects() {
+    let (client, server) = tokio::io::duplex(65536);
+    let server_handle = tokio::spawn(handle_connection(server, streaming_echo_actions(), None, DEFAULT_MAX_IN_FLIGHT));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+
+    let packets = [
+        Packet {
+            packet_type: PacketType::Header,
+            msg_no: 0,
+            packet_header: Some(crate::test_helpers::make_request_header("stream_echo", 1, 1)),
+            body: Bytes::new(),
+        },
+        Packet {
+            packet_type: PacketType::Data,
+            msg_no: 0,
+            packet_header: None,
+            body: Bytes::from_static(b"first chunk"),
+        },
+    ];
+    for packet in packets {
+        packet.write(&mut client_write).await.unwrap();
+    }
+    client_write.flush().await.unwrap();
+
+    // Wait for the echo to start, then go away mid-request.
+    let mut received = Vec::new();
+    while !reply_body(&parse_all_packets(&received), 0).ends_with(b"first chunk") {
+        let mut buf = [0u8; 1024];
+        let n = client_read.read(&mut buf).await.unwrap();
+        assert!(n > 0, "server closed before echoing");
+        received.extend_from_slice(&buf[..n]);
+    }
+    drop((client_read, client_write));
+
+    tokio::time::timeout(Duration::from_secs(5), server_handle)
+        .await
+        .expect("server should exit once the client is gone")
+        .unwrap();
+}
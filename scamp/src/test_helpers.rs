@@ -3,12 +3,16 @@
 
 #![cfg(test)]
 
+use bytes::Bytes;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::service::handler::{RegisteredAction, ScampReply};
+use crate::discovery::service_info::{Action, AnnouncementParams, PacketSection};
+use crate::discovery::{ActionEntry, ServiceInfo};
+use crate::service::handler::{ActionHandler, RegisteredAction, ScampReply, StreamingReply};
+use crate::service::RequestBody;
 use crate::transport::beepish::proto::{FlexInt, MessageType, Packet, PacketHeader, PacketType, ParseResult, DATA_CHUNK_SIZE};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
 /// Create a registered action map with an echo handler for testing.
 pub fn echo_actions() -> Arc<HashMap<String, RegisteredAction>> {
@@ -19,7 +23,51 @@ pub fn echo_actions() -> Arc<HashMap<String, RegisteredAction>> {
             name: "echo".to_string(),
             version: 1,
             flags: vec![],
-            handler: Arc::new(|req| Box::pin(async move { ScampReply::ok(req.body) })),
+            zone: None,
+            handler: ActionHandler::Buffered(Arc::new(|req| Box::pin(async move { ScampReply::ok(req.body) }))),
+        },
+    );
+    Arc::new(actions)
+}
+
+/// `echo_actions` plus `stream_echo.v1`, a streaming action that forwards each
+/// request chunk into a streamed reply as soon as it is read.
+pub fn streaming_echo_actions() -> Arc<HashMap<String, RegisteredAction>> {
+    let mut actions: HashMap<String, RegisteredAction> = HashMap::new();
+    actions.insert(
+        "echo.v1".to_string(),
+        RegisteredAction {
+            name: "echo".to_string(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+            handler: ActionHandler::Buffered(Arc::new(|req| Box::pin(async move { ScampReply::ok(req.body) }))),
+        },
+    );
+    actions.insert(
+        "stream_echo.v1".to_string(),
+        RegisteredAction {
+            name: "stream_echo".to_string(),
+            version: 1,
+            flags: vec![],
+            zone: None,
+            handler: ActionHandler::Streaming(Arc::new(|_req, mut body: RequestBody| {
+                Box::pin(async move {
+                    let (reply, sender) = StreamingReply::channel();
+                    tokio::spawn(async move {
+                        while let Some(chunk) = body.chunk().await {
+                            let sent = match chunk {
+                                Ok(chunk) => sender.send(chunk).await,
+                                Err(e) => return sender.abort(&e.to_string()).await,
+                            };
+                            if sent.is_err() {
+                                return;
+                            }
+                        }
+                    });
+                    reply
+                })
+            })),
         },
     );
     Arc::new(actions)
@@ -36,6 +84,32 @@ pub fn make_request_header(action: &str, version: i32, request_id: i64) -> Packe
     }
 }
 
+/// A routable registry entry for `path`~`version` in the main sector.
+pub fn make_action_entry(path: &str, version: u32) -> ActionEntry {
+    ActionEntry {
+        action: Action {
+            path: path.to_string(),
+            version,
+            pathver: format!("{path}~{version}"),
+            flags: vec![],
+            sector: "main".to_string(),
+            envelopes: vec!["json".to_string()],
+            packet_section: PacketSection::V3,
+        },
+        service_info: ServiceInfo {
+            identity: "test:abcd".to_string(),
+            uri: "beepish+tls://127.0.0.1:30100".to_string(),
+            fingerprint: None,
+        },
+        announcement_params: AnnouncementParams {
+            weight: 1,
+            interval: 5000,
+            timestamp: 0.0,
+        },
+        authorized: true,
+    }
+}
+
 /// Write a complete SCAMP request (HEADER + DATA chunks + EOF) to a stream.
 pub async fn write_request(
     writer: &mut (impl AsyncWriteExt + Unpin),
@@ -49,7 +123,7 @@ pub async fn write_request(
         packet_type: PacketType::Header,
         msg_no,
         packet_header: Some(make_request_header(action, version, request_id)),
-        body: vec![],
+        body: Bytes::new(),
     }
     .write(writer)
     .await
@@ -60,7 +134,7 @@ pub async fn write_request(
             packet_type: PacketType::Data,
             msg_no,
             packet_header: None,
-            body: chunk.to_vec(),
+            body: Bytes::copy_from_slice(chunk),
         }
         .write(writer)
         .await
@@ -71,7 +145,7 @@ pub async fn write_request(
         packet_type: PacketType::Eof,
         msg_no,
         packet_header: None,
-        body: vec![],
+        body: Bytes::new(),
     }
     .write(writer)
     .await
@@ -95,3 +169,51 @@ pub fn parse_all_packets(data: &[u8]) -> Vec<Packet> {
     }
     packets
 }
+
+/// Read from a raw peer until a packet of `packet_type` has arrived; returns everything so far.
+pub async fn read_until(reader: &mut (impl AsyncRead + Unpin), buf: &mut Vec<u8>, packet_type: PacketType) -> Vec<Packet> {
+    loop {
+        let packets = parse_all_packets(buf);
+        if packets.iter().any(|p| p.packet_type == packet_type) {
+            return packets;
+        }
+        let mut tmp = [0u8; 4096];
+        let n = reader.read(&mut tmp).await.unwrap();
+        assert!(n > 0, "peer closed before {:?}", packet_type);
+        buf.extend_from_slice(&tmp[..n]);
+    }
+}
+
+/// Generate a self-signed RSA 2048 certificate + private key (PKCS8 PEM).
+pub fn generate_test_keypair() -> (Vec<u8>, Vec<u8>) {
+    crate::testing::generate_keypair().unwrap()
+}
+
+/// A signed announcement for `identity` offering `actions` (`Namespace.method`
+/// at version 1) in the main sector on a loopback URI. Weight 0 announces
+/// the service as shutting down, with no actions.
+pub fn signed_announcement(keypair: &(Vec<u8>, Vec<u8>), identity: &str, weight: u32, actions: &[&str]) -> String {
+    use crate::service::announce::AnnouncementSource;
+    use crate::service::ActionInfo;
+
+    let source = AnnouncementSource {
+        identity: identity.to_string(),
+        sector: "main".to_string(),
+        envelopes: vec!["json".to_string()],
+        uri: "beepish+tls://127.0.0.1:30100".to_string(),
+        actions: actions
+            .iter()
+            .map(|name| ActionInfo {
+                name: name.to_string(),
+                version: 1,
+                flags: vec![],
+                zone: None,
+            })
+            .collect(),
+        key_pem: keypair.0.clone(),
+        cert_pem: keypair.1.clone(),
+        weight,
+        interval_secs: 5,
+    };
+    String::from_utf8(source.build_packet(weight > 0).unwrap()).unwrap()
+}
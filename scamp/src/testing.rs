@@ -0,0 +1,168 @@
+//! Hermetic multi-service integration tests.
+//!
+//! A [`TestBus`] runs [`ScampService`]s on loopback with freshly generated
+//! keys and puts their signed announcements straight into a shared
+//! [`ServiceRegistry`] — no multicast, no cache file, no sleeps. Requesters
+//! from [`TestBus::requester`] route through that registry, so services
+//! started on the bus can call each other.
+//!
+//! [`FaultPlan`] wraps a connection's stream to inject faults for
+//! protocol resilience tests.
+
+mod faults;
+mod faulty_stream;
+#[cfg(test)]
+mod resilience_tests;
+
+pub use faults::FaultPlan;
+pub use faulty_stream::FaultyStream;
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use tokio::sync::{watch, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::auth::authorized_services::AuthorizedServices;
+use crate::config::Config;
+use crate::discovery::packet::AnnouncementPacket;
+use crate::discovery::ServiceRegistry;
+use crate::requester::Requester;
+use crate::service::ScampService;
+
+/// Generate a self-signed RSA 2048 certificate + private key (PKCS8 PEM),
+/// returned as `(key_pem, cert_pem)`.
+pub fn generate_keypair() -> Result<(Vec<u8>, Vec<u8>)> {
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    let pkey = PKey::from_rsa(Rsa::generate(2048)?)?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "scamp-test")?;
+    let name = name.build();
+
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&pkey)?;
+    let (not_before, not_after) = (Asn1Time::days_from_now(0)?, Asn1Time::days_from_now(1)?);
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    builder.sign(&pkey, MessageDigest::sha256())?;
+
+    Ok((pkey.private_key_to_pem_pkcs8()?, builder.build().to_pem()?))
+}
+
+struct RunningService {
+    identity: String,
+    shutdown_tx: watch::Sender<bool>,
+    handle: JoinHandle<Result<()>>,
+}
+
+/// Services running in-process, discoverable through one shared registry.
+///
+/// Dropping the bus stops its services at once; [`TestBus::shutdown`] stops
+/// them gracefully, waiting for open connections to drain, so drop any
+/// requesters first.
+pub struct TestBus {
+    config: Config,
+    registry: Arc<RwLock<ServiceRegistry>>,
+    /// authorized_services lines for every service started so far.
+    authorized: String,
+    services: Vec<RunningService>,
+}
+
+impl Default for TestBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestBus {
+    pub fn new() -> Self {
+        TestBus {
+            config: Config::from_content("").expect("empty config parses"),
+            registry: Arc::new(RwLock::new(ServiceRegistry::empty())),
+            authorized: String::new(),
+            services: Vec::new(),
+        }
+    }
+
+    /// Bind `service` on 127.0.0.1 with a new key, announce it on the bus
+    /// and start serving. Returns the service's identity. The service is
+    /// authorized for every sector its actions are in.
+    pub async fn start(&mut self, mut service: ScampService) -> Result<String> {
+        let (key_pem, cert_pem) = generate_keypair()?;
+        service.bind_pem(&key_pem, &cert_pem, Ipv4Addr::LOCALHOST).await?;
+
+        let announcement = service.build_announcement_packet(true)?;
+        let packet = AnnouncementPacket::parse(std::str::from_utf8(&announcement)?)
+            .map_err(|e| anyhow!("Service built an unparseable announcement: {:?}", e))?;
+        let fingerprint = packet
+            .body
+            .info
+            .fingerprint
+            .clone()
+            .ok_or_else(|| anyhow!("Announcement has no certificate fingerprint"))?;
+        let sectors: BTreeSet<_> = packet.body.actions.iter().map(|a| format!("{}:ALL", a.sector)).collect();
+        self.authorized += &format!("{} {}\n", fingerprint, sectors.into_iter().collect::<Vec<_>>().join(", "));
+
+        let auth = AuthorizedServices::from_content(&self.authorized);
+        self.registry.write().await.inject_packet(packet, &auth);
+
+        let identity = service.identity().to_string();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let handle = tokio::spawn(service.run(shutdown_rx));
+        self.services.push(RunningService {
+            identity: identity.clone(),
+            shutdown_tx,
+            handle,
+        });
+        Ok(identity)
+    }
+
+    /// The registry every service on the bus is announced in.
+    pub fn registry(&self) -> Arc<RwLock<ServiceRegistry>> {
+        self.registry.clone()
+    }
+
+    /// A Requester that routes through the bus registry.
+    pub fn requester(&self) -> Requester {
+        Requester::from_shared_registry(&self.config, self.registry.clone())
+    }
+
+    /// Identities of the services started so far, in start order.
+    pub fn identities(&self) -> Vec<String> {
+        self.services.iter().map(|s| s.identity.clone()).collect()
+    }
+
+    /// Signal every service to stop and wait for them; returns the first error.
+    pub async fn shutdown(mut self) -> Result<()> {
+        let services = std::mem::take(&mut self.services);
+        for service in &services {
+            let _ = service.shutdown_tx.send(true);
+        }
+        let mut result = Ok(());
+        for service in services {
+            let outcome = service.handle.await.map_err(anyhow::Error::from).and_then(|r| r);
+            if let Err(e) = outcome {
+                log::warn!("Service {} failed: {}", service.identity, e);
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        for service in &self.services {
+            service.handle.abort();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! A stream wrapper that injects faults on a schedule fixed by a seed.
+//!
+//! Wrap either end of a connection with [`FaultPlan::wrap`]: bytes are
+//! delivered in randomly sized pieces, operations are randomly delayed, the
+//! connection can be cut after a byte count, and whole BEEPish frames can
+//! have their trailer corrupted or be swapped with the next frame. Every
+//! decision comes from a per-direction RNG seeded by the plan, so a failing
+//! seed replays the same faults.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+
+use super::FaultyStream;
+
+/// Which faults to inject, and the seed that schedules them.
+#[derive(Debug, Clone)]
+pub struct FaultPlan {
+    pub(super) seed: u64,
+    pub(super) max_read: Option<usize>,
+    pub(super) max_write: Option<usize>,
+    pub(super) delay: Option<(f64, Duration, Duration)>,
+    pub(super) drop_after_read: Option<usize>,
+    pub(super) drop_after_write: Option<usize>,
+    pub(super) corrupt_trailers: f64,
+    pub(super) reorder_frames: f64,
+}
+
+impl FaultPlan {
+    /// A plan that injects nothing until faults are added.
+    pub fn new(seed: u64) -> Self {
+        FaultPlan {
+            seed,
+            max_read: None,
+            max_write: None,
+            delay: None,
+            drop_after_read: None,
+            drop_after_write: None,
+            corrupt_trailers: 0.0,
+            reorder_frames: 0.0,
+        }
+    }
+
+    /// Faults that leave the byte stream intact: split reads and writes, short delays.
+    pub fn benign(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        FaultPlan::new(seed)
+            .split_reads(rng.gen_range(1..=256))
+            .split_writes(rng.gen_range(1..=256))
+            .delays(0.01, Duration::ZERO, Duration::from_millis(rng.gen_range(1..=3)))
+    }
+
+    /// Benign faults plus at least one destructive fault, chosen by the seed.
+    pub fn destructive(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(1));
+        let mut plan = FaultPlan::benign(seed);
+        match rng.gen_range(0..4) {
+            0 => plan = plan.drop_after_read(rng.gen_range(0..20_000)),
+            1 => plan = plan.drop_after_write(rng.gen_range(0..20_000)),
+            2 => plan = plan.corrupt_trailers(rng.gen_range(0.01..0.3)),
+            _ => plan = plan.reorder_frames(rng.gen_range(0.05..0.5)),
+        }
+        plan
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Hand reads to the caller in pieces of 1..=`max` bytes.
+    pub fn split_reads(mut self, max: usize) -> Self {
+        self.max_read = Some(max.max(1));
+        self
+    }
+
+    /// Pass writes on in pieces of 1..=`max` bytes.
+    pub fn split_writes(mut self, max: usize) -> Self {
+        self.max_write = Some(max.max(1));
+        self
+    }
+
+    /// Before each read or write, with this probability, wait between `min` and `max`.
+    pub fn delays(mut self, probability: f64, min: Duration, max: Duration) -> Self {
+        self.delay = Some((probability, min, max.max(min)));
+        self
+    }
+
+    /// Cut the connection once `bytes` have been read.
+    pub fn drop_after_read(mut self, bytes: usize) -> Self {
+        self.drop_after_read = Some(bytes);
+        self
+    }
+
+    /// Cut the connection once `bytes` have been written.
+    pub fn drop_after_write(mut self, bytes: usize) -> Self {
+        self.drop_after_write = Some(bytes);
+        self
+    }
+
+    /// Corrupt the `END\r\n` trailer of frames, in both directions, with this probability.
+    pub fn corrupt_trailers(mut self, probability: f64) -> Self {
+        self.corrupt_trailers = probability;
+        self
+    }
+
+    /// Swap frames with the one after them, in both directions, with this probability.
+    pub fn reorder_frames(mut self, probability: f64) -> Self {
+        self.reorder_frames = probability;
+        self
+    }
+
+    pub fn wrap<S>(&self, stream: S) -> FaultyStream<S> {
+        FaultyStream::new(self, stream)
+    }
+}
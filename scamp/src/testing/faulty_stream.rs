@@ -0,0 +1,266 @@
+//! The stream side of [`FaultPlan`]: applies its faults to reads and writes.
+
+use bytes::{Buf, BytesMut};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+use super::FaultPlan;
+use crate::transport::beepish::proto::{Packet, ParseResult};
+
+/// Write side stops accepting bytes past this much undelivered output.
+const MAX_BUFFERED: usize = 64 * 1024;
+
+/// One direction of a [`FaultyStream`]: frames bytes, mangles frames, meters output.
+struct Direction {
+    rng: StdRng,
+    max_chunk: Option<usize>,
+    drop_after: Option<usize>,
+    delay: Option<(f64, Duration, Duration)>,
+    corrupt: f64,
+    reorder: f64,
+    /// Bytes handed on so far.
+    passed: usize,
+    sleep: Option<Pin<Box<Sleep>>>,
+    /// Whether the current operation has had its delay.
+    delayed: bool,
+    /// Bytes not yet split into frames.
+    pending: BytesMut,
+    /// A frame waiting to be swapped with the next one.
+    held: Option<BytesMut>,
+    /// Bytes ready to hand on.
+    out: BytesMut,
+    /// No frame faults, or the stream stopped parsing: pass bytes through.
+    raw: bool,
+}
+
+impl Direction {
+    fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.delayed {
+            self.delayed = true;
+            if let Some((probability, min, max)) = self.delay {
+                if self.rng.gen_bool(probability) {
+                    self.sleep = Some(Box::pin(tokio::time::sleep(self.rng.gen_range(min..=max))));
+                }
+            }
+        }
+        if let Some(sleep) = &mut self.sleep {
+            ready!(sleep.as_mut().poll(cx));
+            self.sleep = None;
+        }
+        Poll::Ready(())
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        if self.raw {
+            self.out.extend_from_slice(data);
+            return;
+        }
+        self.pending.extend_from_slice(data);
+        loop {
+            let used = match Packet::parse(&self.pending) {
+                ParseResult::Success { bytes_used, .. } | ParseResult::Drop { bytes_used } => bytes_used,
+                ParseResult::TooShort | ParseResult::NeedBytes { .. } => return,
+                ParseResult::Fatal(_) => {
+                    // Not BEEPish (or already mangled): stop framing.
+                    self.raw = true;
+                    self.release_held();
+                    let rest = self.pending.split();
+                    self.out.extend_from_slice(&rest);
+                    return;
+                }
+            };
+            let frame = self.pending.split_to(used);
+            self.emit(frame);
+        }
+    }
+
+    fn emit(&mut self, mut frame: BytesMut) {
+        if self.corrupt > 0.0 && self.rng.gen_bool(self.corrupt) {
+            let at = frame.len() - 5;
+            frame[at..].copy_from_slice(b"ENX\r\n");
+        }
+        if let Some(held) = self.held.take() {
+            self.out.extend_from_slice(&frame);
+            self.out.extend_from_slice(&held);
+        } else if self.reorder > 0.0 && self.rng.gen_bool(self.reorder) {
+            self.held = Some(frame);
+        } else {
+            self.out.extend_from_slice(&frame);
+        }
+    }
+
+    fn release_held(&mut self) {
+        if let Some(held) = self.held.take() {
+            self.out.extend_from_slice(&held);
+        }
+    }
+
+    /// How many bytes of `out` the next hand-off may carry; 0 means the
+    /// drop point has been reached.
+    fn next_chunk(&mut self, room: usize) -> usize {
+        let mut n = self.out.len().min(room);
+        if let Some(max) = self.max_chunk {
+            n = n.min(self.rng.gen_range(1..=max));
+        }
+        if let Some(limit) = self.drop_after {
+            n = n.min(limit.saturating_sub(self.passed));
+        }
+        n
+    }
+
+    fn at_drop_point(&self) -> bool {
+        self.drop_after.is_some_and(|limit| self.passed >= limit)
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.out.advance(n);
+        self.passed += n;
+        self.delayed = false;
+    }
+}
+
+/// A stream with faults injected by a [`FaultPlan`].
+pub struct FaultyStream<S> {
+    inner: S,
+    read: Direction,
+    write: Direction,
+    /// The connection has been cut: reads see EOF, writes fail.
+    cut: bool,
+}
+
+impl<S> FaultyStream<S> {
+    pub(super) fn new(plan: &FaultPlan, stream: S) -> Self {
+        let direction = |seed: u64, max_chunk, drop_after| Direction {
+            rng: StdRng::seed_from_u64(seed),
+            max_chunk,
+            drop_after,
+            delay: plan.delay,
+            corrupt: plan.corrupt_trailers,
+            reorder: plan.reorder_frames,
+            passed: 0,
+            sleep: None,
+            delayed: false,
+            pending: BytesMut::new(),
+            held: None,
+            out: BytesMut::new(),
+            raw: plan.corrupt_trailers == 0.0 && plan.reorder_frames == 0.0,
+        };
+        FaultyStream {
+            inner: stream,
+            read: direction(plan.seed, plan.max_read, plan.drop_after_read),
+            write: direction(plan.seed ^ 0x5eed_5eed_5eed_5eed, plan.max_write, plan.drop_after_write),
+            cut: false,
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> FaultyStream<S> {
+    fn cut(&mut self, cx: &mut Context<'_>) {
+        if !self.cut {
+            self.cut = true;
+            // Best effort, so the peer sees the connection close too.
+            let _ = Pin::new(&mut self.inner).poll_shutdown(cx);
+        }
+    }
+
+    /// Write out buffered bytes until at most `keep` remain.
+    fn poll_drain(&mut self, cx: &mut Context<'_>, keep: usize) -> Poll<io::Result<()>> {
+        while self.write.out.len() > keep {
+            if self.cut {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+            }
+            let n = self.write.next_chunk(usize::MAX);
+            if n == 0 {
+                self.cut(cx);
+                continue;
+            }
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.write.out[..n]))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write.advance(written);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for FaultyStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if this.cut || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        ready!(this.read.poll_delay(cx));
+
+        while this.read.out.is_empty() {
+            let mut tmp = [0u8; 8192];
+            let mut tmp_buf = ReadBuf::new(&mut tmp);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut tmp_buf) {
+                Poll::Ready(Ok(())) if tmp_buf.filled().is_empty() => {
+                    this.read.release_held();
+                    if this.read.out.is_empty() {
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+                Poll::Ready(Ok(())) => this.read.push(tmp_buf.filled()),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending if this.read.held.is_some() => this.read.release_held(),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let n = this.read.next_chunk(buf.remaining());
+        if n == 0 {
+            this.cut(cx);
+            return Poll::Ready(Ok(()));
+        }
+        buf.put_slice(&this.read.out[..n]);
+        this.read.advance(n);
+        if this.read.at_drop_point() {
+            this.cut(cx);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for FaultyStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.cut {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        ready!(this.write.poll_delay(cx));
+        ready!(this.poll_drain(cx, MAX_BUFFERED))?;
+        this.write.push(data);
+        this.write.delayed = false;
+        // Hand on what we can now; the rest goes out on later writes or flush.
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx, 0) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.write.release_held();
+        ready!(this.poll_drain(cx, 0))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if this.cut {
+            return Poll::Ready(Ok(()));
+        }
+        this.write.release_held();
+        ready!(this.poll_drain(cx, 0))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
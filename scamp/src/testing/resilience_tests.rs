@@ -0,0 +1,185 @@
+//! Seeded fault-injection runs of the BEEPish client and server.
+//!
+//! Each test runs a fixed range of seeds; set `SCAMP_FAULT_SEED` to replay
+//! a single one. Failures name the seed and side that broke.
+
+use std::future::Future;
+use std::io::Cursor;
+use std::time::Duration;
+use tokio::task::{JoinHandle, JoinSet};
+
+use super::FaultPlan;
+use crate::service::server_connection::{handle_connection, DEFAULT_MAX_IN_FLIGHT};
+use crate::test_helpers::streaming_echo_actions;
+use crate::transport::beepish::proto::EnvelopeFormat;
+use crate::transport::beepish::ConnectionHandle;
+
+const SEEDS: u64 = 24;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Generous bound on one seed's run; hitting it means something hung.
+const RUN_DEADLINE: Duration = Duration::from_secs(60);
+
+fn seeds() -> Vec<u64> {
+    match std::env::var("SCAMP_FAULT_SEED") {
+        Ok(seed) => vec![seed.parse().expect("SCAMP_FAULT_SEED must be an integer")],
+        Err(_) => (0..SEEDS).collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Client,
+    Server,
+}
+
+/// A client connected to a server over a duplex, with `side` wrapped by `plan`.
+fn connect(side: Side, plan: &FaultPlan) -> (ConnectionHandle, JoinHandle<()>) {
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    let actions = streaming_echo_actions();
+    match side {
+        Side::Client => (
+            ConnectionHandle::from_stream(plan.wrap(client_stream)),
+            tokio::spawn(handle_connection(server_stream, actions, None, DEFAULT_MAX_IN_FLIGHT)),
+        ),
+        Side::Server => (
+            ConnectionHandle::from_stream(client_stream),
+            tokio::spawn(handle_connection(plan.wrap(server_stream), actions, None, DEFAULT_MAX_IN_FLIGHT)),
+        ),
+    }
+}
+
+fn body(seed: u64, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i as u64).wrapping_mul(31).wrapping_add(seed) as u8).collect()
+}
+
+/// Small and large buffered echoes plus a streamed echo several times the
+/// ACK watermark, all in flight at once. Returns (sent, reply) pairs.
+async fn exchange(conn: &ConnectionHandle, seed: u64) -> Vec<(Vec<u8>, anyhow::Result<Vec<u8>>)> {
+    let buffered = |len: usize| {
+        let sent = body(seed, len);
+        async move {
+            let reply = conn
+                .send_request("echo", 1, EnvelopeFormat::Json, "", 0, sent.clone(), REQUEST_TIMEOUT)
+                .await
+                .and_then(|resp| match resp.error.or(resp.header.error) {
+                    Some(err) => Err(anyhow::anyhow!(err)),
+                    None => Ok(resp.body),
+                });
+            (sent, reply)
+        }
+    };
+    let streamed = async {
+        let sent = body(seed, 150_000);
+        let reply = async {
+            let stream = conn
+                .send_request_streaming(
+                    "stream_echo",
+                    1,
+                    EnvelopeFormat::Json,
+                    "",
+                    0,
+                    Cursor::new(sent.clone()),
+                    REQUEST_TIMEOUT,
+                )
+                .await?;
+            tokio::time::timeout(REQUEST_TIMEOUT * 5, stream.read_to_end()).await?
+        }
+        .await;
+        (sent, reply)
+    };
+    let (a, b, c) = tokio::join!(buffered(10), buffered(70_000), streamed);
+    vec![a, b, c]
+}
+
+/// Run `scenario` for every seed and side concurrently, failing on any panic or hang.
+async fn for_each_seed<F, Fut>(scenario: F)
+where
+    F: Fn(u64, Side) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut runs = JoinSet::new();
+    for seed in seeds() {
+        for side in [Side::Client, Side::Server] {
+            let run = scenario(seed, side);
+            runs.spawn(async move {
+                let finished = tokio::time::timeout(RUN_DEADLINE, run).await;
+                assert!(finished.is_ok(), "seed {seed} ({side:?}): hung");
+            });
+        }
+    }
+    while let Some(result) = runs.join_next().await {
+        if let Err(e) = result {
+            std::panic::resume_unwind(e.into_panic());
+        }
+    }
+}
+
+/// The connection is dropped and the server must then wind down on its own.
+async fn close(conn: ConnectionHandle, server: JoinHandle<()>, seed: u64, side: Side) {
+    drop(conn);
+    match tokio::time::timeout(Duration::from_secs(5), server).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => panic!("seed {seed} ({side:?}): server panicked: {e}"),
+        Err(_) => panic!("seed {seed} ({side:?}): server did not exit after the client left"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_benign_faults_keep_replies_intact() {
+    for_each_seed(|seed, side| async move {
+        let (conn, server) = connect(side, &FaultPlan::benign(seed));
+        for (sent, reply) in exchange(&conn, seed).await {
+            let reply = reply.unwrap_or_else(|e| panic!("seed {seed} ({side:?}): {e}"));
+            assert!(reply == sent, "seed {seed} ({side:?}): reply differs from request");
+        }
+        close(conn, server, seed, side).await;
+    })
+    .await;
+}
+
+/// Corrupted or truncated streams must surface as errors, never as wrong data.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_corruption_and_drops_are_detected() {
+    for_each_seed(|seed, side| async move {
+        let plan = match seed % 3 {
+            0 => FaultPlan::benign(seed).corrupt_trailers(0.05),
+            1 => FaultPlan::benign(seed).drop_after_read((seed as usize * 7919) % 150_000),
+            _ => FaultPlan::benign(seed).drop_after_write((seed as usize * 7919) % 150_000),
+        };
+        let (conn, server) = connect(side, &plan);
+        for (sent, reply) in exchange(&conn, seed).await {
+            if let Ok(reply) = reply {
+                assert!(reply == sent, "seed {seed} ({side:?}): corrupted reply accepted");
+            }
+        }
+        close(conn, server, seed, side).await;
+    })
+    .await;
+}
+
+/// Any mix of faults, including reordered frames: every request resolves
+/// and both ends shut down cleanly.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_destructive_faults_never_hang() {
+    for_each_seed(|seed, side| async move {
+        let (conn, server) = connect(side, &FaultPlan::destructive(seed));
+        exchange(&conn, seed).await;
+        close(conn, server, seed, side).await;
+    })
+    .await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_slow_peer_times_out() {
+    for_each_seed(|seed, side| async move {
+        let plan = FaultPlan::new(seed).delays(1.0, Duration::from_millis(100), Duration::from_millis(150));
+        let (conn, server) = connect(side, &plan);
+        let result = conn
+            .send_request("echo", 1, EnvelopeFormat::Json, "", 0, body(seed, 100), Duration::from_millis(50))
+            .await;
+        let err = result.err().map(|e| e.to_string()).unwrap_or_default();
+        assert!(err.contains("timed out"), "seed {seed} ({side:?}): expected a timeout, got {err:?}");
+        close(conn, server, seed, side).await;
+    })
+    .await;
+}
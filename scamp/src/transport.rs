@@ -1,2 +1,54 @@
+//! Transports carry a request to a discovered service and bring back its reply.
+//!
+//! [`Requester`](crate::requester::Requester) is generic over [`Transport`],
+//! so code that routes through it can be tested against
+//! [`mock::MockClient`] instead of live TLS connections.
+
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::discovery::ActionEntry;
+use beepish::proto::EnvelopeFormat;
+use beepish::ScampResponse;
+
 pub mod beepish;
 pub mod mock;
+pub(crate) mod tls;
+
+/// Boxed future returned by [`Transport`] methods.
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// One request, addressed to the action it was routed to.
+#[derive(Debug, Clone)]
+pub struct TransportRequest<'a> {
+    pub action: &'a str,
+    pub version: i32,
+    pub envelope: EnvelopeFormat,
+    pub ticket: &'a str,
+    pub client_id: i64,
+    pub body: Vec<u8>,
+    pub timeout: Duration,
+}
+
+/// Sends requests to service instances picked from the registry.
+///
+/// An `Err` means the request could not be delivered; a reply that failed
+/// in flight comes back as `Ok` with [`ScampResponse::error`] set.
+pub trait Transport: Send + Sync {
+    fn request<'a>(&'a self, target: &'a ActionEntry, request: TransportRequest<'a>) -> TransportFuture<'a, ScampResponse>;
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn request<'a>(&'a self, target: &'a ActionEntry, request: TransportRequest<'a>) -> TransportFuture<'a, ScampResponse> {
+        (**self).request(target, request)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn request<'a>(&'a self, target: &'a ActionEntry, request: TransportRequest<'a>) -> TransportFuture<'a, ScampResponse> {
+        (**self).request(target, request)
+    }
+}
@@ -4,4 +4,7 @@
 mod client;
 pub mod proto;
 
-pub use client::{BeepishClient, ConnectionHandle, ScampResponse};
+pub use client::{
+    BeepishClient, ConnectionHandle, HeartbeatConfig, PoolConfig, PoolStats, PooledConnectionStats, ReplyStream, RequestHandle,
+    ScampResponse,
+};
@@ -0,0 +1,132 @@
+//! [`BeepishClient`]: requests to services over pooled connections.
+
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+
+use super::connection::{ConnectionHandle, ScampResponse};
+use super::heartbeat::HeartbeatConfig;
+use super::pool::{ConnectionPool, PoolConfig, PoolStats};
+use super::request::RequestHandle;
+use super::streaming::ReplyStream;
+use crate::config::Config;
+use crate::discovery::{ActionEntry, ServiceInfo};
+use crate::transport::beepish::proto::EnvelopeFormat;
+use crate::transport::{Transport, TransportFuture, TransportRequest};
+
+pub const DEFAULT_RPC_TIMEOUT_SECS: u64 = 75; // Perl ServiceInfo.pm:257
+
+pub struct BeepishClient {
+    config: Config,
+    pool: ConnectionPool,
+    heartbeat: Option<HeartbeatConfig>,
+}
+
+impl BeepishClient {
+    pub fn new(config: &Config) -> Self {
+        BeepishClient {
+            config: config.clone(),
+            pool: ConnectionPool::new(PoolConfig::from_config(config)),
+            heartbeat: HeartbeatConfig::from_config(config),
+        }
+    }
+
+    /// Heartbeat new connections (existing ones are unaffected); None disables.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<HeartbeatConfig>) {
+        self.heartbeat = heartbeat;
+    }
+
+    /// Replace the connection pool, closing every pooled connection.
+    pub fn set_pool_config(&mut self, config: PoolConfig) {
+        self.pool = ConnectionPool::new(config);
+    }
+
+    pub async fn pool_stats(&self) -> PoolStats {
+        self.pool.stats().await
+    }
+
+    pub async fn get_connection(&self, service_info: &ServiceInfo) -> Result<Arc<ConnectionHandle>> {
+        self.pool
+            .get_or_connect(&service_info.uri, || async {
+                let handle = ConnectionHandle::connect(&self.config, service_info, service_info.fingerprint.as_deref()).await?;
+                Ok(match self.heartbeat {
+                    Some(heartbeat) => handle.with_heartbeat(heartbeat),
+                    None => handle,
+                })
+            })
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn request(
+        &self,
+        service_info: &ServiceInfo,
+        action: &str,
+        version: i32,
+        envelope: EnvelopeFormat,
+        ticket: &str,
+        client_id: i64,
+        body: Vec<u8>,
+        timeout_secs: Option<u64>,
+    ) -> Result<ScampResponse> {
+        let conn = self.get_connection(service_info).await?;
+        let dur = Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_RPC_TIMEOUT_SECS));
+        conn.send_request(action, version, envelope, ticket, client_id, body, dur).await
+    }
+
+    /// Like [`BeepishClient::request`], but returns a [`RequestHandle`] that
+    /// can be cancelled.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn start_request(
+        &self,
+        service_info: &ServiceInfo,
+        action: &str,
+        version: i32,
+        envelope: EnvelopeFormat,
+        ticket: &str,
+        client_id: i64,
+        body: Vec<u8>,
+    ) -> Result<RequestHandle> {
+        let conn = self.get_connection(service_info).await?;
+        conn.start_request(action, version, envelope, ticket, client_id, body).await
+    }
+
+    /// Like [`BeepishClient::request`], but the body is read from `body` and
+    /// the reply is returned as a stream of DATA chunks.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn request_stream(
+        &self,
+        service_info: &ServiceInfo,
+        action: &str,
+        version: i32,
+        envelope: EnvelopeFormat,
+        ticket: &str,
+        client_id: i64,
+        body: impl AsyncRead + Unpin + Send + 'static,
+        timeout_secs: Option<u64>,
+    ) -> Result<ReplyStream> {
+        let conn = self.get_connection(service_info).await?;
+        let dur = Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_RPC_TIMEOUT_SECS));
+        conn.send_request_streaming(action, version, envelope, ticket, client_id, body, dur)
+            .await
+    }
+}
+
+impl Transport for BeepishClient {
+    fn request<'a>(&'a self, target: &'a ActionEntry, request: TransportRequest<'a>) -> TransportFuture<'a, ScampResponse> {
+        Box::pin(async move {
+            let conn = self.get_connection(&target.service_info).await?;
+            conn.send_request(
+                request.action,
+                request.version,
+                request.envelope,
+                request.ticket,
+                request.client_id,
+                request.body,
+                request.timeout,
+            )
+            .await
+        })
+    }
+}
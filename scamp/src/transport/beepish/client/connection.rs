@@ -1,23 +1,27 @@
-//! Connection pooling and request sending.
+//! A single client connection: reader/writer tasks and request sending.
 
 use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, oneshot, Mutex, Notify};
 use tokio::time::timeout;
-use tokio_native_tls::{native_tls, TlsConnector};
 
-use super::reader;
+use super::heartbeat::{Heartbeat, HeartbeatConfig};
+use super::reader::{self, PendingMap, PendingReply, ReaderShared};
+use super::request::{RequestHandle, Upload};
+use super::streaming::{self, ReplyStream};
+use super::upload::{MessageWriter, OutgoingMap};
+use super::writer;
 use crate::config::Config;
 use crate::discovery::ServiceInfo;
-use crate::transport::beepish::proto::{EnvelopeFormat, FlexInt, MessageType, Packet, PacketHeader, PacketType, DATA_CHUNK_SIZE};
+use crate::transport::beepish::proto::{EnvelopeFormat, FlexInt, MessageType, Packet, PacketHeader, PacketType};
+use crate::transport::tls;
 
-pub const DEFAULT_RPC_TIMEOUT_SECS: u64 = 75; // Perl ServiceInfo.pm:257
-const FLOW_CONTROL_WATERMARK: u64 = 65536; // JS connection.js:4
 #[derive(Debug)]
 pub struct ScampResponse {
     pub header: PacketHeader,
@@ -25,61 +29,21 @@ pub struct ScampResponse {
     pub error: Option<String>,
 }
 
-pub struct BeepishClient {
-    config: Config,
-    connections: Arc<Mutex<HashMap<String, Arc<ConnectionHandle>>>>,
-}
-
 pub struct ConnectionHandle {
-    writer_tx: mpsc::Sender<Packet>,
-    pending: Arc<Mutex<HashMap<i64, oneshot::Sender<ScampResponse>>>>,
-    outgoing: reader::OutgoingMap,
-    ack_notify: Arc<Notify>,
+    pub(super) sender: MessageWriter,
+    pub(super) pending: PendingMap,
     next_request_id: AtomicI64,
     next_outgoing_msg_no: AtomicU64,
-    closed: Arc<AtomicBool>,
-    proxy_handle: tokio::task::JoinHandle<()>,
+    pub(super) closed: Arc<AtomicBool>,
+    pub(super) pong_notify: Arc<Notify>,
+    pub(super) proxy_handle: tokio::task::JoinHandle<()>,
     reader_handle: tokio::task::JoinHandle<()>,
     writer_handle: tokio::task::JoinHandle<()>,
+    heartbeat: Option<Heartbeat>,
+    /// One extra strong reference per live `ReplyStream`.
+    reply_streams: Arc<()>,
 }
 
-impl BeepishClient {
-    pub fn new(config: &Config) -> Self {
-        BeepishClient {
-            config: config.clone(),
-            connections: Arc::new(Mutex::new(HashMap::new())),
-        }
-    }
-
-    pub async fn get_connection(&self, service_info: &ServiceInfo) -> Result<Arc<ConnectionHandle>> {
-        let mut connections = self.connections.lock().await;
-        if let Some(conn) = connections.get(&service_info.uri) {
-            if !conn.closed.load(Ordering::Relaxed) {
-                return Ok(conn.clone());
-            }
-            connections.remove(&service_info.uri);
-        }
-        let handle = Arc::new(ConnectionHandle::connect(&self.config, service_info, service_info.fingerprint.as_deref()).await?);
-        connections.insert(service_info.uri.clone(), handle.clone());
-        Ok(handle)
-    }
-
-    pub async fn request(
-        &self,
-        service_info: &ServiceInfo,
-        action: &str,
-        version: i32,
-        envelope: EnvelopeFormat,
-        ticket: &str,
-        client_id: i64,
-        body: Vec<u8>,
-        timeout_secs: Option<u64>,
-    ) -> Result<ScampResponse> {
-        let conn = self.get_connection(service_info).await?;
-        let dur = Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_RPC_TIMEOUT_SECS));
-        conn.send_request(action, version, envelope, ticket, client_id, body, dur).await
-    }
-}
 impl ConnectionHandle {
     /// Set up reader/writer tasks over any async stream.
     /// Proxies through a duplex to avoid split-lock contention on TLS streams.
@@ -91,41 +55,60 @@ impl ConnectionHandle {
         });
         let (read_half, write_half) = tokio::io::split(proxy_client);
         let (writer_tx, writer_rx) = mpsc::channel::<Packet>(256);
-        let pending: Arc<Mutex<HashMap<i64, oneshot::Sender<ScampResponse>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let pending: PendingMap = Arc::new(Mutex::new(HashMap::new()));
         let closed = Arc::new(AtomicBool::new(false));
-        let outgoing: reader::OutgoingMap = Arc::new(Mutex::new(HashMap::new()));
+        let outgoing: OutgoingMap = Arc::new(Mutex::new(HashMap::new()));
         let ack_notify = Arc::new(Notify::new());
+        let pong_notify = Arc::new(Notify::new());
+        let reply_streams = Arc::new(());
 
-        let writer_handle = tokio::spawn(writer_task(write_half, writer_rx));
-        let reader_pending = pending.clone();
-        let reader_writer_tx = writer_tx.clone();
+        let writer_handle = tokio::spawn(writer::writer_task(write_half, writer_rx));
+        let shared = ReaderShared {
+            pending: pending.clone(),
+            writer_tx: writer_tx.clone(),
+            outgoing: outgoing.clone(),
+            ack_notify: ack_notify.clone(),
+            pong_notify: pong_notify.clone(),
+            reply_streams: Arc::downgrade(&reply_streams),
+        };
         let reader_closed = closed.clone();
-        let reader_outgoing = outgoing.clone();
         let reader_ack_notify = ack_notify.clone();
         let reader_handle = tokio::spawn(async move {
-            reader::reader_task(read_half, reader_pending, reader_writer_tx, reader_outgoing, reader_ack_notify).await;
+            reader::reader_task(read_half, shared).await;
             // D12: set closed flag when reader exits
             reader_closed.store(true, Ordering::Relaxed);
+            // Wake senders paused on flow control so they see the closed flag.
+            reader_ack_notify.notify_waiters();
         });
 
         ConnectionHandle {
-            writer_tx,
+            sender: MessageWriter {
+                writer_tx,
+                outgoing,
+                ack_notify,
+                closed: closed.clone(),
+            },
             pending,
-            outgoing,
-            ack_notify,
             next_request_id: AtomicI64::new(1),      // Perl Client.pm:33
             next_outgoing_msg_no: AtomicU64::new(0), // All impls start at 0
             closed,
+            pong_notify,
             proxy_handle,
             reader_handle,
             writer_handle,
+            heartbeat: None,
+            reply_streams,
         }
     }
 
+    /// Send heartbeats on this connection, replacing any already running.
+    pub fn with_heartbeat(mut self, config: HeartbeatConfig) -> Self {
+        self.heartbeat = Some(Heartbeat::start(config, &self));
+        self
+    }
+
     /// Connect with TLS fingerprint verification (Perl Connection.pm:61-68).
-    async fn connect(_config: &Config, service_info: &ServiceInfo, expected_fingerprint: Option<&str>) -> Result<Self> {
-        let tls = native_tls::TlsConnector::builder().danger_accept_invalid_certs(true).build()?;
-        let connector = TlsConnector::from(tls);
+    pub(super) async fn connect(_config: &Config, service_info: &ServiceInfo, expected_fingerprint: Option<&str>) -> Result<Self> {
         let addr = service_info.socket_addr().map_err(|e| anyhow::anyhow!("Bad service URI: {}", e))?;
 
         let stream = timeout(Duration::from_secs(30), TcpStream::connect(addr))
@@ -134,46 +117,32 @@ impl ConnectionHandle {
             .context("Failed to connect")?;
         stream.set_nodelay(true)?;
 
-        let tls_stream = timeout(Duration::from_secs(30), connector.connect(&addr.ip().to_string(), stream))
+        // Fingerprint verification before any packets (natural corking)
+        let tls_stream = timeout(Duration::from_secs(30), tls::connect(stream, addr.ip(), expected_fingerprint))
             .await
             .context("TLS handshake timed out")?
             .context("TLS handshake failed")?;
 
-        // Fingerprint verification before any packets (natural corking)
-        if let Some(expected_fp) = expected_fingerprint {
-            let peer_cert = tls_stream
-                .get_ref()
-                .peer_certificate()
-                .context("Failed to get peer certificate")?
-                .ok_or_else(|| anyhow!("Peer did not present a certificate"))?;
-            let peer_der = peer_cert.to_der().context("Failed to get peer certificate DER")?;
-            let actual_fp = crate::crypto::cert_sha1_fingerprint(&peer_der);
-            if actual_fp != expected_fp {
-                return Err(anyhow!("CERTIFICATE MISMATCH! Announced {} got {}", expected_fp, actual_fp));
-            }
-            log::debug!("Certificate fingerprint verified: {}", actual_fp);
-        }
-
         Ok(Self::from_stream(tls_stream))
     }
 
-    pub async fn send_request(
+    /// Allocate a request id and msgno, register where the reply should go,
+    /// and send the HEADER. Returns `(request_id, msg_no)`.
+    pub(super) async fn begin_request(
         &self,
         action: &str,
         version: i32,
         envelope: EnvelopeFormat,
         ticket: &str,
         client_id: i64,
-        body: Vec<u8>,
-        timeout_duration: Duration,
-    ) -> Result<ScampResponse> {
+        reply_to: PendingReply,
+    ) -> Result<(i64, u64)> {
         if self.closed.load(Ordering::Relaxed) {
             return Err(anyhow!("Connection is closed"));
         }
         let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
         let msg_no = self.next_outgoing_msg_no.fetch_add(1, Ordering::Relaxed);
-        let (response_tx, response_rx) = oneshot::channel();
-        self.pending.lock().await.insert(request_id, response_tx);
+        self.pending.lock().await.insert(request_id, reply_to);
         let header = PacketHeader {
             action: action.to_string(),
             envelope,
@@ -187,94 +156,104 @@ impl ConnectionHandle {
             message_type: MessageType::Request,
             version,
         };
-        self.outgoing.lock().await.insert(msg_no, reader::OutgoingState::default());
-        // HEADER
-        if self
-            .writer_tx
+        self.sender.outgoing.lock().await.insert(msg_no, Default::default());
+        let sent = self
+            .sender
             .send(Packet {
                 packet_type: PacketType::Header,
                 msg_no,
                 packet_header: Some(header),
-                body: vec![],
+                body: Bytes::new(),
             })
-            .await
-            .is_err()
-        {
-            self.cleanup_request(request_id, msg_no).await;
-            return Err(anyhow!("Connection closed while sending header"));
-        }
-        // DATA chunks — track bytes sent for ACK validation
-        let mut offset = 0;
-        while offset < body.len() {
-            // D5b: Flow control — pause when sent-acked >= watermark (JS connection.js:298)
-            loop {
-                if self.closed.load(Ordering::Relaxed) {
-                    self.cleanup_request(request_id, msg_no).await;
-                    return Err(anyhow!("Connection closed during flow control wait"));
-                }
-                let over_watermark = {
-                    let out = self.outgoing.lock().await;
-                    out.get(&msg_no)
-                        .is_some_and(|s| s.sent.saturating_sub(s.acknowledged) >= FLOW_CONTROL_WATERMARK)
-                };
-                if !over_watermark {
-                    break;
-                }
-                self.ack_notify.notified().await;
-            }
-            let end = (offset + DATA_CHUNK_SIZE).min(body.len());
-            let chunk_len = (end - offset) as u64;
-            if self
-                .writer_tx
-                .send(Packet {
-                    packet_type: PacketType::Data,
-                    msg_no,
-                    packet_header: None,
-                    body: body[offset..end].to_vec(),
-                })
-                .await
-                .is_err()
-            {
-                self.cleanup_request(request_id, msg_no).await;
-                return Err(anyhow!("Connection closed while sending data"));
-            }
-            if let Some(s) = self.outgoing.lock().await.get_mut(&msg_no) {
-                s.sent += chunk_len;
-            }
-            offset = end;
-        }
-        // EOF (empty body — Perl Connection.pm:162)
-        if self
-            .writer_tx
-            .send(Packet {
-                packet_type: PacketType::Eof,
-                msg_no,
-                packet_header: None,
-                body: vec![],
-            })
-            .await
-            .is_err()
-        {
+            .await;
+        if let Err(e) = sent {
             self.cleanup_request(request_id, msg_no).await;
-            return Err(anyhow!("Connection closed while sending EOF"));
+            return Err(e);
         }
+        Ok((request_id, msg_no))
+    }
+
+    /// Send a request, returning as soon as its HEADER is queued. The body
+    /// is sent in the background; await [`RequestHandle::response`] for the
+    /// reply or call [`RequestHandle::cancel`] to abandon it.
+    pub async fn start_request(
+        &self,
+        action: &str,
+        version: i32,
+        envelope: EnvelopeFormat,
+        ticket: &str,
+        client_id: i64,
+        body: Vec<u8>,
+    ) -> Result<RequestHandle> {
+        let (response_tx, response_rx) = oneshot::channel();
+        let (request_id, msg_no) = self
+            .begin_request(action, version, envelope, ticket, client_id, PendingReply::Buffered(response_tx))
+            .await?;
         // Outgoing state kept alive until response arrives so ACK validation
         // works for the full request lifecycle (I1 from audit).
-        let result = match timeout(timeout_duration, response_rx).await {
-            Ok(Ok(response)) => Ok(response),
-            Ok(Err(_)) => Err(anyhow!("Connection lost while waiting for response")),
-            Err(_) => {
-                self.pending.lock().await.remove(&request_id);
-                Err(anyhow!("Request timed out after {:?}", timeout_duration))
-            }
-        };
-        self.outgoing.lock().await.remove(&msg_no);
-        result
+        let upload = Upload::start(self, request_id, msg_no, |sender| async move {
+            sender.send_data(msg_no, body.into()).await?;
+            sender.send_eof(msg_no).await
+        });
+        Ok(RequestHandle::new(request_id, response_rx, upload))
+    }
+
+    /// Send a request and wait for its reply. Dropping the returned future
+    /// cancels the request.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_request(
+        &self,
+        action: &str,
+        version: i32,
+        envelope: EnvelopeFormat,
+        ticket: &str,
+        client_id: i64,
+        body: Vec<u8>,
+        timeout_duration: Duration,
+    ) -> Result<ScampResponse> {
+        self.start_request(action, version, envelope, ticket, client_id, body)
+            .await?
+            .response(timeout_duration)
+            .await
+    }
+
+    /// Send a request whose body is read from `body`, returning the reply as a
+    /// [`ReplyStream`] as soon as its HEADER arrives.
+    ///
+    /// The body is sent from a background task, so a service may start
+    /// replying before it has read the whole request. The timeout runs from
+    /// when the body has been sent, as for [`ConnectionHandle::send_request`].
+    /// Dropping the returned future or timing out cancels the request.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_request_streaming(
+        &self,
+        action: &str,
+        version: i32,
+        envelope: EnvelopeFormat,
+        ticket: &str,
+        client_id: i64,
+        body: impl AsyncRead + Unpin + Send + 'static,
+        timeout_duration: Duration,
+    ) -> Result<ReplyStream> {
+        let (stream_tx, stream_rx) = oneshot::channel();
+        let (request_id, msg_no) = self
+            .begin_request(action, version, envelope, ticket, client_id, PendingReply::Streaming(stream_tx))
+            .await?;
+        let upload = Upload::start(self, request_id, msg_no, |sender| async move {
+            sender.send_from_reader(msg_no, body).await?;
+            sender.send_eof(msg_no).await
+        });
+        streaming::await_reply(stream_rx, upload, timeout_duration).await
+    }
+
+    /// Requests awaiting a reply plus replies still streaming.
+    pub async fn in_flight(&self) -> usize {
+        self.pending.lock().await.len() + Arc::strong_count(&self.reply_streams) - 1
     }
 
     async fn cleanup_request(&self, request_id: i64, msg_no: u64) {
         self.pending.lock().await.remove(&request_id);
-        self.outgoing.lock().await.remove(&msg_no);
+        self.sender.outgoing.lock().await.remove(&msg_no);
     }
 }
 
@@ -286,15 +265,3 @@ impl Drop for ConnectionHandle {
         self.writer_handle.abort();
     }
 }
-async fn writer_task(mut writer: impl AsyncWrite + Unpin, mut rx: mpsc::Receiver<Packet>) {
-    while let Some(packet) = rx.recv().await {
-        if let Err(e) = packet.write(&mut writer).await {
-            log::error!("Error writing packet: {}", e);
-            break;
-        }
-        if let Err(e) = writer.flush().await {
-            log::error!("Error flushing writer: {}", e);
-            break;
-        }
-    }
-}
@@ -1,4 +1,4 @@
-use crate::service::server_connection;
+use crate::service::server_connection::{self, DEFAULT_MAX_IN_FLIGHT};
 use crate::test_helpers::echo_actions;
 use crate::transport::beepish::proto::{EnvelopeFormat, MessageType};
 use std::time::Duration;
@@ -9,7 +9,12 @@ use super::connection::ConnectionHandle;
 async fn test_client_echo() {
     let (client_stream, server_stream) = tokio::io::duplex(65536);
     let actions = echo_actions();
-    let _server = tokio::spawn(server_connection::handle_connection(server_stream, actions, None));
+    let _server = tokio::spawn(server_connection::handle_connection(
+        server_stream,
+        actions,
+        None,
+        DEFAULT_MAX_IN_FLIGHT,
+    ));
 
     let conn = ConnectionHandle::from_stream(client_stream);
     let resp = conn
@@ -34,7 +39,12 @@ async fn test_client_echo() {
 #[tokio::test]
 async fn test_client_unknown_action_error() {
     let (client_stream, server_stream) = tokio::io::duplex(65536);
-    let _server = tokio::spawn(server_connection::handle_connection(server_stream, echo_actions(), None));
+    let _server = tokio::spawn(server_connection::handle_connection(
+        server_stream,
+        echo_actions(),
+        None,
+        DEFAULT_MAX_IN_FLIGHT,
+    ));
 
     let conn = ConnectionHandle::from_stream(client_stream);
     let resp = conn
@@ -57,7 +67,12 @@ async fn test_client_unknown_action_error() {
 #[tokio::test]
 async fn test_client_large_body() {
     let (client_stream, server_stream) = tokio::io::duplex(65536);
-    let _server = tokio::spawn(server_connection::handle_connection(server_stream, echo_actions(), None));
+    let _server = tokio::spawn(server_connection::handle_connection(
+        server_stream,
+        echo_actions(),
+        None,
+        DEFAULT_MAX_IN_FLIGHT,
+    ));
 
     let body = vec![0xABu8; 5000];
     let conn = ConnectionHandle::from_stream(client_stream);
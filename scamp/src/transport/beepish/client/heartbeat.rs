@@ -0,0 +1,119 @@
+//! Opt-in client heartbeats for dead connection detection.
+//!
+//! Services answer PING with PONG (Perl Connection.pm). Without heartbeats a
+//! half-open connection is only noticed when a request times out; with them
+//! the connection is closed as soon as a PONG is overdue, failing its pending
+//! requests so the pool reconnects on next use.
+
+use bytes::Bytes;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::{AbortHandle, JoinHandle};
+
+use super::connection::ConnectionHandle;
+use super::reader::{self, PendingMap};
+use crate::config::Config;
+use crate::transport::beepish::proto::{Packet, PacketType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    /// Time between PINGs.
+    pub interval: Duration,
+    /// How long to wait for each PONG before giving up on the connection.
+    pub timeout: Duration,
+}
+
+impl HeartbeatConfig {
+    /// PING every `interval`, allowing as long again for each PONG.
+    pub fn new(interval: Duration) -> Self {
+        HeartbeatConfig {
+            interval,
+            timeout: interval,
+        }
+    }
+
+    /// `beepish.heartbeat_interval` (seconds) enables heartbeats;
+    /// `beepish.heartbeat_timeout` (seconds) defaults to the interval.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let interval = config.get::<u64>("beepish.heartbeat_interval").and_then(|r| r.ok())?;
+        let mut heartbeat = Self::new(Duration::from_secs(interval));
+        if let Some(Ok(timeout)) = config.get::<u64>("beepish.heartbeat_timeout") {
+            heartbeat.timeout = Duration::from_secs(timeout);
+        }
+        Some(heartbeat)
+    }
+}
+
+/// A connection's heartbeat task; stops when dropped.
+pub(super) struct Heartbeat(JoinHandle<()>);
+
+impl Heartbeat {
+    pub fn start(config: HeartbeatConfig, conn: &ConnectionHandle) -> Self {
+        Heartbeat(tokio::spawn(heartbeat_task(
+            config,
+            conn.sender.writer_tx.clone(),
+            conn.pong_notify.clone(),
+            conn.closed.clone(),
+            conn.pending.clone(),
+            conn.proxy_handle.abort_handle(),
+        )))
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn heartbeat_task(
+    config: HeartbeatConfig,
+    writer_tx: mpsc::Sender<Packet>,
+    pong_notify: Arc<Notify>,
+    closed: Arc<AtomicBool>,
+    pending: PendingMap,
+    proxy: AbortHandle,
+) {
+    let mut ping_no: u64 = 0;
+    loop {
+        tokio::time::sleep(config.interval).await;
+        if closed.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Register for the PONG before sending the PING so a fast reply isn't missed.
+        let pong = pong_notify.notified();
+        tokio::pin!(pong);
+        pong.as_mut().enable();
+        let ping = Packet {
+            packet_type: PacketType::Ping,
+            msg_no: ping_no,
+            packet_header: None,
+            body: Bytes::new(),
+        };
+        ping_no += 1;
+
+        // A wedged writer counts against the timeout too.
+        let answered = tokio::time::timeout(config.timeout, async {
+            writer_tx.send(ping).await.is_ok() && {
+                pong.await;
+                true
+            }
+        })
+        .await;
+        match answered {
+            Ok(true) => {}
+            Ok(false) => return, // writer gone: the connection is already closing
+            Err(_) => {
+                log::warn!("No PONG within {:?}, closing connection", config.timeout);
+                closed.store(true, Ordering::Relaxed);
+                reader::fail_pending(&pending, &format!("Heartbeat timeout: no PONG within {:?}", config.timeout)).await;
+                // Tear down the transport; the reader then fails any replies mid-stream.
+                proxy.abort();
+                return;
+            }
+        }
+    }
+}
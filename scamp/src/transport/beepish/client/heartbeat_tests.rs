@@ -0,0 +1,62 @@
+use crate::service::server_connection::{self, DEFAULT_MAX_IN_FLIGHT};
+use crate::test_helpers::echo_actions;
+use crate::transport::beepish::proto::EnvelopeFormat;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use super::connection::ConnectionHandle;
+use super::heartbeat::HeartbeatConfig;
+
+const FAST: HeartbeatConfig = HeartbeatConfig {
+    interval: Duration::from_millis(50),
+    timeout: Duration::from_millis(100),
+};
+
+#[tokio::test]
+async fn test_heartbeat_keeps_live_connection_open() {
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    let _server = tokio::spawn(server_connection::handle_connection(
+        server_stream,
+        echo_actions(),
+        None,
+        DEFAULT_MAX_IN_FLIGHT,
+    ));
+
+    let conn = ConnectionHandle::from_stream(client_stream).with_heartbeat(FAST);
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(!conn.closed.load(Ordering::Relaxed));
+
+    let resp = conn
+        .send_request(
+            "echo",
+            1,
+            EnvelopeFormat::Json,
+            "",
+            0,
+            b"still here".to_vec(),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.body, b"still here");
+}
+
+#[tokio::test]
+async fn test_heartbeat_timeout_fails_pending_requests() {
+    // A peer that accepts bytes but never answers anything.
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    let (mut server_read, _server_write) = tokio::io::split(server_stream);
+    let _sink = tokio::spawn(async move { tokio::io::copy(&mut server_read, &mut tokio::io::sink()).await });
+
+    let conn = ConnectionHandle::from_stream(client_stream).with_heartbeat(FAST);
+    let started = Instant::now();
+    let resp = conn
+        .send_request("echo", 1, EnvelopeFormat::Json, "", 0, b"anyone?".to_vec(), Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    let err = resp.error.expect("request should have failed");
+    assert!(err.contains("Heartbeat timeout"), "got: {}", err);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(conn.closed.load(Ordering::Relaxed));
+}
@@ -1,8 +1,28 @@
 //! SCAMP BEEPish client: connection pooling, TLS, request/response.
 
+mod beepish_client;
 mod connection;
 #[cfg(test)]
 mod connection_tests;
+mod heartbeat;
+#[cfg(test)]
+mod heartbeat_tests;
+mod pool;
+#[cfg(test)]
+mod pool_tests;
 mod reader;
+mod request;
+#[cfg(test)]
+mod request_tests;
+mod streaming;
+#[cfg(test)]
+mod streaming_tests;
+mod upload;
+mod writer;
 
-pub use connection::{BeepishClient, ConnectionHandle, ScampResponse};
+pub use beepish_client::BeepishClient;
+pub use connection::{ConnectionHandle, ScampResponse};
+pub use heartbeat::HeartbeatConfig;
+pub use pool::{PoolConfig, PoolStats, PooledConnectionStats};
+pub use request::RequestHandle;
+pub use streaming::ReplyStream;
@@ -0,0 +1,239 @@
+//! Bounded pool of client connections, keyed by service URI (A7).
+//!
+//! Closed connections are dropped as soon as they are noticed, idle ones are
+//! closed by a background sweep once they pass `idle_timeout`, and when the
+//! pool is full the least recently used idle connection makes way.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use super::connection::ConnectionHandle;
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// Most connections held open at once.
+    pub max_connections: usize,
+    /// Close a connection after it has had nothing in flight for this long.
+    pub idle_timeout: Duration,
+    /// How often the background sweep looks for idle and closed connections.
+    pub sweep_interval: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_connections: 256,
+            idle_timeout: Duration::from_secs(300),
+            sweep_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Read `beepish.pool_max_connections`, `beepish.pool_idle_timeout` and
+    /// `beepish.pool_sweep_interval` (seconds), defaulting any that are unset.
+    pub fn from_config(config: &Config) -> Self {
+        let defaults = Self::default();
+        let secs = |key: &str, default: Duration| config.get::<u64>(key).and_then(|r| r.ok()).map_or(default, Duration::from_secs);
+        PoolConfig {
+            max_connections: config
+                .get::<usize>("beepish.pool_max_connections")
+                .and_then(|r| r.ok())
+                .unwrap_or(defaults.max_connections),
+            idle_timeout: secs("beepish.pool_idle_timeout", defaults.idle_timeout),
+            sweep_interval: secs("beepish.pool_sweep_interval", defaults.sweep_interval),
+        }
+    }
+}
+
+/// Snapshot of the pool, from [`BeepishClient::pool_stats`](super::BeepishClient::pool_stats).
+#[derive(Debug, Clone, Default)]
+pub struct PoolStats {
+    pub connections: Vec<PooledConnectionStats>,
+    /// Connections opened over the pool's lifetime.
+    pub opened: u64,
+    /// Connections closed by the sweep after `idle_timeout`.
+    pub evicted_idle: u64,
+    /// Idle connections closed to make room under `max_connections`.
+    pub evicted_for_capacity: u64,
+    /// Connections dropped after the peer, a heartbeat or an error closed them.
+    pub removed_closed: u64,
+}
+
+impl PoolStats {
+    /// Connections with nothing in flight.
+    pub fn idle(&self) -> usize {
+        self.connections.iter().filter(|c| c.in_flight == 0).count()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PooledConnectionStats {
+    pub uri: String,
+    /// Requests awaiting a reply plus replies still streaming.
+    pub in_flight: usize,
+    /// Time since the connection was last handed out or seen busy.
+    pub idle_for: Duration,
+}
+
+struct PoolEntry {
+    conn: Arc<ConnectionHandle>,
+    last_used: Instant,
+}
+
+impl PoolEntry {
+    /// Idle if nothing is in flight and no caller still holds the handle.
+    async fn is_idle(&self) -> bool {
+        Arc::strong_count(&self.conn) == 1 && self.conn.in_flight().await == 0
+    }
+}
+
+#[derive(Default)]
+struct PoolState {
+    entries: HashMap<String, PoolEntry>,
+    stats: PoolStats,
+}
+
+impl PoolState {
+    fn remove_closed(&mut self) {
+        let before = self.entries.len();
+        self.entries.retain(|_, e| !e.conn.closed.load(Ordering::Relaxed));
+        self.stats.removed_closed += (before - self.entries.len()) as u64;
+    }
+
+    /// Close connections idle for longer than `idle_timeout`. A connection
+    /// found busy has its idle clock restarted.
+    async fn sweep(&mut self, idle_timeout: Duration) {
+        self.remove_closed();
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        for (uri, entry) in self.entries.iter_mut() {
+            if !entry.is_idle().await {
+                entry.last_used = now;
+            } else if now.duration_since(entry.last_used) >= idle_timeout {
+                expired.push(uri.clone());
+            }
+        }
+        for uri in expired {
+            log::debug!("Closing idle connection to {}", uri);
+            self.entries.remove(&uri);
+            self.stats.evicted_idle += 1;
+        }
+    }
+
+    /// Evict the least recently used idle connection, if any.
+    async fn evict_lru_idle(&mut self) -> bool {
+        let mut lru: Option<(&String, Instant)> = None;
+        for (uri, entry) in &self.entries {
+            if entry.is_idle().await && lru.is_none_or(|(_, t)| entry.last_used < t) {
+                lru = Some((uri, entry.last_used));
+            }
+        }
+        let Some(uri) = lru.map(|(uri, _)| uri.clone()) else {
+            return false;
+        };
+        log::debug!("Pool full, closing idle connection to {}", uri);
+        self.entries.remove(&uri);
+        self.stats.evicted_for_capacity += 1;
+        true
+    }
+}
+
+pub(super) struct ConnectionPool {
+    config: PoolConfig,
+    state: Arc<Mutex<PoolState>>,
+    /// Started on first use, so the pool can be built outside a runtime.
+    sweeper: OnceLock<JoinHandle<()>>,
+}
+
+impl ConnectionPool {
+    pub fn new(config: PoolConfig) -> Self {
+        ConnectionPool {
+            config,
+            state: Arc::new(Mutex::new(PoolState::default())),
+            sweeper: OnceLock::new(),
+        }
+    }
+
+    /// The open connection for `uri`, or a new one from `connect`.
+    pub async fn get_or_connect<F, Fut>(&self, uri: &str, connect: F) -> Result<Arc<ConnectionHandle>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<ConnectionHandle>>,
+    {
+        self.sweeper
+            .get_or_init(|| tokio::spawn(sweep_task(Arc::downgrade(&self.state), self.config)));
+
+        let mut state = self.state.lock().await;
+        if let Some(entry) = state.entries.get_mut(uri) {
+            if !entry.conn.closed.load(Ordering::Relaxed) {
+                entry.last_used = Instant::now();
+                return Ok(entry.conn.clone());
+            }
+            state.entries.remove(uri);
+            state.stats.removed_closed += 1;
+        }
+        if state.entries.len() >= self.config.max_connections {
+            state.remove_closed();
+        }
+        if state.entries.len() >= self.config.max_connections && !state.evict_lru_idle().await {
+            return Err(anyhow!("Connection pool full: {} connections, none idle", state.entries.len()));
+        }
+
+        let conn = Arc::new(connect().await?);
+        state.entries.insert(
+            uri.to_string(),
+            PoolEntry {
+                conn: conn.clone(),
+                last_used: Instant::now(),
+            },
+        );
+        state.stats.opened += 1;
+        Ok(conn)
+    }
+
+    pub async fn stats(&self) -> PoolStats {
+        let state = self.state.lock().await;
+        let mut stats = state.stats.clone();
+        for (uri, entry) in &state.entries {
+            stats.connections.push(PooledConnectionStats {
+                uri: uri.clone(),
+                in_flight: entry.conn.in_flight().await,
+                idle_for: entry.last_used.elapsed(),
+            });
+        }
+        stats.connections.sort_by(|a, b| a.uri.cmp(&b.uri));
+        stats
+    }
+
+    /// Run one sweep now rather than waiting for the background task.
+    #[cfg(test)]
+    pub async fn sweep(&self) {
+        self.state.lock().await.sweep(self.config.idle_timeout).await;
+    }
+}
+
+impl Drop for ConnectionPool {
+    fn drop(&mut self) {
+        if let Some(sweeper) = self.sweeper.get() {
+            sweeper.abort();
+        }
+    }
+}
+
+async fn sweep_task(state: Weak<Mutex<PoolState>>, config: PoolConfig) {
+    loop {
+        tokio::time::sleep(config.sweep_interval).await;
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        state.lock().await.sweep(config.idle_timeout).await;
+    }
+}
@@ -0,0 +1,112 @@
+use crate::service::server_connection::{self, DEFAULT_MAX_IN_FLIGHT};
+use crate::test_helpers::echo_actions;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::connection::ConnectionHandle;
+use super::pool::{ConnectionPool, PoolConfig};
+
+fn pool(max_connections: usize, idle_timeout: Duration) -> ConnectionPool {
+    ConnectionPool::new(PoolConfig {
+        max_connections,
+        idle_timeout,
+        sweep_interval: Duration::from_secs(3600),
+    })
+}
+
+async fn echo_connection() -> anyhow::Result<ConnectionHandle> {
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    tokio::spawn(server_connection::handle_connection(
+        server_stream,
+        echo_actions(),
+        None,
+        DEFAULT_MAX_IN_FLIGHT,
+    ));
+    Ok(ConnectionHandle::from_stream(client_stream))
+}
+
+async fn get(pool: &ConnectionPool, uri: &str) -> anyhow::Result<Arc<ConnectionHandle>> {
+    pool.get_or_connect(uri, echo_connection).await
+}
+
+fn uris(stats: &super::PoolStats) -> Vec<&str> {
+    stats.connections.iter().map(|c| c.uri.as_str()).collect()
+}
+
+#[tokio::test]
+async fn test_pool_reuses_open_connection() {
+    let pool = pool(4, Duration::from_secs(60));
+    let first = get(&pool, "beepish+tls://a:1").await.unwrap();
+    let second = get(&pool, "beepish+tls://a:1").await.unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+
+    // Once closed, the next use reconnects.
+    first.closed.store(true, Ordering::Relaxed);
+    let third = get(&pool, "beepish+tls://a:1").await.unwrap();
+    assert!(!Arc::ptr_eq(&first, &third));
+    let stats = pool.stats().await;
+    assert_eq!(stats.opened, 2);
+    assert_eq!(stats.removed_closed, 1);
+}
+
+#[tokio::test]
+async fn test_full_pool_evicts_least_recently_used_idle() {
+    let pool = pool(2, Duration::from_secs(60));
+    drop(get(&pool, "a").await.unwrap());
+    drop(get(&pool, "b").await.unwrap());
+    drop(get(&pool, "a").await.unwrap()); // "b" is now least recently used
+    drop(get(&pool, "c").await.unwrap());
+
+    let stats = pool.stats().await;
+    assert_eq!(uris(&stats), ["a", "c"]);
+    assert_eq!(stats.evicted_for_capacity, 1);
+    assert_eq!(stats.idle(), 2);
+}
+
+#[tokio::test]
+async fn test_full_pool_of_busy_connections_refuses() {
+    let pool = pool(1, Duration::from_secs(60));
+    let busy = get(&pool, "a").await.unwrap();
+    let err = get(&pool, "b").await.err().expect("pool should be full");
+    assert!(err.to_string().contains("pool full"), "got: {}", err);
+
+    drop(busy);
+    get(&pool, "b").await.unwrap();
+    assert_eq!(uris(&pool.stats().await), ["b"]);
+}
+
+#[tokio::test]
+async fn test_sweep_closes_only_idle_connections() {
+    let pool = pool(4, Duration::from_millis(50));
+    drop(get(&pool, "idle").await.unwrap());
+    let busy = get(&pool, "busy").await.unwrap();
+    let dead = get(&pool, "dead").await.unwrap();
+    dead.closed.store(true, Ordering::Relaxed);
+    drop(dead);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    pool.sweep().await;
+
+    let stats = pool.stats().await;
+    assert_eq!(uris(&stats), ["busy"]);
+    assert_eq!(stats.evicted_idle, 1);
+    assert_eq!(stats.removed_closed, 1);
+    // A busy connection's idle clock restarts when the sweep sees it in use.
+    assert!(stats.connections[0].idle_for < Duration::from_millis(50));
+    drop(busy);
+}
+
+#[tokio::test]
+async fn test_background_sweep_runs() {
+    let pool = ConnectionPool::new(PoolConfig {
+        max_connections: 4,
+        idle_timeout: Duration::from_millis(20),
+        sweep_interval: Duration::from_millis(20),
+    });
+    drop(get(&pool, "a").await.unwrap());
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let stats = pool.stats().await;
+    assert!(stats.connections.is_empty());
+    assert_eq!(stats.evicted_idle, 1);
+}
@@ -2,116 +2,125 @@
 //!
 //! Implements inbound message assembly from HEADER → DATA* → EOF/TXERR.
 //! Matches Perl Connection.pm _packet() and JS connection.js _onpacket.
+//! Replies to streaming requests are forwarded chunk by chunk instead of
+//! being assembled.
 
+use bytes::Bytes;
+use futures_util::StreamExt;
 use log;
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use std::sync::{Arc, Weak};
+use tokio::io::AsyncRead;
 use tokio::sync::{mpsc, oneshot, Mutex, Notify};
+use tokio_util::codec::FramedRead;
 
+use super::streaming::{ChunkResult, ReplyStream};
+use super::upload::OutgoingMap;
 use super::ScampResponse;
-use crate::transport::beepish::proto::{Packet, PacketHeader, PacketType, ParseResult, MAX_PACKET_SIZE};
+use crate::transport::beepish::proto::{Packet, PacketCodec, PacketHeader, PacketType};
+
+/// Where the reply to an outstanding request should be delivered.
+pub(super) enum PendingReply {
+    /// Assembled, delivered at EOF.
+    Buffered(oneshot::Sender<ScampResponse>),
+    /// Delivered as a stream once the reply HEADER arrives.
+    Streaming(oneshot::Sender<Result<ReplyStream, String>>),
+}
+
+/// Outstanding requests keyed by request_id.
+pub(super) type PendingMap = Arc<Mutex<HashMap<i64, PendingReply>>>;
 
 /// In-progress incoming message being assembled from packets.
 struct IncomingMessage {
     header: PacketHeader,
-    body: Vec<u8>,
+    body: IncomingBody,
     received: usize,
 }
 
-/// Tracks bytes sent/acknowledged for an outgoing message (D5 flow control).
-/// Perl Connection.pm:177-183
-#[derive(Debug, Default)]
-pub(super) struct OutgoingState {
-    pub sent: u64,
-    pub acknowledged: u64,
+enum IncomingBody {
+    Buffered(Vec<u8>),
+    /// Forwarded to a `ReplyStream`; None once the caller has dropped it.
+    Streaming(Option<mpsc::UnboundedSender<ChunkResult>>),
 }
 
-/// Shared map of outgoing message states, keyed by msgno.
-pub(super) type OutgoingMap = Arc<Mutex<HashMap<u64, OutgoingState>>>;
+/// Connection state the reader task shares with the rest of the connection.
+pub(super) struct ReaderShared {
+    pub pending: PendingMap,
+    pub writer_tx: mpsc::Sender<Packet>,
+    pub outgoing: OutgoingMap,
+    /// Signalled whenever an ACK advances.
+    pub ack_notify: Arc<Notify>,
+    /// Signalled whenever a PONG arrives.
+    pub pong_notify: Arc<Notify>,
+    /// Upgraded into each `ReplyStream` so the connection can tell it is in use.
+    pub reply_streams: Weak<()>,
+}
 
 /// Read packets from the stream, assemble messages, deliver to pending map.
-pub(super) async fn reader_task(
-    reader: impl AsyncRead + Unpin,
-    pending: Arc<Mutex<HashMap<i64, oneshot::Sender<ScampResponse>>>>,
-    writer_tx: mpsc::Sender<Packet>,
-    outgoing: OutgoingMap,
-    ack_notify: Arc<Notify>,
-) {
-    let mut reader = reader;
-    let mut buf = Vec::with_capacity(8192);
+pub(super) async fn reader_task(reader: impl AsyncRead + Unpin, shared: ReaderShared) {
+    let mut frames = FramedRead::new(reader, PacketCodec);
     let mut incoming: HashMap<u64, IncomingMessage> = HashMap::new();
     let mut next_incoming_msg_no: u64 = 0; // Starts at 0 — all implementations agree
 
-    loop {
-        let mut tmp = [0u8; 4096];
-        let n = match reader.read(&mut tmp).await {
-            Ok(0) => break,
-            Ok(n) => n,
-            Err(e) => {
-                log::error!("Read error: {}", e);
+    while let Some(frame) = frames.next().await {
+        match frame {
+            Ok(packet) => route_packet(packet, &mut incoming, &mut next_incoming_msg_no, &shared).await,
+            Err(err) if err.is::<std::io::Error>() => {
+                log::error!("Read error: {}", err);
                 break;
             }
-        };
-        buf.extend_from_slice(&tmp[..n]);
-        if buf.len() > MAX_PACKET_SIZE + 100 {
-            log::error!("Read buffer exceeded max packet size, closing");
-            break;
-        }
-
-        let mut consumed = 0;
-        while consumed < buf.len() {
-            match Packet::parse(&buf[consumed..]) {
-                ParseResult::TooShort | ParseResult::NeedBytes { .. } => break,
-                ParseResult::Drop { bytes_used } => consumed += bytes_used,
-                ParseResult::Success { packet, bytes_used } => {
-                    consumed += bytes_used;
-                    route_packet(
-                        packet,
-                        &mut incoming,
-                        &mut next_incoming_msg_no,
-                        &pending,
-                        &writer_tx,
-                        &outgoing,
-                        &ack_notify,
-                    )
-                    .await;
-                }
-                ParseResult::Fatal(err) => {
-                    log::error!("Fatal protocol error: {}", err);
-                    notify_all_pending(&pending, &format!("Protocol error: {err}")).await;
-                    return;
-                }
+            Err(err) => {
+                log::error!("Fatal protocol error: {}", err);
+                notify_all_pending(&shared.pending, &mut incoming, &format!("Protocol error: {err}")).await;
+                return;
             }
         }
-        buf.drain(..consumed);
     }
 
     // Connection closed
-    notify_all_pending(&pending, "Connection lost").await;
+    notify_all_pending(&shared.pending, &mut incoming, "Connection lost").await;
+}
+
+/// Fail every outstanding request, including replies mid-stream — a stream
+/// that just ended would look like a complete reply.
+async fn notify_all_pending(pending: &PendingMap, incoming: &mut HashMap<u64, IncomingMessage>, error: &str) {
+    fail_pending(pending, error).await;
+    for (_, msg) in incoming.drain() {
+        if let IncomingBody::Streaming(Some(tx)) = msg.body {
+            let _ = tx.send(Err(error.to_string()));
+        }
+    }
 }
 
-async fn notify_all_pending(pending: &Arc<Mutex<HashMap<i64, oneshot::Sender<ScampResponse>>>>, error: &str) {
+/// Fail every request still waiting for its reply HEADER.
+pub(super) async fn fail_pending(pending: &PendingMap, error: &str) {
     let mut pend = pending.lock().await;
-    for (_, tx) in pend.drain() {
-        let _ = tx.send(ScampResponse {
-            header: PacketHeader::default(),
-            body: vec![],
-            error: Some(error.to_string()),
-        });
+    for (_, reply_to) in pend.drain() {
+        match reply_to {
+            PendingReply::Buffered(tx) => {
+                let _ = tx.send(ScampResponse {
+                    header: PacketHeader::default(),
+                    body: vec![],
+                    error: Some(error.to_string()),
+                });
+            }
+            PendingReply::Streaming(tx) => {
+                let _ = tx.send(Err(error.to_string()));
+            }
+        }
     }
 }
 
 /// Route a single packet: assemble HEADER → DATA* → EOF/TXERR.
-async fn route_packet(
-    packet: Packet,
-    incoming: &mut HashMap<u64, IncomingMessage>,
-    next_incoming_msg_no: &mut u64,
-    pending: &Arc<Mutex<HashMap<i64, oneshot::Sender<ScampResponse>>>>,
-    writer_tx: &mpsc::Sender<Packet>,
-    outgoing: &OutgoingMap,
-    ack_notify: &Arc<Notify>,
-) {
+async fn route_packet(packet: Packet, incoming: &mut HashMap<u64, IncomingMessage>, next_incoming_msg_no: &mut u64, shared: &ReaderShared) {
+    let ReaderShared {
+        pending,
+        writer_tx,
+        outgoing,
+        ack_notify,
+        pong_notify,
+        ..
+    } = shared;
     match packet.packet_type {
         PacketType::Header => {
             // Perl Connection.pm:140 — validate sequential msgno
@@ -121,14 +130,10 @@ async fn route_packet(
             }
             *next_incoming_msg_no += 1;
             if let Some(header) = packet.packet_header {
-                incoming.insert(
-                    packet.msg_no,
-                    IncomingMessage {
-                        header,
-                        body: Vec::new(),
-                        received: 0,
-                    },
-                );
+                let body = open_stream(shared, &header, packet.msg_no)
+                    .await
+                    .unwrap_or(IncomingBody::Buffered(Vec::new()));
+                incoming.insert(packet.msg_no, IncomingMessage { header, body, received: 0 });
             }
         }
         PacketType::Data => {
@@ -139,15 +144,27 @@ async fn route_packet(
             if packet.body.is_empty() {
                 return;
             } // JS connection.js:202
-            msg.body.extend_from_slice(&packet.body);
             msg.received += packet.body.len();
+            match &mut msg.body {
+                IncomingBody::Buffered(body) => body.extend_from_slice(&packet.body),
+                // The ReplyStream ACKs as the caller reads. Once it has been
+                // dropped, discard the rest and ACK here so the service isn't stalled.
+                IncomingBody::Streaming(stream) => {
+                    if let Some(tx) = stream {
+                        if tx.send(Ok(packet.body)).is_ok() {
+                            return;
+                        }
+                        *stream = None;
+                    }
+                }
+            }
 
             // ACK: cumulative bytes as decimal string (Perl Connection.pm:153)
             let ack = Packet {
                 packet_type: PacketType::Ack,
                 msg_no: packet.msg_no,
                 packet_header: None,
-                body: msg.received.to_string().into_bytes(),
+                body: msg.received.to_string().into(),
             };
             let _ = writer_tx.send(ack).await;
         }
@@ -161,12 +178,16 @@ async fn route_packet(
                 log::error!("EOF with no active message for msgno {}", packet.msg_no);
                 return;
             };
+            // Dropping a stream's sender ends the ReplyStream.
+            let IncomingBody::Buffered(body) = msg.body else {
+                return;
+            };
             let request_id = msg.header.request_id.0;
             let mut pend = pending.lock().await;
-            if let Some(tx) = pend.remove(&request_id) {
+            if let Some(PendingReply::Buffered(tx)) = pend.remove(&request_id) {
                 let _ = tx.send(ScampResponse {
                     header: msg.header,
-                    body: msg.body,
+                    body,
                     error: None,
                 });
             }
@@ -183,12 +204,21 @@ async fn route_packet(
                 return;
             };
             let error_text = body_str.to_string();
+            let body = match msg.body {
+                IncomingBody::Buffered(body) => body,
+                IncomingBody::Streaming(stream) => {
+                    if let Some(tx) = stream {
+                        let _ = tx.send(Err(error_text));
+                    }
+                    return;
+                }
+            };
             let request_id = msg.header.request_id.0;
             let mut pend = pending.lock().await;
-            if let Some(tx) = pend.remove(&request_id) {
+            if let Some(PendingReply::Buffered(tx)) = pend.remove(&request_id) {
                 let _ = tx.send(ScampResponse {
                     header: msg.header,
-                    body: msg.body,
+                    body,
                     error: Some(error_text),
                 });
             }
@@ -214,8 +244,8 @@ async fn route_packet(
                     return;
                 }
                 state.acknowledged = ack_val;
-                // D5b: Wake sender if it's blocked on flow control watermark
-                ack_notify.notify_one();
+                // D5b: Wake senders blocked on the flow control watermark
+                ack_notify.notify_waiters();
             }
         }
         PacketType::Ping => {
@@ -223,12 +253,32 @@ async fn route_packet(
                 packet_type: PacketType::Pong,
                 msg_no: packet.msg_no,
                 packet_header: None,
-                body: vec![],
+                body: Bytes::new(),
             };
             let _ = writer_tx.send(pong).await;
         }
         PacketType::Pong => {
-            // Heartbeat response — would reset timer (not implemented)
+            // Heartbeat response — see heartbeat.rs
+            pong_notify.notify_waiters();
+        }
+    }
+}
+
+/// If the request behind this reply asked for a stream, hand the caller its
+/// `ReplyStream` now and return where to forward the DATA.
+async fn open_stream(shared: &ReaderShared, header: &PacketHeader, msg_no: u64) -> Option<IncomingBody> {
+    let request_id = header.request_id.0;
+    let mut pend = shared.pending.lock().await;
+    match pend.remove(&request_id)? {
+        PendingReply::Streaming(tx) => {
+            let (chunk_tx, stream) = ReplyStream::new(header.clone(), msg_no, shared.writer_tx.clone(), shared.reply_streams.upgrade());
+            // If the caller has gone away, the body is discarded.
+            let chunk_tx = tx.send(Ok(stream)).ok().map(|()| chunk_tx);
+            Some(IncomingBody::Streaming(chunk_tx))
+        }
+        buffered => {
+            pend.insert(request_id, buffered);
+            None
         }
     }
 }
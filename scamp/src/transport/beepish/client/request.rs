@@ -0,0 +1,170 @@
+//! Requests as cancellable handles.
+//!
+//! An [`Upload`] owns a request's pending reply slot and outgoing
+//! flow-control state while its body is sent in the background. Abandoning
+//! the request — explicitly, by timing out, or by dropping it — releases
+//! both, and aborts the body with TXERR if it was still being sent. A reply
+//! arriving afterwards is discarded by the reader.
+
+use anyhow::{anyhow, Result};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use super::connection::{ConnectionHandle, ScampResponse};
+use super::reader::PendingMap;
+use super::upload::MessageWriter;
+
+/// A buffered request that has been sent, or is still sending its body.
+pub struct RequestHandle {
+    request_id: i64,
+    response_rx: oneshot::Receiver<ScampResponse>,
+    upload: Upload,
+}
+
+impl RequestHandle {
+    pub(super) fn new(request_id: i64, response_rx: oneshot::Receiver<ScampResponse>, upload: Upload) -> Self {
+        RequestHandle {
+            request_id,
+            response_rx,
+            upload,
+        }
+    }
+
+    pub fn request_id(&self) -> i64 {
+        self.request_id
+    }
+
+    /// Wait for the reply. The timeout runs from when the body has been sent.
+    pub async fn response(mut self, timeout_duration: Duration) -> Result<ScampResponse> {
+        self.upload.sent().await?;
+        let result = match timeout(timeout_duration, &mut self.response_rx).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(anyhow!("Connection lost while waiting for response")),
+            Err(_) => Err(anyhow!("Request timed out after {:?}", timeout_duration)),
+        };
+        self.upload.finish("timed out").await;
+        result
+    }
+
+    /// Abandon the request: forget its reply and stop sending its body.
+    pub async fn cancel(mut self) {
+        self.upload.finish("cancelled").await;
+    }
+}
+
+/// A request body being sent on a background task, and the request state
+/// to release if the request is abandoned.
+pub(super) struct Upload {
+    task: Option<JoinHandle<Result<()>>>,
+    /// Taken once the request completes; still present means cancel on drop.
+    cleanup: Option<Cleanup>,
+}
+
+struct Cleanup {
+    request_id: i64,
+    msg_no: u64,
+    pending: PendingMap,
+    sender: MessageWriter,
+    body_sent: Arc<AtomicBool>,
+}
+
+impl Cleanup {
+    async fn run(self, task: Option<JoinHandle<Result<()>>>, reason: &str) {
+        if let Some(task) = task {
+            task.abort();
+            let _ = task.await;
+        }
+        self.pending.lock().await.remove(&self.request_id);
+        self.sender.outgoing.lock().await.remove(&self.msg_no);
+        if !self.body_sent.load(Ordering::Relaxed) {
+            self.sender.send_txerr(self.msg_no, reason).await;
+        }
+    }
+}
+
+impl Upload {
+    /// Send the body of request `request_id` with `send`, which is given the
+    /// connection's writer.
+    pub fn start<F, Fut>(conn: &ConnectionHandle, request_id: i64, msg_no: u64, send: F) -> Self
+    where
+        F: FnOnce(MessageWriter) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let body_sent = Arc::new(AtomicBool::new(false));
+        let task_sent = body_sent.clone();
+        let sending = send(conn.sender.clone());
+        let task = tokio::spawn(async move {
+            sending.await?;
+            task_sent.store(true, Ordering::Relaxed);
+            Ok(())
+        });
+        Upload {
+            task: Some(task),
+            cleanup: Some(Cleanup {
+                request_id,
+                msg_no,
+                pending: conn.pending.clone(),
+                sender: conn.sender.clone(),
+                body_sent,
+            }),
+        }
+    }
+
+    /// Wait until the body has been sent. If sending fails the request is
+    /// abandoned, with the error as the TXERR text.
+    pub async fn sent(&mut self) -> Result<()> {
+        // Borrowed rather than taken, so dropping this future mid-upload
+        // still lets Drop abort the upload.
+        let Some(task) = self.task.as_mut() else {
+            return Ok(());
+        };
+        let sent = task.await.unwrap_or_else(|e| Err(anyhow!("Request body upload failed: {}", e)));
+        self.task = None;
+        if let Err(e) = &sent {
+            self.finish(&e.to_string()).await;
+        }
+        sent
+    }
+
+    /// Release the request's state, stopping the body with TXERR `reason`
+    /// if it is still being sent.
+    pub async fn finish(&mut self, reason: &str) {
+        if let Some(cleanup) = self.cleanup.take() {
+            cleanup.run(self.task.take(), reason).await;
+        }
+    }
+
+    /// Let the body finish sending on its own, for a request whose reply is
+    /// already streaming and no longer needs the pending slot.
+    pub fn detach(mut self) {
+        let (Some(cleanup), task) = (self.cleanup.take(), self.task.take()) else {
+            return;
+        };
+        tokio::spawn(async move {
+            if let Some(task) = task {
+                if let Ok(Err(e)) = task.await {
+                    log::warn!("Request {} body failed: {}", cleanup.request_id, e);
+                    cleanup.sender.send_txerr(cleanup.msg_no, &e.to_string()).await;
+                }
+            }
+            cleanup.sender.outgoing.lock().await.remove(&cleanup.msg_no);
+        });
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        let Some(cleanup) = self.cleanup.take() else {
+            return;
+        };
+        let task = self.task.take();
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(cleanup.run(task, "cancelled"));
+        }
+    }
+}
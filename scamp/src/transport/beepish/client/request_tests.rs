@@ -0,0 +1,110 @@
+use crate::test_helpers::read_until;
+use crate::transport::beepish::proto::{EnvelopeFormat, FlexInt, MessageType, Packet, PacketHeader, PacketType};
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use super::connection::ConnectionHandle;
+
+pub(super) async fn assert_released(conn: &ConnectionHandle) {
+    // Cleanup after a drop runs on a spawned task.
+    for _ in 0..50 {
+        if conn.in_flight().await == 0 && conn.sender.outgoing.lock().await.is_empty() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("request state was not released");
+}
+
+#[tokio::test]
+async fn test_cancel_mid_body_sends_txerr() {
+    // The peer never ACKs, so the body stalls at the flow-control watermark.
+    let (client_stream, server_stream) = tokio::io::duplex(1 << 20);
+    let (mut server_read, _server_write) = tokio::io::split(server_stream);
+    let conn = ConnectionHandle::from_stream(client_stream);
+
+    let request = conn
+        .start_request("upload", 1, EnvelopeFormat::Json, "", 0, vec![0u8; 200_000])
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(conn.in_flight().await, 1);
+    request.cancel().await;
+
+    assert_released(&conn).await;
+    let packets = read_until(&mut server_read, &mut Vec::new(), PacketType::Txerr).await;
+    let txerr = packets.iter().find(|p| p.packet_type == PacketType::Txerr).unwrap();
+    assert_eq!((txerr.msg_no, &txerr.body[..]), (0, &b"cancelled"[..]));
+    assert!(!packets.iter().any(|p| p.packet_type == PacketType::Eof));
+}
+
+#[tokio::test]
+async fn test_dropped_request_is_cleaned_up_and_late_reply_ignored() {
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    let (mut server_read, mut server_write) = tokio::io::split(server_stream);
+    let conn = ConnectionHandle::from_stream(client_stream);
+
+    // Abandon the request after its body has gone out.
+    let abandoned = tokio::time::timeout(
+        Duration::from_millis(100),
+        conn.send_request("echo", 1, EnvelopeFormat::Json, "", 0, b"{}".to_vec(), Duration::from_secs(30)),
+    )
+    .await;
+    assert!(abandoned.is_err());
+    assert_released(&conn).await;
+    let packets = read_until(&mut server_read, &mut Vec::new(), PacketType::Eof).await;
+    assert!(!packets.iter().any(|p| p.packet_type == PacketType::Txerr), "body was complete");
+
+    // The late reply for request 1 is discarded; request 2 still gets its own.
+    let reply = |request_id: i64, msg_no: u64| {
+        [
+            Packet {
+                packet_type: PacketType::Header,
+                msg_no,
+                packet_header: Some(PacketHeader {
+                    request_id: FlexInt(request_id),
+                    message_type: MessageType::Reply,
+                    ..PacketHeader::default()
+                }),
+                body: Bytes::new(),
+            },
+            Packet {
+                packet_type: PacketType::Eof,
+                msg_no,
+                packet_header: None,
+                body: Bytes::new(),
+            },
+        ]
+    };
+    for packet in reply(1, 0) {
+        packet.write(&mut server_write).await.unwrap();
+    }
+    server_write.flush().await.unwrap();
+
+    let second = conn
+        .start_request("echo", 1, EnvelopeFormat::Json, "", 0, b"{}".to_vec())
+        .await
+        .unwrap();
+    assert_eq!(second.request_id(), 2);
+    for packet in reply(2, 1) {
+        packet.write(&mut server_write).await.unwrap();
+    }
+    server_write.flush().await.unwrap();
+    let resp = second.response(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(resp.header.request_id.0, 2);
+    assert_released(&conn).await;
+}
+
+#[tokio::test]
+async fn test_timed_out_request_is_cleaned_up() {
+    let (client_stream, _server_stream) = tokio::io::duplex(65536);
+    let conn = ConnectionHandle::from_stream(client_stream);
+
+    let err = conn
+        .send_request("echo", 1, EnvelopeFormat::Json, "", 0, b"{}".to_vec(), Duration::from_millis(50))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("timed out"));
+    assert_released(&conn).await;
+}
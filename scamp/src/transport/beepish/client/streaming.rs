@@ -0,0 +1,158 @@
+//! Streaming replies, delivered as a [`Stream`] of DATA chunks. Requests are
+//! sent with [`ConnectionHandle::send_request_streaming`].
+//!
+//! Reply chunks are ACKed as the caller takes them off the stream rather than
+//! as they arrive, so a slow consumer holds the service at its flow-control
+//! watermark instead of the reply piling up in client memory.
+//!
+//! [`ConnectionHandle::send_request_streaming`]: super::ConnectionHandle::send_request_streaming
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+use tokio_util::sync::PollSender;
+
+use super::request::Upload;
+use crate::transport::beepish::proto::{Packet, PacketHeader, PacketType};
+
+/// A DATA payload, or the TXERR text that aborted the reply.
+pub(super) type ChunkResult = std::result::Result<Bytes, String>;
+
+/// A reply whose body is still arriving.
+pub struct ReplyStream {
+    header: PacketHeader,
+    chunks: mpsc::UnboundedReceiver<ChunkResult>,
+    msg_no: u64,
+    consumed: u64,
+    ack_pending: bool,
+    acks: PollSender<Packet>,
+    /// Marks the connection as in use while the stream is alive.
+    _in_use: Option<Arc<()>>,
+}
+
+impl ReplyStream {
+    /// Stream for reply `msg_no`, fed through the returned sender by the reader task.
+    pub(super) fn new(
+        header: PacketHeader,
+        msg_no: u64,
+        writer_tx: mpsc::Sender<Packet>,
+        in_use: Option<Arc<()>>,
+    ) -> (mpsc::UnboundedSender<ChunkResult>, Self) {
+        let (tx, chunks) = mpsc::unbounded_channel();
+        let stream = ReplyStream {
+            header,
+            chunks,
+            msg_no,
+            consumed: 0,
+            ack_pending: false,
+            acks: PollSender::new(writer_tx),
+            _in_use: in_use,
+        };
+        (tx, stream)
+    }
+
+    /// The reply HEADER; check `error` / `error_code` before reading the body.
+    pub fn header(&self) -> &PacketHeader {
+        &self.header
+    }
+
+    /// Next chunk of the body, or None after EOF. Errors if the service
+    /// aborted the reply with TXERR or the connection was lost.
+    pub async fn chunk(&mut self) -> Option<Result<Bytes>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Collect the rest of the body.
+    pub async fn read_to_end(mut self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.chunk().await {
+            body.extend_from_slice(&chunk?);
+        }
+        Ok(body)
+    }
+
+    /// Send the ACK for everything consumed so far once the writer has room.
+    fn poll_send_ack(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.ack_pending {
+            return Poll::Ready(());
+        }
+        if ready!(self.acks.poll_reserve(cx)).is_ok() {
+            // ACK: cumulative bytes as decimal string (Perl Connection.pm:153)
+            let _ = self.acks.send_item(Packet {
+                packet_type: PacketType::Ack,
+                msg_no: self.msg_no,
+                packet_header: None,
+                body: self.consumed.to_string().into(),
+            });
+        }
+        self.ack_pending = false;
+        Poll::Ready(())
+    }
+}
+
+impl Stream for ReplyStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        ready!(this.poll_send_ack(cx));
+        match ready!(this.chunks.poll_recv(cx)) {
+            Some(Ok(chunk)) => {
+                this.consumed += chunk.len() as u64;
+                this.ack_pending = true;
+                // Usually goes out immediately; otherwise on the next poll.
+                let _ = this.poll_send_ack(cx);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Some(Err(error)) => Poll::Ready(Some(Err(anyhow!(error)))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// Wait for the reply to a streaming request, which may arrive before the
+/// body has been sent. Once it does the body carries on sending; until then,
+/// a timeout or dropping this future abandons the request.
+pub(super) async fn await_reply(
+    mut reply_rx: oneshot::Receiver<std::result::Result<ReplyStream, String>>,
+    mut upload: Upload,
+    timeout_duration: Duration,
+) -> Result<ReplyStream> {
+    let early = tokio::select! {
+        reply = &mut reply_rx => Some(reply),
+        sent = upload.sent() => {
+            sent?;
+            None
+        }
+    };
+    let reply = match early {
+        Some(reply) => reply,
+        None => match timeout(timeout_duration, &mut reply_rx).await {
+            Ok(reply) => reply,
+            Err(_) => {
+                upload.finish("timed out").await;
+                return Err(anyhow!("Request timed out after {:?}", timeout_duration));
+            }
+        },
+    };
+    match reply {
+        Ok(Ok(stream)) => {
+            upload.detach();
+            Ok(stream)
+        }
+        Ok(Err(e)) => {
+            upload.finish(&e).await;
+            Err(anyhow!(e))
+        }
+        Err(_) => {
+            upload.finish("cancelled").await;
+            Err(anyhow!("Connection lost while waiting for response"))
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::service::server_connection::{self, DEFAULT_MAX_IN_FLIGHT};
+use crate::test_helpers::{read_until, streaming_echo_actions};
+use crate::transport::beepish::proto::{EnvelopeFormat, FlexInt, MessageType, Packet, PacketHeader, PacketType};
+use bytes::Bytes;
+use std::io::Cursor;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf};
+
+use super::connection::ConnectionHandle;
+use super::request_tests::assert_released;
+
+fn reply_packet(packet_type: PacketType, body: &[u8]) -> Packet {
+    let packet_header = (packet_type == PacketType::Header).then(|| PacketHeader {
+        request_id: FlexInt(1),
+        message_type: MessageType::Reply,
+        ..PacketHeader::default()
+    });
+    Packet {
+        packet_type,
+        msg_no: 0,
+        packet_header,
+        body: Bytes::copy_from_slice(body),
+    }
+}
+
+async fn send_stream(
+    conn: &ConnectionHandle,
+    action: &str,
+    body: impl AsyncRead + Unpin + Send + 'static,
+) -> anyhow::Result<super::ReplyStream> {
+    conn.send_request_streaming(action, 1, EnvelopeFormat::Json, "", 0, body, Duration::from_secs(5))
+        .await
+}
+
+#[tokio::test]
+async fn test_streaming_roundtrip_past_watermark() {
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    let _server = tokio::spawn(server_connection::handle_connection(
+        server_stream,
+        streaming_echo_actions(),
+        None,
+        DEFAULT_MAX_IN_FLIGHT,
+    ));
+
+    // Several times the 64K watermark in each direction.
+    let body: Vec<u8> = (0..300_000u32).map(|i| (i % 253) as u8).collect();
+    let conn = ConnectionHandle::from_stream(client_stream);
+    let stream = send_stream(&conn, "stream_echo", Cursor::new(body.clone())).await.unwrap();
+
+    assert!(stream.header().error.is_none());
+    assert_eq!(stream.header().message_type, MessageType::Reply);
+    assert_eq!(stream.read_to_end().await.unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_streaming_request_to_buffered_action() {
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    let _server = tokio::spawn(server_connection::handle_connection(
+        server_stream,
+        streaming_echo_actions(),
+        None,
+        DEFAULT_MAX_IN_FLIGHT,
+    ));
+
+    let conn = ConnectionHandle::from_stream(client_stream);
+    let stream = send_stream(&conn, "echo", Cursor::new(vec![0xCD; 5000])).await.unwrap();
+    assert_eq!(stream.read_to_end().await.unwrap(), vec![0xCD; 5000]);
+}
+
+#[tokio::test]
+async fn test_reply_stream_acks_as_consumed() {
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    let (mut server_read, mut server_write) = tokio::io::split(server_stream);
+    let conn = ConnectionHandle::from_stream(client_stream);
+    let request = tokio::spawn(async move { send_stream(&conn, "export", Cursor::new(vec![])).await.map(|s| (conn, s)) });
+
+    let mut buf = Vec::new();
+    read_until(&mut server_read, &mut buf, PacketType::Eof).await;
+    buf.clear();
+    for packet in [
+        reply_packet(PacketType::Header, b""),
+        reply_packet(PacketType::Data, b"first"),
+        reply_packet(PacketType::Data, b"second"),
+        reply_packet(PacketType::Eof, b""),
+    ] {
+        packet.write(&mut server_write).await.unwrap();
+    }
+    server_write.flush().await.unwrap();
+
+    let (_conn, mut stream) = request.await.unwrap().unwrap();
+    // Delivered but not yet read: no ACK.
+    let mut tmp = [0u8; 64];
+    let early = tokio::time::timeout(Duration::from_millis(200), server_read.read(&mut tmp)).await;
+    assert!(early.is_err(), "client ACKed before the stream was read");
+
+    assert_eq!(stream.chunk().await.unwrap().unwrap(), &b"first"[..]);
+    let packets = read_until(&mut server_read, &mut buf, PacketType::Ack).await;
+    assert_eq!(&packets[0].body[..], b"5");
+
+    assert_eq!(stream.chunk().await.unwrap().unwrap(), &b"second"[..]);
+    assert!(stream.chunk().await.is_none());
+}
+
+#[tokio::test]
+async fn test_reply_stream_errors_on_txerr_and_connection_loss() {
+    for (end, expected) in [
+        (Some(reply_packet(PacketType::Txerr, b"disk full")), "disk full"),
+        (None, "Connection lost"),
+    ] {
+        let (client_stream, server_stream) = tokio::io::duplex(65536);
+        let (mut server_read, mut server_write) = tokio::io::split(server_stream);
+        let conn = ConnectionHandle::from_stream(client_stream);
+        let request = tokio::spawn(async move { send_stream(&conn, "export", Cursor::new(vec![])).await.map(|s| (conn, s)) });
+
+        read_until(&mut server_read, &mut Vec::new(), PacketType::Eof).await;
+        reply_packet(PacketType::Header, b"").write(&mut server_write).await.unwrap();
+        reply_packet(PacketType::Data, b"partial").write(&mut server_write).await.unwrap();
+        server_write.flush().await.unwrap();
+        let (_conn, mut stream) = request.await.unwrap().unwrap();
+        assert_eq!(stream.chunk().await.unwrap().unwrap(), &b"partial"[..]);
+
+        match end {
+            Some(packet) => {
+                packet.write(&mut server_write).await.unwrap();
+                server_write.flush().await.unwrap();
+            }
+            None => drop((server_read, server_write)),
+        }
+        let err = stream.chunk().await.expect("stream ended cleanly").unwrap_err();
+        assert!(err.to_string().contains(expected), "got: {}", err);
+    }
+}
+
+/// Yields some bytes, then fails.
+struct FailingReader {
+    sent: bool,
+}
+
+impl AsyncRead for FailingReader {
+    fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        if self.sent {
+            return Poll::Ready(Err(std::io::Error::other("disk unplugged")));
+        }
+        self.sent = true;
+        buf.put_slice(b"some bytes");
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn test_request_body_read_error_sends_txerr() {
+    let (client_stream, server_stream) = tokio::io::duplex(65536);
+    let (mut server_read, _server_write) = tokio::io::split(server_stream);
+    let conn = ConnectionHandle::from_stream(client_stream);
+
+    let result = send_stream(&conn, "upload", FailingReader { sent: false }).await;
+    let err = result.err().expect("request should fail").to_string();
+    assert!(err.contains("disk unplugged"), "got: {}", err);
+
+    let packets = read_until(&mut server_read, &mut Vec::new(), PacketType::Txerr).await;
+    let txerr = packets.iter().find(|p| p.packet_type == PacketType::Txerr).unwrap();
+    assert!(String::from_utf8_lossy(&txerr.body).contains("disk unplugged"));
+    assert!(!packets.iter().any(|p| p.packet_type == PacketType::Eof));
+}
+
+#[tokio::test]
+async fn test_dropped_streaming_request_stops_upload() {
+    // The peer never ACKs, so the body stalls at the flow-control watermark.
+    let (client_stream, server_stream) = tokio::io::duplex(1 << 20);
+    let (mut server_read, _server_write) = tokio::io::split(server_stream);
+    let conn = ConnectionHandle::from_stream(client_stream);
+
+    let abandoned = tokio::time::timeout(
+        Duration::from_millis(100),
+        send_stream(&conn, "upload", Cursor::new(vec![0u8; 200_000])),
+    )
+    .await;
+    assert!(abandoned.is_err());
+
+    assert_released(&conn).await;
+    let packets = read_until(&mut server_read, &mut Vec::new(), PacketType::Txerr).await;
+    let txerr = packets.iter().find(|p| p.packet_type == PacketType::Txerr).unwrap();
+    assert_eq!(&txerr.body[..], b"cancelled");
+    assert!(!packets.iter().any(|p| p.packet_type == PacketType::Eof));
+}
+
+#[tokio::test]
+async fn test_timed_out_streaming_request_is_cleaned_up() {
+    let (client_stream, _server_stream) = tokio::io::duplex(65536);
+    let conn = ConnectionHandle::from_stream(client_stream);
+
+    let err = conn
+        .send_request_streaming(
+            "echo",
+            1,
+            EnvelopeFormat::Json,
+            "",
+            0,
+            Cursor::new(b"{}".to_vec()),
+            Duration::from_millis(50),
+        )
+        .await
+        .err()
+        .expect("request should time out");
+    assert!(err.to_string().contains("timed out"), "got: {}", err);
+    assert_released(&conn).await;
+}
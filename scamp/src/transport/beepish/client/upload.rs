@@ -0,0 +1,133 @@
+//! Outgoing message bodies: DATA chunking with ACK flow control.
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{mpsc, Mutex, Notify};
+
+use crate::transport::beepish::proto::{data_chunks, Packet, PacketType, DATA_CHUNK_SIZE, FLOW_CONTROL_WATERMARK};
+
+/// Tracks bytes sent/acknowledged for an outgoing message (D5 flow control).
+/// Perl Connection.pm:177-183
+#[derive(Debug, Default)]
+pub(super) struct OutgoingState {
+    pub sent: u64,
+    pub acknowledged: u64,
+}
+
+/// Shared map of outgoing message states, keyed by msgno.
+pub(super) type OutgoingMap = Arc<Mutex<HashMap<u64, OutgoingState>>>;
+
+/// Everything needed to write a message onto a connection, cheap to clone
+/// into a background upload task.
+#[derive(Clone)]
+pub(super) struct MessageWriter {
+    pub writer_tx: mpsc::Sender<Packet>,
+    pub outgoing: OutgoingMap,
+    /// Signalled by the reader whenever an ACK advances.
+    pub ack_notify: Arc<Notify>,
+    pub closed: Arc<AtomicBool>,
+}
+
+impl MessageWriter {
+    pub async fn send(&self, packet: Packet) -> Result<()> {
+        let packet_type = packet.packet_type;
+        self.writer_tx
+            .send(packet)
+            .await
+            .map_err(|_| anyhow!("Connection closed while sending {:?}", packet_type))
+    }
+
+    /// Send `body` as DATA packets.
+    pub async fn send_data(&self, msg_no: u64, body: Bytes) -> Result<()> {
+        for chunk in data_chunks(body) {
+            self.send_chunk(msg_no, chunk).await?;
+        }
+        Ok(())
+    }
+
+    /// Send DATA packets read from `body` until it is exhausted.
+    pub async fn send_from_reader(&self, msg_no: u64, mut body: impl AsyncRead + Unpin) -> Result<()> {
+        let mut buf = BytesMut::new();
+        loop {
+            buf.reserve(DATA_CHUNK_SIZE);
+            let n = (&mut body)
+                .take(DATA_CHUNK_SIZE as u64)
+                .read_buf(&mut buf)
+                .await
+                .map_err(|e| anyhow!("Failed to read request body: {}", e))?;
+            if n == 0 {
+                return Ok(());
+            }
+            self.send_chunk(msg_no, buf.split().freeze()).await?;
+        }
+    }
+
+    /// EOF (empty body — Perl Connection.pm:162)
+    pub async fn send_eof(&self, msg_no: u64) -> Result<()> {
+        self.send(Packet {
+            packet_type: PacketType::Eof,
+            msg_no,
+            packet_header: None,
+            body: Bytes::new(),
+        })
+        .await
+    }
+
+    /// Abort a message the peer has only partly received.
+    pub async fn send_txerr(&self, msg_no: u64, error: &str) {
+        // JS connection.js:229 — an empty TXERR body is invalid
+        let error = if error.is_empty() { "aborted" } else { error };
+        let _ = self
+            .send(Packet {
+                packet_type: PacketType::Txerr,
+                msg_no,
+                packet_header: None,
+                body: Bytes::copy_from_slice(error.as_bytes()),
+            })
+            .await;
+    }
+
+    async fn send_chunk(&self, msg_no: u64, chunk: Bytes) -> Result<()> {
+        self.wait_for_window(msg_no).await?;
+        let chunk_len = chunk.len() as u64;
+        self.send(Packet {
+            packet_type: PacketType::Data,
+            msg_no,
+            packet_header: None,
+            body: chunk,
+        })
+        .await?;
+        if let Some(s) = self.outgoing.lock().await.get_mut(&msg_no) {
+            s.sent += chunk_len;
+        }
+        Ok(())
+    }
+
+    /// D5b: Flow control — pause when sent-acked >= watermark (JS connection.js:298)
+    async fn wait_for_window(&self, msg_no: u64) -> Result<()> {
+        loop {
+            // Register for the wakeup before checking, so an ACK landing in
+            // between isn't missed.
+            let notified = self.ack_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.closed.load(Ordering::Relaxed) {
+                return Err(anyhow!("Connection closed during flow control wait"));
+            }
+            let over_watermark = {
+                let out = self.outgoing.lock().await;
+                out.get(&msg_no)
+                    .is_some_and(|s| s.sent.saturating_sub(s.acknowledged) >= FLOW_CONTROL_WATERMARK)
+            };
+            if !over_watermark {
+                return Ok(());
+            }
+            notified.await;
+        }
+    }
+}
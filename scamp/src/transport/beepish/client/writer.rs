@@ -0,0 +1,27 @@
+//! Client-side writer task: serializes packets from the channel onto the stream.
+
+use futures_util::SinkExt;
+use tokio::io::AsyncWrite;
+use tokio::sync::mpsc;
+use tokio_util::codec::FramedWrite;
+
+use crate::transport::beepish::proto::{Packet, PacketCodec};
+
+pub(super) async fn writer_task(writer: impl AsyncWrite + Unpin, mut rx: mpsc::Receiver<Packet>) {
+    let mut frames = FramedWrite::new(writer, PacketCodec);
+    while let Some(packet) = rx.recv().await {
+        // Encode everything already queued, then flush once.
+        let mut result = frames.feed(packet).await;
+        while let (Ok(()), Ok(packet)) = (&result, rx.try_recv()) {
+            result = frames.feed(packet).await;
+        }
+        if let Err(e) = result {
+            log::error!("Error writing packet: {}", e);
+            break;
+        }
+        if let Err(e) = SinkExt::<Packet>::flush(&mut frames).await {
+            log::error!("Error flushing writer: {}", e);
+            break;
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! `tokio_util::codec` framing for BEEPish packets, shared by the client and
+//! server read loops.
+//!
+//! Decoding validates exactly as [`Packet::parse`] does, then splits the
+//! payload out of the read buffer instead of copying it. Encoding writes
+//! header line, payload and trailer straight into the output buffer.
+
+use bytes::{Buf, BufMut, BytesMut};
+use std::fmt::Write;
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::packet::{scan_frame, Scan};
+use super::Packet;
+
+/// Decodes [`Packet`]s from, and encodes them to, a byte stream.
+/// Every decode error is a fatal protocol error: close the connection.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PacketCodec;
+
+impl Decoder for PacketCodec {
+    type Item = Packet;
+    type Error = anyhow::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> anyhow::Result<Option<Packet>> {
+        let frame = match scan_frame(src)? {
+            Scan::Complete(frame) => frame,
+            Scan::TooShort => return Ok(None),
+            Scan::NeedBytes(more) => {
+                src.reserve(more);
+                return Ok(None);
+            }
+        };
+        src.advance(frame.payload.start);
+        let payload = src.split_to(frame.payload.len()).freeze();
+        src.advance(5); // END\r\n
+        Packet::from_payload(frame.packet_type, frame.msg_no, payload).map(Some)
+    }
+}
+
+impl Encoder<&Packet> for PacketCodec {
+    type Error = anyhow::Error;
+
+    fn encode(&mut self, packet: &Packet, dst: &mut BytesMut) -> anyhow::Result<()> {
+        let json;
+        let body: &[u8] = match &packet.packet_header {
+            Some(header) => {
+                json = serde_json::to_vec(header)?;
+                &json
+            }
+            None => &packet.body,
+        };
+        dst.reserve(32 + body.len());
+        write!(dst, "{} {} {}\r\n", packet.packet_type.wire_name(), packet.msg_no, body.len())?;
+        dst.put_slice(body);
+        dst.put_slice(b"END\r\n");
+        Ok(())
+    }
+}
+
+impl Encoder<Packet> for PacketCodec {
+    type Error = anyhow::Error;
+
+    fn encode(&mut self, packet: Packet, dst: &mut BytesMut) -> anyhow::Result<()> {
+        self.encode(&packet, dst)
+    }
+}
@@ -0,0 +1,87 @@
+use bytes::{Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::*;
+
+fn data(msg_no: u64, body: &'static [u8]) -> Packet {
+    Packet {
+        packet_type: PacketType::Data,
+        msg_no,
+        packet_header: None,
+        body: Bytes::from_static(body),
+    }
+}
+
+fn encode(packet: &Packet) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    PacketCodec.encode(packet, &mut buf).unwrap();
+    buf.to_vec()
+}
+
+#[test]
+fn test_encode_matches_wire_format() {
+    assert_eq!(encode(&data(3, b"hello world")), b"DATA 3 11\r\nhello worldEND\r\n");
+}
+
+#[test]
+fn test_decode_across_partial_reads() {
+    let wire = encode(&data(7, b"split me"));
+    let mut buf = BytesMut::new();
+    for byte in &wire[..wire.len() - 1] {
+        buf.extend_from_slice(&[*byte]);
+        assert!(PacketCodec.decode(&mut buf).unwrap().is_none());
+    }
+    buf.extend_from_slice(&wire[wire.len() - 1..]);
+    let packet = PacketCodec.decode(&mut buf).unwrap().expect("complete packet");
+    assert_eq!((packet.msg_no, &packet.body[..]), (7, &b"split me"[..]));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_decode_consumes_one_packet_at_a_time() {
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(&encode(&data(1, b"first")));
+    buf.extend_from_slice(&encode(&data(2, b"second")));
+
+    let first = PacketCodec.decode(&mut buf).unwrap().unwrap();
+    let second = PacketCodec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(&first.body[..], b"first");
+    assert_eq!(&second.body[..], b"second");
+    assert!(PacketCodec.decode(&mut buf).unwrap().is_none());
+}
+
+#[test]
+fn test_header_roundtrip() {
+    let header: PacketHeader =
+        serde_json::from_str(r#"{"action":"foo.bar","envelope":"json","request_id":4,"type":"request","version":1}"#).unwrap();
+    let packet = Packet {
+        packet_type: PacketType::Header,
+        msg_no: 0,
+        packet_header: Some(header),
+        body: Bytes::new(),
+    };
+    let mut buf = BytesMut::from(&encode(&packet)[..]);
+    let decoded = PacketCodec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(decoded.packet_header.unwrap().action, "foo.bar");
+}
+
+/// The codec must refuse exactly what `Packet::parse` refuses.
+#[test]
+fn test_decode_errors_match_parse() {
+    let cases: &[&[u8]] = &[
+        b"DATA 0 3\nabcEND\r\n",
+        b"DATA 0 3\r\nabcEND\n\n",
+        b"BOGUS 0 0\r\nEND\r\n",
+        b"DATA 0 999999999\r\n",
+        b"DATA zero 0\r\nEND\r\n",
+        b"HEADER 0 2\r\n{]END\r\n",
+        &[b'X'; 100],
+    ];
+    for case in cases {
+        let ParseResult::Fatal(expected) = Packet::parse(case) else {
+            panic!("parse accepted {:?}", String::from_utf8_lossy(case));
+        };
+        let err = PacketCodec.decode(&mut BytesMut::from(*case)).err().expect("codec accepted");
+        assert_eq!(err.to_string(), expected.to_string());
+    }
+}
@@ -44,7 +44,7 @@ fn test_perl_data_packet() {
     match Packet::parse(&buf) {
         ParseResult::Success { packet, .. } => {
             assert_eq!(packet.packet_type, PacketType::Data);
-            assert_eq!(packet.body, b"{}");
+            assert_eq!(&packet.body[..], b"{}");
         }
         _ => panic!("Failed to parse Perl DATA fixture"),
     }
@@ -165,5 +165,29 @@ fn test_perl_reply_sequence() {
     assert_eq!(packets[1].packet_type, PacketType::Data);
     assert_eq!(packets[2].packet_type, PacketType::Eof);
     assert!(packets.iter().all(|p| p.msg_no == 1));
-    assert_eq!(packets[1].body, b"{\"status\":\"ok\"}");
+    assert_eq!(&packets[1].body[..], b"{\"status\":\"ok\"}");
+}
+
+/// Regenerate the `packet_parse` fuzz seeds from these fixtures.
+/// Run with: cargo test -p scamp -- --ignored write_fuzz_seed_corpus
+#[test]
+#[ignore] // writes into fuzz/corpus
+fn write_fuzz_seed_corpus() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/packet_parse");
+    std::fs::create_dir_all(&dir).unwrap();
+    let seeds = [
+        ("request_header", fixtures::perl_request_header()),
+        ("request_header_with_nulls", fixtures::perl_request_header_with_nulls()),
+        ("reply_ok", fixtures::perl_reply_ok()),
+        ("reply_error", fixtures::perl_reply_error()),
+        ("data_empty_json", fixtures::perl_data_empty_json()),
+        ("eof", fixtures::perl_eof()),
+        ("ack", fixtures::perl_ack(2)),
+        ("txerr", fixtures::perl_txerr()),
+        ("request_sequence", fixtures::perl_request_sequence()),
+        ("reply_sequence", fixtures::perl_reply_sequence(b"{\"ok\":1}")),
+    ];
+    for (name, bytes) in seeds {
+        std::fs::write(dir.join(format!("seed-{name}")), bytes).unwrap();
+    }
 }
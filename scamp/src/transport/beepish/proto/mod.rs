@@ -1,5 +1,8 @@
 //! SCAMP BEEPish wire protocol types: packet framing, header JSON, serde.
 
+mod codec;
+#[cfg(test)]
+mod codec_tests;
 #[cfg(test)]
 mod fixture_tests;
 #[cfg(test)]
@@ -7,17 +10,33 @@ pub(crate) mod fixtures;
 mod header;
 mod packet;
 #[cfg(test)]
+mod property_tests;
+#[cfg(test)]
 mod tests;
 
+pub use codec::PacketCodec;
 pub use header::{EnvelopeFormat, FlexInt, MessageType, PacketHeader};
 pub use packet::{Packet, ParseResult};
 
+use bytes::Bytes;
+
 pub const MAX_PACKET_SIZE: usize = 131072;
 
 /// Maximum DATA chunk size when sending.
 /// Perl Connection.pm:218 uses 2048. All receivers handle up to MAX_PACKET_SIZE.
 pub const DATA_CHUNK_SIZE: usize = 2048;
 
+/// Split a body into DATA_CHUNK_SIZE payloads sharing its buffer.
+pub fn data_chunks(body: Bytes) -> impl Iterator<Item = Bytes> {
+    (0..body.len())
+        .step_by(DATA_CHUNK_SIZE)
+        .map(move |start| body.slice(start..body.len().min(start + DATA_CHUNK_SIZE)))
+}
+
+/// Un-ACKed bytes a sender may have outstanding on one message before it pauses.
+/// JS connection.js:4
+pub const FLOW_CONTROL_WATERMARK: u64 = 65536;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum PacketType {
     Header,
@@ -28,3 +47,31 @@ pub enum PacketType {
     Ping,
     Pong,
 }
+
+impl PacketType {
+    /// The packet type as it appears on the wire.
+    pub fn wire_name(self) -> &'static str {
+        match self {
+            PacketType::Header => "HEADER",
+            PacketType::Data => "DATA",
+            PacketType::Eof => "EOF",
+            PacketType::Txerr => "TXERR",
+            PacketType::Ack => "ACK",
+            PacketType::Ping => "PING",
+            PacketType::Pong => "PONG",
+        }
+    }
+
+    fn from_wire_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "HEADER" => PacketType::Header,
+            "DATA" => PacketType::Data,
+            "EOF" => PacketType::Eof,
+            "TXERR" => PacketType::Txerr,
+            "ACK" => PacketType::Ack,
+            "PING" => PacketType::Ping,
+            "PONG" => PacketType::Pong,
+            _ => return None,
+        })
+    }
+}
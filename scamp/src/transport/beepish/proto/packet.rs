@@ -4,17 +4,21 @@
 //! Matches Perl Connection.pm:46,192-201.
 
 use anyhow::anyhow;
+use bytes::{Bytes, BytesMut};
+use std::ops::Range;
 use tokio::io::AsyncWriteExt;
+use tokio_util::codec::Encoder;
 
-use super::{PacketHeader, PacketType, MAX_PACKET_SIZE};
+use super::{PacketCodec, PacketHeader, PacketType, MAX_PACKET_SIZE};
 
 pub struct Packet {
     pub packet_type: PacketType,
     pub msg_no: u64,
     pub packet_header: Option<PacketHeader>,
-    pub body: Vec<u8>,
+    pub body: Bytes,
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum ParseResult {
     TooShort,
     NeedBytes { bytes: usize },
@@ -23,137 +27,137 @@ pub enum ParseResult {
     Fatal(anyhow::Error),
 }
 
-impl Packet {
-    pub async fn write<W>(&self, writer: &mut W) -> std::io::Result<usize>
-    where
-        W: AsyncWriteExt + Unpin,
-    {
-        let type_str: &[u8] = match self.packet_type {
-            PacketType::Header => b"HEADER",
-            PacketType::Data => b"DATA",
-            PacketType::Eof => b"EOF",
-            PacketType::Txerr => b"TXERR",
-            PacketType::Ack => b"ACK",
-            PacketType::Ping => b"PING",
-            PacketType::Pong => b"PONG",
-        };
-
-        let mut body_buf = Vec::new();
-        if let Some(header) = &self.packet_header {
-            serde_json::to_writer(&mut body_buf, header)?;
-        } else {
-            body_buf.extend_from_slice(&self.body);
-        }
+/// A complete, validated frame found at the start of a buffer.
+pub(super) struct Frame {
+    pub packet_type: PacketType,
+    pub msg_no: u64,
+    /// Where the payload sits in the buffer.
+    pub payload: Range<usize>,
+}
 
-        let header_line = format!("{} {} {}\r\n", std::str::from_utf8(type_str).unwrap(), self.msg_no, body_buf.len());
+impl Frame {
+    /// Bytes the whole frame occupies, trailer included.
+    pub fn len(&self) -> usize {
+        self.payload.end + 5
+    }
+}
 
-        writer.write_all(header_line.as_bytes()).await?;
-        writer.write_all(&body_buf).await?;
-        writer.write_all(b"END\r\n").await?;
+/// Outcome of scanning a buffer for one frame.
+pub(super) enum Scan {
+    /// The header line isn't complete yet.
+    TooShort,
+    /// The header line is valid; this many more bytes complete the frame.
+    NeedBytes(usize),
+    Complete(Frame),
+}
 
-        Ok(header_line.len() + body_buf.len() + 5)
+/// Validate the frame at the start of `buf` without copying anything.
+pub(super) fn scan_frame(buf: &[u8]) -> anyhow::Result<Scan> {
+    if buf.is_empty() {
+        return Ok(Scan::TooShort);
     }
 
-    pub fn parse(buf: &[u8]) -> ParseResult {
-        if buf.is_empty() {
-            return ParseResult::TooShort;
-        }
-
-        // Find \r\n in raw bytes first — the body after the header line may
-        // contain arbitrary binary data that isn't valid UTF-8.
-        // Perl Connection.pm:46 limits header line to 80 bytes.
-        let scan_len = buf.len().min(80);
-        let cut = match buf[..scan_len].windows(2).position(|w| w == b"\r\n") {
-            Some(pos) => pos,
-            None => {
-                if buf[..scan_len].contains(&b'\n') {
-                    return ParseResult::Fatal(anyhow!("Malformed request line (bare \\n, expected \\r\\n)"));
-                }
-                if scan_len >= 80 {
-                    return ParseResult::Fatal(anyhow!("Overlong header line"));
-                }
-                return ParseResult::TooShort;
+    // Find \r\n in raw bytes first — the body after the header line may
+    // contain arbitrary binary data that isn't valid UTF-8.
+    // Perl Connection.pm:46 limits header line to 80 bytes.
+    let scan_len = buf.len().min(80);
+    let cut = match buf[..scan_len].windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => pos,
+        None => {
+            if buf[..scan_len].contains(&b'\n') {
+                return Err(anyhow!("Malformed request line (bare \\n, expected \\r\\n)"));
             }
-        };
-
-        // Only the header line needs to be valid UTF-8
-        let header_line = match std::str::from_utf8(&buf[..cut]) {
-            Ok(line) => line,
-            Err(_) => return ParseResult::Fatal(anyhow!("Invalid UTF-8 in header line")),
-        };
-        let parts: Vec<&str> = header_line.split_whitespace().collect();
-        if parts.len() != 3 {
-            return ParseResult::Fatal(anyhow!("Malformed header line"));
+            if scan_len >= 80 {
+                return Err(anyhow!("Overlong header line"));
+            }
+            return Ok(Scan::TooShort);
         }
+    };
 
-        let cmd = parts[0];
-        let msg_no: u64 = match parts[1].parse() {
-            Ok(n) => n,
-            Err(_) => return ParseResult::Fatal(anyhow!("Invalid message number")),
-        };
-        let siz: usize = match parts[2].parse() {
-            Ok(n) => n,
-            Err(_) => return ParseResult::Fatal(anyhow!("Invalid packet size")),
-        };
+    // Only the header line needs to be valid UTF-8
+    let header_line = std::str::from_utf8(&buf[..cut]).map_err(|_| anyhow!("Invalid UTF-8 in header line"))?;
+    let parts: Vec<&str> = header_line.split_whitespace().collect();
+    if parts.len() != 3 {
+        return Err(anyhow!("Malformed header line"));
+    }
 
-        if siz > MAX_PACKET_SIZE {
-            return ParseResult::Fatal(anyhow!("Unreasonably large packet"));
-        }
+    let cmd = parts[0];
+    let msg_no: u64 = parts[1].parse().map_err(|_| anyhow!("Invalid message number"))?;
+    let siz: usize = parts[2].parse().map_err(|_| anyhow!("Invalid packet size"))?;
 
-        let payload_start = header_line.len() + 2; // +2 for \r\n
-        let payload_end = payload_start + siz;
+    if siz > MAX_PACKET_SIZE {
+        return Err(anyhow!("Unreasonably large packet"));
+    }
 
-        if payload_end + 5 > buf.len() {
-            return ParseResult::NeedBytes {
-                bytes: payload_end + 5 - buf.len(),
-            };
-        }
+    let payload_start = cut + 2; // +2 for \r\n
+    let payload_end = payload_start + siz;
 
-        let payload = &buf[payload_start..payload_end];
-        let trailer = &buf[payload_end..payload_end + 5];
+    if payload_end + 5 > buf.len() {
+        return Ok(Scan::NeedBytes(payload_end + 5 - buf.len()));
+    }
 
-        if trailer != b"END\r\n" {
-            return ParseResult::Fatal(anyhow!("Malformed trailer"));
-        }
+    if &buf[payload_end..payload_end + 5] != b"END\r\n" {
+        return Err(anyhow!("Malformed trailer"));
+    }
 
-        let packet_type = match cmd {
-            "HEADER" => PacketType::Header,
-            "DATA" => PacketType::Data,
-            "EOF" => PacketType::Eof,
-            "TXERR" => PacketType::Txerr,
-            "ACK" => PacketType::Ack,
-            "PING" => PacketType::Ping,
-            "PONG" => PacketType::Pong,
-            // Perl Connection.pm:187 — unknown packet type is fatal
-            _ => {
-                return ParseResult::Fatal(anyhow!("Unexpected packet of type {}", cmd));
-            }
-        };
+    // Perl Connection.pm:187 — unknown packet type is fatal
+    let packet_type = PacketType::from_wire_name(cmd).ok_or_else(|| anyhow!("Unexpected packet of type {}", cmd))?;
 
-        let packet = Packet {
-            packet_header: if packet_type == PacketType::Header {
-                match serde_json::from_slice(payload) {
-                    Ok(header) => Some(header),
-                    // Perl Connection.pm:148-149 — malformed header JSON is fatal
-                    Err(e) => {
-                        return ParseResult::Fatal(anyhow!("Malformed JSON in received header: {}", e));
-                    }
-                }
-            } else {
-                None
-            },
-            body: if packet_type != PacketType::Header {
-                payload.to_vec()
-            } else {
-                vec![]
-            },
+    Ok(Scan::Complete(Frame {
+        packet_type,
+        msg_no,
+        payload: payload_start..payload_end,
+    }))
+}
+
+impl Packet {
+    /// Build a packet from a framed payload, decoding the JSON of a HEADER.
+    pub(super) fn from_payload(packet_type: PacketType, msg_no: u64, payload: Bytes) -> anyhow::Result<Packet> {
+        if packet_type != PacketType::Header {
+            return Ok(Packet {
+                packet_type,
+                msg_no,
+                packet_header: None,
+                body: payload,
+            });
+        }
+        // Perl Connection.pm:148-149 — malformed header JSON is fatal
+        let header = serde_json::from_slice(&payload).map_err(|e| anyhow!("Malformed JSON in received header: {}", e))?;
+        Ok(Packet {
             packet_type,
             msg_no,
-        };
+            packet_header: Some(header),
+            body: Bytes::new(),
+        })
+    }
+
+    /// Write the packet with a single `write_all`.
+    pub async fn write<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: AsyncWriteExt + Unpin,
+    {
+        let mut buf = BytesMut::new();
+        PacketCodec.encode(self, &mut buf).map_err(std::io::Error::other)?;
+        writer.write_all(&buf).await?;
+        Ok(buf.len())
+    }
 
-        ParseResult::Success {
-            packet,
-            bytes_used: payload_end + 5,
+    /// Parse one packet from the start of `buf`, copying its payload.
+    /// Read loops use [`PacketCodec`], which applies the same rules without copying.
+    pub fn parse(buf: &[u8]) -> ParseResult {
+        let frame = match scan_frame(buf) {
+            Ok(Scan::Complete(frame)) => frame,
+            Ok(Scan::TooShort) => return ParseResult::TooShort,
+            Ok(Scan::NeedBytes(bytes)) => return ParseResult::NeedBytes { bytes },
+            Err(e) => return ParseResult::Fatal(e),
+        };
+        let payload = Bytes::copy_from_slice(&buf[frame.payload.clone()]);
+        match Packet::from_payload(frame.packet_type, frame.msg_no, payload) {
+            Ok(packet) => ParseResult::Success {
+                packet,
+                bytes_used: frame.len(),
+            },
+            Err(e) => ParseResult::Fatal(e),
         }
     }
 }
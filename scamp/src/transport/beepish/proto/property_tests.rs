@@ -0,0 +1,175 @@
+// Seeded property tests: whatever we write parses back unchanged, and
+// arbitrary bytes never panic the parser or the codec.
+
+use bytes::{Bytes, BytesMut};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio_util::codec::Decoder;
+
+use super::fixtures;
+use super::*;
+
+const CASES: u64 = 256;
+
+const TYPES: [PacketType; 7] = [
+    PacketType::Header,
+    PacketType::Data,
+    PacketType::Eof,
+    PacketType::Txerr,
+    PacketType::Ack,
+    PacketType::Ping,
+    PacketType::Pong,
+];
+
+fn random_string(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(0..24);
+    (0..len).map(|_| rng.gen_range(' '..='~')).collect()
+}
+
+fn random_header(rng: &mut StdRng) -> PacketHeader {
+    PacketHeader {
+        action: random_string(rng),
+        envelope: match rng.gen_range(0..3) {
+            0 => EnvelopeFormat::Json,
+            1 => EnvelopeFormat::JsonStore,
+            _ => EnvelopeFormat::Other(format!("x{}", random_string(rng))),
+        },
+        error: rng.gen_bool(0.3).then(|| random_string(rng)),
+        error_code: rng.gen_bool(0.3).then(|| random_string(rng)),
+        error_data: None,
+        request_id: FlexInt(rng.gen()),
+        client_id: FlexInt(rng.gen()),
+        ticket: random_string(rng),
+        identifying_token: random_string(rng),
+        message_type: if rng.gen() { MessageType::Request } else { MessageType::Reply },
+        version: rng.gen(),
+    }
+}
+
+fn random_packet(rng: &mut StdRng) -> Packet {
+    let packet_type = TYPES[rng.gen_range(0..TYPES.len())];
+    let msg_no = if rng.gen_bool(0.1) { u64::MAX } else { rng.gen_range(0..1000) };
+    if packet_type == PacketType::Header {
+        return Packet {
+            packet_type,
+            msg_no,
+            packet_header: Some(random_header(rng)),
+            body: Bytes::new(),
+        };
+    }
+    let len = match rng.gen_range(0..10) {
+        0 => MAX_PACKET_SIZE,
+        1..=3 => 0,
+        _ => rng.gen_range(0..4096),
+    };
+    let mut body = vec![0u8; len];
+    rng.fill(&mut body[..]);
+    Packet {
+        packet_type,
+        msg_no,
+        packet_header: None,
+        body: body.into(),
+    }
+}
+
+fn assert_same(a: &Packet, b: &Packet, seed: u64) {
+    assert_eq!(a.packet_type, b.packet_type, "seed {seed}");
+    assert_eq!(a.msg_no, b.msg_no, "seed {seed}");
+    assert_eq!(a.body, b.body, "seed {seed}");
+    let header_json = |p: &Packet| p.packet_header.as_ref().map(|h| serde_json::to_value(h).unwrap());
+    assert_eq!(header_json(a), header_json(b), "seed {seed}");
+}
+
+#[tokio::test]
+async fn test_write_then_parse_round_trips() {
+    for seed in 0..CASES {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let packet = random_packet(&mut rng);
+        let mut wire = Vec::new();
+        let written = packet.write(&mut wire).await.unwrap();
+        assert_eq!(written, wire.len());
+
+        match Packet::parse(&wire) {
+            ParseResult::Success {
+                packet: parsed,
+                bytes_used,
+            } => {
+                assert_eq!(bytes_used, wire.len(), "seed {seed}");
+                assert_same(&packet, &parsed, seed);
+            }
+            _ => panic!("seed {seed}: written packet did not parse"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_codec_decodes_any_split_of_a_packet_stream() {
+    for seed in 0..CASES {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let packets: Vec<Packet> = (0..rng.gen_range(1..6)).map(|_| random_packet(&mut rng)).collect();
+        let mut wire = Vec::new();
+        for packet in &packets {
+            packet.write(&mut wire).await.unwrap();
+        }
+
+        let mut codec = PacketCodec;
+        let mut buf = BytesMut::new();
+        let mut decoded = Vec::new();
+        let mut rest = &wire[..];
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(rng.gen_range(1..=rest.len().min(3000)));
+            buf.extend_from_slice(chunk);
+            rest = tail;
+            while let Some(packet) = codec.decode(&mut buf).unwrap() {
+                decoded.push(packet);
+            }
+        }
+
+        assert!(buf.is_empty(), "seed {seed}: bytes left over");
+        assert_eq!(decoded.len(), packets.len(), "seed {seed}");
+        for (a, b) in packets.iter().zip(&decoded) {
+            assert_same(a, b, seed);
+        }
+    }
+}
+
+/// Truncated and mutated fixtures: `parse` and the codec never panic and
+/// agree on what they accept.
+#[test]
+fn test_mangled_fixtures_never_panic() {
+    let corpus = [
+        fixtures::perl_request_header(),
+        fixtures::perl_request_header_with_nulls(),
+        fixtures::perl_reply_error(),
+        fixtures::perl_ack(2),
+        fixtures::perl_txerr(),
+        fixtures::perl_request_sequence(),
+        fixtures::perl_reply_sequence(b"{\"ok\":1}"),
+    ];
+    for seed in 0..CASES * 4 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut input = corpus[rng.gen_range(0..corpus.len())].clone();
+        for _ in 0..rng.gen_range(0..4) {
+            match rng.gen_range(0..3) {
+                0 if !input.is_empty() => {
+                    let at = rng.gen_range(0..input.len());
+                    input[at] = rng.gen();
+                }
+                1 => input.truncate(rng.gen_range(0..=input.len())),
+                _ => {
+                    let at = rng.gen_range(0..=input.len());
+                    input.insert(at, b" 0\r\nEND9"[rng.gen_range(0..8)]);
+                }
+            }
+        }
+
+        let parsed = Packet::parse(&input);
+        let decoded = PacketCodec.decode(&mut BytesMut::from(&input[..]));
+        match (parsed, decoded) {
+            (ParseResult::Success { packet, .. }, Ok(Some(frame))) => assert_same(&packet, &frame, seed),
+            (ParseResult::TooShort | ParseResult::NeedBytes { .. }, Ok(None)) => {}
+            (ParseResult::Fatal(_), Err(_)) => {}
+            _ => panic!("seed {seed}: parse and codec disagree on {:?}", String::from_utf8_lossy(&input)),
+        }
+    }
+}
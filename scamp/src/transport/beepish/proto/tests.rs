@@ -132,7 +132,7 @@ fn test_packet_parse_roundtrip() {
         packet_type: PacketType::Data,
         msg_no: 3,
         packet_header: None,
-        body: b"hello world".to_vec(),
+        body: Bytes::from_static(b"hello world"),
     };
 
     let mut buf = Vec::new();
@@ -144,7 +144,7 @@ fn test_packet_parse_roundtrip() {
             assert_eq!(bytes_used, buf.len());
             assert_eq!(packet.packet_type, PacketType::Data);
             assert_eq!(packet.msg_no, 3);
-            assert_eq!(packet.body, b"hello world");
+            assert_eq!(&packet.body[..], b"hello world");
         }
         _ => panic!("Expected Success"),
     }
@@ -1,159 +1,165 @@
-use crate::discovery::ActionEntry;
+//! A [`Transport`] that answers from expectations instead of the network,
+//! for testing code that sends requests through a
+//! [`Requester`](crate::requester::Requester).
+//!
+//! Each call is matched against the expectations in the order they were
+//! added; the first one that matches and still has calls left answers it.
+//! Calls nothing matched fail and are kept for the [`MockReport`].
+
+mod expectation;
+#[cfg(test)]
+mod mock_tests;
+mod report;
+
+pub use expectation::Expectation;
+pub use report::{MockReport, RecordedCall, UnmetExpectation};
 
-use anyhow::Result;
-use std::{collections::BTreeMap, io::Cursor, sync::Mutex};
-use tokio::io::{AsyncRead, AsyncReadExt};
+use anyhow::{anyhow, Result};
+use std::sync::Mutex;
 
-pub struct MockResponse {
-    pub headers: BTreeMap<String, String>,
-    pub body: Box<dyn AsyncRead + Unpin>,
+use crate::discovery::ActionEntry;
+use crate::transport::beepish::proto::{FlexInt, MessageType, PacketHeader};
+use crate::transport::beepish::ScampResponse;
+use crate::transport::{Transport, TransportFuture, TransportRequest};
+use expectation::MockReply;
+
+struct Registered {
+    expectation: Expectation,
+    calls: usize,
 }
 
-pub struct Expectation {
-    pub pathver: String,
-    pub req_headers: BTreeMap<String, String>,
-    pub req_body: Vec<u8>,
-    pub res_headers: BTreeMap<String, String>,
-    pub res_body: Vec<u8>,
-    pub sleep: Option<u64>,
+impl Registered {
+    fn exhausted(&self) -> bool {
+        self.expectation.times.is_some_and(|n| self.calls >= n)
+    }
 }
 
-pub struct MockClient {
-    expect: Mutex<Vec<Expectation>>,
+#[derive(Default)]
+struct MockState {
+    expectations: Vec<Registered>,
+    calls: Vec<RecordedCall>,
 }
 
-impl Default for MockClient {
-    fn default() -> Self {
-        Self::new()
-    }
+#[derive(Default)]
+pub struct MockClient {
+    state: Mutex<MockState>,
 }
 
 impl MockClient {
     pub fn new() -> Self {
-        Self {
-            expect: Mutex::new(vec![]),
-        }
-    }
-    pub fn expect(&mut self, expectation: Expectation) {
-        self.expect.lock().unwrap().push(expectation);
-    }
-    pub fn clear(&mut self) {
-        self.expect.lock().unwrap().clear();
-    }
-    pub fn expectations_met(&mut self) -> bool {
-        self.expect.lock().unwrap().is_empty()
-    }
-    pub fn expectation_count(&mut self) -> usize {
-        self.expect.lock().unwrap().len()
+        Self::default()
     }
 
-    pub async fn request(
-        &self,
-        action: &ActionEntry,
-        headers: BTreeMap<String, String>,
-        mut body: Box<dyn AsyncRead + Unpin + Send>,
-    ) -> Result<MockResponse> {
-        eprintln!("  * Mock Call to {} at {}", action.action.path, action.service_info.uri);
-
-        let pathver = format!("{}~{}", action.action.path, action.action.version);
+    pub fn expect(&self, expectation: Expectation) {
+        self.state.lock().unwrap().expectations.push(Registered { expectation, calls: 0 });
+    }
 
-        let index = {
-            let expectations = self.expect.lock().unwrap();
-            expectations.iter().position(|e| e.pathver == pathver)
-        };
+    /// Forget all expectations and recorded calls.
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = MockState::default();
+    }
 
-        let expectation = match index {
-            Some(idx) => self.expect.lock().unwrap().remove(idx),
-            None => return Err(anyhow::anyhow!("No expectation found for pathver {pathver}")),
-        };
+    /// Whether every expectation has had all its calls.
+    pub fn expectations_met(&self) -> bool {
+        self.expectation_count() == 0
+    }
 
-        if headers != expectation.req_headers {
-            return Err(anyhow::anyhow!("Request headers do not match expectation"));
-        }
+    /// How many expectations are still waiting for calls.
+    pub fn expectation_count(&self) -> usize {
+        self.report().unmet.len()
+    }
 
-        println!("    * Request headers match expectation:  {:?}", headers);
+    /// Every call made so far, matched or not.
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
 
-        if let Some(sleep) = expectation.sleep {
-            tokio::time::sleep(tokio::time::Duration::from_millis(sleep)).await;
+    /// Unmet expectations and unexpected calls.
+    pub fn report(&self) -> MockReport {
+        let state = self.state.lock().unwrap();
+        MockReport {
+            unmet: state
+                .expectations
+                .iter()
+                .filter_map(|r| match r.expectation.times {
+                    Some(expected) if r.calls < expected => Some(UnmetExpectation {
+                        expectation: r.expectation.to_string(),
+                        expected,
+                        calls: r.calls,
+                    }),
+                    _ => None,
+                })
+                .collect(),
+            unexpected: state.calls.iter().filter(|c| c.matched.is_none()).cloned().collect(),
         }
+    }
 
-        let mut buf = Vec::new();
-        body.read_to_end(&mut buf).await.unwrap();
-
-        if buf == expectation.req_body {
-            println!("    * Request body matches expectation");
+    /// Err with the report unless every expectation was met and every call expected.
+    pub fn verify(&self) -> Result<()> {
+        let report = self.report();
+        if report.is_clean() {
+            Ok(())
         } else {
-            return Err(anyhow::anyhow!("Request body does not match expectation"));
+            Err(anyhow!("{}", report))
         }
-
-        let mut headers = BTreeMap::new();
-        headers.insert("content-type".to_string(), "application/json".to_string());
-
-        let body = Box::new(tokio::io::BufReader::new(Cursor::new(expectation.res_body))) as Box<dyn AsyncRead + Unpin>;
-
-        println!("    * Response headers: {:?}", headers);
-        Ok(MockResponse { headers, body })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::discovery::*;
-
-    use super::*;
-
-    #[tokio::test]
-    async fn test_mock_client() {
-        let pathver = "foo.bar~1".to_string();
-        let req_headers = BTreeMap::from([("content-type".to_string(), "application/json".to_string())]);
-        let req_body: Vec<u8> = r#"{"operation":"turboencabulate"}"#.into();
-        let res_headers = BTreeMap::from([("content-type".to_string(), "application/json".to_string())]);
-        let res_body: Vec<u8> = r#"{"status":"great", "reframulation_level": 42}"#.into();
-
-        let mut client = MockClient::new();
-        client.expect(Expectation {
-            pathver: pathver.clone(),
-            req_headers: req_headers.clone(),
-            req_body: req_body.clone(),
-            res_headers: res_headers.clone(),
-            res_body: res_body.clone(),
-            sleep: None,
-        });
-
-        let service_info = ServiceInfo {
-            identity: "test:abcd".to_string(),
-            uri: "beepish+tls://127.0.0.1:30100".to_string(),
-            fingerprint: None,
+    async fn respond(&self, target: &ActionEntry, request: TransportRequest<'_>) -> Result<ScampResponse> {
+        log::debug!("Mock call to {} at {}", target.action.path, target.service_info.uri);
+
+        let matched = {
+            let mut state = self.state.lock().unwrap();
+            let matched = state
+                .expectations
+                .iter_mut()
+                .find(|r| !r.exhausted() && r.expectation.matches(&request))
+                .map(|r| {
+                    r.calls += 1;
+                    r.expectation.clone()
+                });
+            state.calls.push(RecordedCall::new(target, &request, matched.as_ref()));
+            matched
+        };
+        let Some(expectation) = matched else {
+            return Err(anyhow!("No expectation matched {} v{}", request.action, request.version));
         };
 
-        let action = ActionEntry {
-            action: service_info::Action {
-                path: "foo.bar".to_string(),
-                version: 1,
-                pathver: "foo.bar~1".to_string(),
-                flags: vec![],
-                sector: "main".to_string(),
-                envelopes: vec!["json".to_string()],
-                packet_section: service_info::PacketSection::V3,
-            },
-            service_info: service_info.clone(),
-            announcement_params: service_info::AnnouncementParams {
-                weight: 1,
-                interval: 5000,
-                timestamp: 0.0,
+        if let Some(delay) = expectation.delay {
+            if delay > request.timeout {
+                tokio::time::sleep(request.timeout).await;
+                return Err(anyhow!("Request timed out after {:?}", request.timeout));
+            }
+            tokio::time::sleep(delay).await;
+        }
+
+        let mut response = ScampResponse {
+            header: PacketHeader {
+                action: request.action.to_string(),
+                envelope: request.envelope,
+                message_type: MessageType::Reply,
+                client_id: FlexInt(request.client_id),
+                version: request.version,
+                ..Default::default()
             },
-            authorized: true,
+            body: Vec::new(),
+            error: None,
         };
+        match expectation.reply {
+            MockReply::Body(body) => response.body = body,
+            MockReply::Error { message, code, data } => {
+                response.header.error = Some(message);
+                response.header.error_code = code;
+                response.header.error_data = data;
+            }
+            MockReply::Lost(message) => response.error = Some(message),
+            MockReply::TransportError(message) => return Err(anyhow!(message)),
+        }
+        Ok(response)
+    }
+}
 
-        let response = client
-            .request(&action, req_headers.clone(), Box::new(std::io::Cursor::new(req_body)))
-            .await
-            .unwrap();
-
-        let mut body = Vec::new();
-        let mut reader = response.body;
-        tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut body).await.unwrap();
-        assert_eq!(body, res_body);
-        assert!(client.expectations_met());
+impl Transport for MockClient {
+    fn request<'a>(&'a self, target: &'a ActionEntry, request: TransportRequest<'a>) -> TransportFuture<'a, ScampResponse> {
+        Box::pin(self.respond(target, request))
     }
 }
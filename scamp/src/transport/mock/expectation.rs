@@ -0,0 +1,226 @@
+//! What a [`MockClient`](super::MockClient) expects to be asked, and how it answers.
+
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::transport::beepish::proto::EnvelopeFormat;
+use crate::transport::TransportRequest;
+
+type BodyPredicate = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// How a matched call is answered.
+#[derive(Debug, Clone)]
+pub(super) enum MockReply {
+    /// A successful reply with this body.
+    Body(Vec<u8>),
+    /// A reply HEADER carrying an error, as a service's handler would send.
+    Error {
+        message: String,
+        code: Option<String>,
+        data: Option<Value>,
+    },
+    /// The reply was lost in flight: `ScampResponse::error` is set.
+    Lost(String),
+    /// The request could not be delivered: the transport returns `Err`.
+    TransportError(String),
+}
+
+/// One expected call. Build it with [`Expectation::action`] or
+/// [`Expectation::any`], narrow it with the matchers and pick a reply.
+/// Unless [`Expectation::times`] or [`Expectation::repeatedly`] says
+/// otherwise it matches exactly one call and answers with an empty body.
+#[derive(Clone)]
+pub struct Expectation {
+    action: Option<String>,
+    version: Option<i32>,
+    envelope: Option<EnvelopeFormat>,
+    ticket: Option<String>,
+    body: Vec<(String, BodyPredicate)>,
+    pub(super) reply: MockReply,
+    pub(super) delay: Option<Duration>,
+    /// None: any number of calls.
+    pub(super) times: Option<usize>,
+}
+
+impl Expectation {
+    /// Expect a call to `action` (compared case-insensitively, as routing is).
+    pub fn action(action: &str) -> Self {
+        Expectation {
+            action: Some(action.to_string()),
+            ..Self::any()
+        }
+    }
+
+    /// Expect a call to any action.
+    pub fn any() -> Self {
+        Expectation {
+            action: None,
+            version: None,
+            envelope: None,
+            ticket: None,
+            body: Vec::new(),
+            reply: MockReply::Body(Vec::new()),
+            delay: None,
+            times: Some(1),
+        }
+    }
+
+    pub fn version(mut self, version: i32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn envelope(mut self, envelope: EnvelopeFormat) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    pub fn ticket(mut self, ticket: &str) -> Self {
+        self.ticket = Some(ticket.to_string());
+        self
+    }
+
+    /// Match only this exact request body.
+    pub fn body(self, body: impl Into<Vec<u8>>) -> Self {
+        let body = body.into();
+        let label = format!("body = {}", String::from_utf8_lossy(&body));
+        self.body_matches(label, move |actual| actual == body)
+    }
+
+    /// Match a JSON body containing `subset`: every key of an expected object
+    /// must be present with a matching value; other keys are ignored.
+    pub fn body_json(self, subset: Value) -> Self {
+        let label = format!("body ⊇ {}", subset);
+        self.body_matches(label, move |actual| {
+            serde_json::from_slice::<Value>(actual).is_ok_and(|actual| json_contains(&actual, &subset))
+        })
+    }
+
+    /// Match bodies accepted by `predicate`; `label` names it in reports.
+    pub fn body_matches(mut self, label: impl Into<String>, predicate: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+        self.body.push((label.into(), Arc::new(predicate)));
+        self
+    }
+
+    /// Answer with this body.
+    pub fn reply(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.reply = MockReply::Body(body.into());
+        self
+    }
+
+    /// Answer with `value` serialized as JSON.
+    pub fn reply_json(self, value: Value) -> Self {
+        self.reply(value.to_string())
+    }
+
+    /// Answer with an error reply, as a handler returning an error would.
+    pub fn reply_error(mut self, message: &str, code: Option<&str>) -> Self {
+        self.reply = MockReply::Error {
+            message: message.to_string(),
+            code: code.map(str::to_string),
+            data: None,
+        };
+        self
+    }
+
+    /// Like [`Expectation::reply_error`], with structured `error_data`.
+    pub fn reply_error_with_data(mut self, message: &str, code: Option<&str>, data: Value) -> Self {
+        self.reply = MockReply::Error {
+            message: message.to_string(),
+            code: code.map(str::to_string),
+            data: Some(data),
+        };
+        self
+    }
+
+    /// Answer as a service that could not handle the request, which makes
+    /// the Requester retry on another instance.
+    pub fn dispatch_failure(self) -> Self {
+        self.reply_error_with_data(
+            "Service unavailable",
+            Some("dispatch_failure"),
+            serde_json::json!({ "dispatch_failure": true }),
+        )
+    }
+
+    /// Fail as a dropped connection does: the reply comes back with
+    /// `ScampResponse::error` set.
+    pub fn connection_lost(mut self, message: &str) -> Self {
+        self.reply = MockReply::Lost(message.to_string());
+        self
+    }
+
+    /// Fail to deliver the request at all: the transport returns `Err`.
+    pub fn transport_error(mut self, message: &str) -> Self {
+        self.reply = MockReply::TransportError(message.to_string());
+        self
+    }
+
+    /// Wait this long before answering. A delay past the request's timeout
+    /// makes the call time out, as it would on a real connection.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Match exactly `n` calls; fewer is reported as unmet.
+    pub fn times(mut self, n: usize) -> Self {
+        self.times = Some(n);
+        self
+    }
+
+    /// Match any number of calls, including none.
+    pub fn repeatedly(mut self) -> Self {
+        self.times = None;
+        self
+    }
+
+    pub(super) fn matches(&self, request: &TransportRequest<'_>) -> bool {
+        self.action.as_ref().is_none_or(|a| a.eq_ignore_ascii_case(request.action))
+            && self.version.is_none_or(|v| v == request.version)
+            && self.envelope.as_ref().is_none_or(|e| *e == request.envelope)
+            && self.ticket.as_ref().is_none_or(|t| t == request.ticket)
+            && self.body.iter().all(|(_, predicate)| predicate(&request.body))
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.action.as_deref().unwrap_or("any action"))?;
+        if let Some(version) = self.version {
+            write!(f, " v{}", version)?;
+        }
+        if let Some(envelope) = &self.envelope {
+            write!(f, " envelope={:?}", envelope)?;
+        }
+        if let Some(ticket) = &self.ticket {
+            write!(f, " ticket={}", ticket)?;
+        }
+        for (label, _) in &self.body {
+            write!(f, " {}", label)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expectation({})", self)
+    }
+}
+
+/// Whether `actual` contains everything in `expected`. Objects may carry
+/// extra keys; arrays must have the same length, compared element-wise.
+fn json_contains(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => expected
+            .iter()
+            .all(|(key, value)| actual.get(key).is_some_and(|a| json_contains(a, value))),
+        (Value::Array(actual), Value::Array(expected)) => {
+            actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, e)| json_contains(a, e))
+        }
+        _ => actual == expected,
+    }
+}
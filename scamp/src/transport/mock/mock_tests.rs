@@ -0,0 +1,155 @@
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use super::*;
+use crate::config::Config;
+use crate::discovery::ServiceRegistry;
+use crate::requester::Requester;
+use crate::test_helpers::make_action_entry;
+use crate::transport::beepish::proto::EnvelopeFormat;
+
+fn request(action: &'static str, body: &[u8]) -> TransportRequest<'static> {
+    TransportRequest {
+        action,
+        version: 1,
+        envelope: EnvelopeFormat::Json,
+        ticket: "",
+        client_id: 0,
+        body: body.to_vec(),
+        timeout: Duration::from_secs(5),
+    }
+}
+
+async fn call(mock: &MockClient, request: TransportRequest<'static>) -> Result<ScampResponse> {
+    mock.request(&make_action_entry(request.action, 1), request).await
+}
+
+#[tokio::test]
+async fn test_reply_body() {
+    let mock = MockClient::new();
+    mock.expect(Expectation::action("foo.bar").version(1).reply_json(json!({"status": "great"})));
+
+    let response = call(&mock, request("Foo.Bar", b"{}")).await.unwrap();
+
+    assert_eq!(response.body, br#"{"status":"great"}"#);
+    assert_eq!(response.header.message_type, MessageType::Reply);
+    mock.verify().unwrap();
+}
+
+#[tokio::test]
+async fn test_matchers() {
+    let mock = MockClient::new();
+    mock.expect(Expectation::action("foo.bar").ticket("t1").reply("first"));
+    mock.expect(Expectation::action("foo.bar").envelope(EnvelopeFormat::JsonStore).reply("second"));
+    mock.expect(Expectation::any().body_json(json!({"user": {"id": 7}})).reply("third"));
+
+    let mut req = request("foo.bar", b"");
+    req.envelope = EnvelopeFormat::JsonStore;
+    assert_eq!(call(&mock, req).await.unwrap().body, b"second");
+
+    let mut req = request("foo.bar", b"");
+    req.ticket = "t1";
+    assert_eq!(call(&mock, req).await.unwrap().body, b"first");
+
+    let body = br#"{"user": {"id": 7, "name": "ann"}, "extra": true}"#;
+    assert_eq!(call(&mock, request("other.action", body)).await.unwrap().body, b"third");
+
+    mock.verify().unwrap();
+}
+
+#[tokio::test]
+async fn test_error_replies() {
+    let mock = MockClient::new();
+    mock.expect(Expectation::action("a").reply_error_with_data("Nope", Some("denied"), json!({"field": "x"})));
+    mock.expect(Expectation::action("b").connection_lost("Connection lost"));
+    mock.expect(Expectation::action("c").transport_error("Connection refused"));
+
+    let response = call(&mock, request("a", b"")).await.unwrap();
+    assert_eq!(response.header.error.as_deref(), Some("Nope"));
+    assert_eq!(response.header.error_code.as_deref(), Some("denied"));
+    assert_eq!(response.header.error_data, Some(json!({"field": "x"})));
+
+    assert_eq!(
+        call(&mock, request("b", b"")).await.unwrap().error.as_deref(),
+        Some("Connection lost")
+    );
+    assert_eq!(call(&mock, request("c", b"")).await.unwrap_err().to_string(), "Connection refused");
+}
+
+#[tokio::test]
+async fn test_times_and_repeatedly() {
+    let mock = MockClient::new();
+    mock.expect(Expectation::action("limited").times(2));
+    mock.expect(Expectation::action("stub").repeatedly());
+
+    for _ in 0..2 {
+        call(&mock, request("limited", b"")).await.unwrap();
+    }
+    assert!(call(&mock, request("limited", b"")).await.is_err());
+    assert!(mock.expectations_met());
+
+    let report = mock.report();
+    assert_eq!(report.unmet.len(), 0);
+    assert_eq!(report.unexpected.len(), 1);
+    assert_eq!(mock.calls().len(), 3);
+}
+
+#[tokio::test]
+async fn test_delay_and_timeout() {
+    let mock = MockClient::new();
+    mock.expect(Expectation::action("slow").delay(Duration::from_millis(20)).times(2));
+
+    let started = std::time::Instant::now();
+    call(&mock, request("slow", b"")).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(20));
+
+    let mut req = request("slow", b"");
+    req.timeout = Duration::from_millis(5);
+    let err = call(&mock, req).await.unwrap_err();
+    assert!(err.to_string().contains("timed out"), "{err}");
+}
+
+#[tokio::test]
+async fn test_report_lists_unmet_and_unexpected() {
+    let mock = MockClient::new();
+    mock.expect(Expectation::action("wanted").version(2).times(3));
+    mock.expect(Expectation::action("body.check").body(b"{}".to_vec()));
+
+    call(&mock, request("stray", b"hello")).await.unwrap_err();
+    call(&mock, request("body.check", b"[]")).await.unwrap_err();
+
+    let report = mock.report();
+    assert_eq!(report.unmet.len(), 2);
+    assert_eq!((report.unmet[0].expected, report.unmet[0].calls), (3, 0));
+    assert_eq!(report.unexpected.len(), 2);
+    assert_eq!(mock.expectation_count(), 2);
+
+    let text = mock.verify().unwrap_err().to_string();
+    assert!(text.contains("unmet: wanted v2 (expected 3 calls, got 0)"), "{text}");
+    assert!(text.contains("unexpected: stray v1 to test:abcd: hello"), "{text}");
+}
+
+#[tokio::test]
+async fn test_requester_retries_dispatch_failure() {
+    let mut registry = ServiceRegistry::empty();
+    for identity in ["svc:one", "svc:two"] {
+        let mut entry = make_action_entry("Widget.fetch", 1);
+        entry.service_info.identity = identity.to_string();
+        registry.insert(entry);
+    }
+    let mock = Arc::new(MockClient::new());
+    mock.expect(Expectation::action("Widget.fetch").dispatch_failure());
+    mock.expect(Expectation::action("Widget.fetch").reply("ok"));
+    let config = Config::from_content("").unwrap();
+    let requester = Requester::with_transport(&config, Arc::new(RwLock::new(registry)), mock.clone());
+
+    let resp = requester.request("Widget.fetch", 1, vec![]).await.unwrap();
+
+    assert_eq!(resp.body, b"ok");
+    mock.verify().unwrap();
+    let calls = mock.calls();
+    assert_eq!(calls.len(), 2);
+    assert_ne!(calls[0].service, calls[1].service, "retry should go to the other instance");
+}
@@ -0,0 +1,83 @@
+//! What a [`MockClient`](super::MockClient) saw, for verifying tests.
+
+use std::fmt;
+
+use super::Expectation;
+use crate::discovery::ActionEntry;
+use crate::transport::beepish::proto::EnvelopeFormat;
+use crate::transport::TransportRequest;
+
+/// A call made through the mock.
+#[derive(Debug, Clone)]
+pub struct RecordedCall {
+    pub action: String,
+    pub version: i32,
+    pub envelope: EnvelopeFormat,
+    pub ticket: String,
+    pub body: Vec<u8>,
+    /// Identity of the service instance the call was routed to.
+    pub service: String,
+    /// The expectation that answered it, None if nothing did.
+    pub matched: Option<String>,
+}
+
+impl RecordedCall {
+    pub(super) fn new(target: &ActionEntry, request: &TransportRequest<'_>, matched: Option<&Expectation>) -> Self {
+        RecordedCall {
+            action: request.action.to_string(),
+            version: request.version,
+            envelope: request.envelope.clone(),
+            ticket: request.ticket.to_string(),
+            body: request.body.clone(),
+            service: target.service_info.identity.clone(),
+            matched: matched.map(Expectation::to_string),
+        }
+    }
+}
+
+/// An expectation that got fewer calls than it wanted.
+#[derive(Debug, Clone)]
+pub struct UnmetExpectation {
+    pub expectation: String,
+    pub expected: usize,
+    pub calls: usize,
+}
+
+/// Result of checking a mock after a test.
+#[derive(Debug, Clone, Default)]
+pub struct MockReport {
+    pub unmet: Vec<UnmetExpectation>,
+    pub unexpected: Vec<RecordedCall>,
+}
+
+impl MockReport {
+    pub fn is_clean(&self) -> bool {
+        self.unmet.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+impl fmt::Display for MockReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return f.write_str("all expectations met, no unexpected calls");
+        }
+        for unmet in &self.unmet {
+            writeln!(
+                f,
+                "unmet: {} (expected {} calls, got {})",
+                unmet.expectation, unmet.expected, unmet.calls
+            )?;
+        }
+        for call in &self.unexpected {
+            writeln!(
+                f,
+                "unexpected: {} v{} to {}: {}",
+                call.action,
+                call.version,
+                call.service,
+                String::from_utf8_lossy(&call.body)
+            )?;
+        }
+        Ok(())
+    }
+}
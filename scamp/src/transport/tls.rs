@@ -0,0 +1,31 @@
+//! TLS for BEEPish connections.
+//!
+//! SCAMP services present self-signed certificates, so a client trusts a
+//! peer by the SHA1 fingerprint announced for it rather than by a CA chain.
+//! Two backends provide the same `connect` / `TlsAcceptor` surface:
+//! native-tls (OpenSSL, the default), which checks the fingerprint after the
+//! handshake, and rustls (the `rustls` feature), which pins it inside the
+//! handshake through a custom certificate verifier.
+//!
+//! Only the TLS layer is swapped: signatures and digests outside it (see
+//! `crypto.rs`, `auth::ticket`, `service::announce`) still use OpenSSL.
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+mod native_backend;
+#[cfg(feature = "rustls")]
+mod rustls_backend;
+#[cfg(test)]
+mod tls_tests;
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub(crate) use native_backend::{connect, TlsAcceptor};
+#[cfg(feature = "rustls")]
+pub(crate) use rustls_backend::{connect, TlsAcceptor};
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("scamp needs a TLS backend: enable the `native-tls` or `rustls` feature");
+
+/// Why a peer's certificate was refused.
+fn fingerprint_mismatch(expected: &str, actual: &str) -> String {
+    format!("CERTIFICATE MISMATCH! Announced {} got {}", expected, actual)
+}
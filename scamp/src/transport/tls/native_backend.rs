@@ -0,0 +1,51 @@
+//! native-tls (OpenSSL) backend.
+
+use anyhow::{anyhow, Context, Result};
+use std::net::IpAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_native_tls::native_tls;
+
+/// Handshake as a client, then check the peer against `expected_fingerprint`
+/// before any packets are sent (Perl Connection.pm:61-68).
+pub(crate) async fn connect(
+    stream: TcpStream,
+    ip: IpAddr,
+    expected_fingerprint: Option<&str>,
+) -> Result<impl AsyncRead + AsyncWrite + Unpin + Send + 'static> {
+    // Certificates are self-signed; trust comes from the fingerprint below.
+    let tls = native_tls::TlsConnector::builder().danger_accept_invalid_certs(true).build()?;
+    let connector = tokio_native_tls::TlsConnector::from(tls);
+    let tls_stream = connector.connect(&ip.to_string(), stream).await?;
+
+    if let Some(expected_fp) = expected_fingerprint {
+        let peer_cert = tls_stream
+            .get_ref()
+            .peer_certificate()
+            .context("Failed to get peer certificate")?
+            .ok_or_else(|| anyhow!("Peer did not present a certificate"))?;
+        let peer_der = peer_cert.to_der().context("Failed to get peer certificate DER")?;
+        let actual_fp = crate::crypto::cert_sha1_fingerprint(&peer_der);
+        if actual_fp != expected_fp {
+            return Err(anyhow!(super::fingerprint_mismatch(expected_fp, &actual_fp)));
+        }
+        log::debug!("Certificate fingerprint verified: {}", actual_fp);
+    }
+    Ok(tls_stream)
+}
+
+/// Server side of the handshake, presenting the service's key and cert.
+#[derive(Clone)]
+pub(crate) struct TlsAcceptor(tokio_native_tls::TlsAcceptor);
+
+impl TlsAcceptor {
+    pub fn from_pem(key_pem: &[u8], cert_pem: &[u8]) -> Result<Self> {
+        let identity = native_tls::Identity::from_pkcs8(cert_pem, key_pem)?;
+        let tls = native_tls::TlsAcceptor::builder(identity).build()?;
+        Ok(TlsAcceptor(tls.into()))
+    }
+
+    pub async fn accept(&self, stream: TcpStream) -> Result<impl AsyncRead + AsyncWrite + Unpin + Send + 'static> {
+        Ok(self.0.accept(stream).await?)
+    }
+}
@@ -0,0 +1,128 @@
+//! rustls backend, pinning peers by announced fingerprint.
+
+use anyhow::{anyhow, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, ClientConfig, DigitallySignedStruct, OtherError, ServerConfig, SignatureScheme};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+#[derive(Debug)]
+struct FingerprintMismatch(String);
+
+impl std::fmt::Display for FingerprintMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for FingerprintMismatch {}
+
+/// Accepts exactly the certificate whose SHA1 fingerprint was announced.
+///
+/// Chain, name and validity checks are skipped — SCAMP certificates are
+/// self-signed and the fingerprint is the trust anchor — but handshake
+/// signatures are still verified, so the peer must hold the matching key.
+/// With no announced fingerprint any certificate is accepted, as the
+/// native-tls backend does.
+#[derive(Debug)]
+struct FingerprintVerifier {
+    expected: Option<String>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl FingerprintVerifier {
+    fn new(expected: Option<&str>) -> Self {
+        FingerprintVerifier {
+            expected: expected.map(str::to_string),
+            algorithms: provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let Some(expected) = &self.expected else {
+            return Ok(ServerCertVerified::assertion());
+        };
+        let actual = crate::crypto::cert_sha1_fingerprint(end_entity);
+        if actual != *expected {
+            let mismatch = FingerprintMismatch(super::fingerprint_mismatch(expected, &actual));
+            return Err(CertificateError::Other(OtherError(Arc::new(mismatch))).into());
+        }
+        log::debug!("Certificate fingerprint verified: {}", actual);
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// Handshake as a client; the peer is checked against `expected_fingerprint`
+/// during the handshake, before any packets are sent.
+pub(crate) async fn connect(
+    stream: TcpStream,
+    ip: IpAddr,
+    expected_fingerprint: Option<&str>,
+) -> Result<impl AsyncRead + AsyncWrite + Unpin + Send + 'static> {
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(FingerprintVerifier::new(expected_fingerprint)))
+        .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+    Ok(connector.connect(ServerName::from(ip), stream).await?)
+}
+
+/// Server side of the handshake, presenting the service's key and cert.
+#[derive(Clone)]
+pub(crate) struct TlsAcceptor(tokio_rustls::TlsAcceptor);
+
+impl TlsAcceptor {
+    pub fn from_pem(key_pem: &[u8], cert_pem: &[u8]) -> Result<Self> {
+        let certs = rustls_pemfile::certs(&mut &cert_pem[..]).collect::<Result<Vec<_>, _>>()?;
+        let key = rustls_pemfile::private_key(&mut &key_pem[..])?.ok_or_else(|| anyhow!("No private key found in PEM"))?;
+        let config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        Ok(TlsAcceptor(Arc::new(config).into()))
+    }
+
+    pub async fn accept(&self, stream: TcpStream) -> Result<impl AsyncRead + AsyncWrite + Unpin + Send + 'static> {
+        Ok(self.0.accept(stream).await?)
+    }
+}
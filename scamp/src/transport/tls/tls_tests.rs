@@ -0,0 +1,63 @@
+use crate::crypto::cert_pem_fingerprint;
+use crate::test_helpers::generate_test_keypair;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::{connect, TlsAcceptor};
+
+/// A TLS server that echoes one line; returns its address and certificate fingerprint.
+async fn echo_server() -> (SocketAddr, String) {
+    let (key_pem, cert_pem) = generate_test_keypair();
+    let fingerprint = cert_pem_fingerprint(std::str::from_utf8(&cert_pem).unwrap()).unwrap();
+    let acceptor = TlsAcceptor::from_pem(&key_pem, &cert_pem).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut tls) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let mut buf = [0u8; 64];
+                let n = tls.read(&mut buf).await.unwrap_or(0);
+                let _ = tls.write_all(&buf[..n]).await;
+                let _ = tls.flush().await;
+            });
+        }
+    });
+    (addr, fingerprint)
+}
+
+async fn tcp(addr: SocketAddr) -> TcpStream {
+    TcpStream::connect(addr).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_connect_with_announced_fingerprint() {
+    let (addr, fingerprint) = echo_server().await;
+    let mut tls = connect(tcp(addr).await, addr.ip(), Some(&fingerprint)).await.unwrap();
+    tls.write_all(b"ping").await.unwrap();
+    tls.flush().await.unwrap();
+    let mut buf = [0u8; 4];
+    tls.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn test_connect_rejects_wrong_fingerprint() {
+    let (addr, _) = echo_server().await;
+    let wrong = ["00"; 20].join(":");
+    let err = connect(tcp(addr).await, addr.ip(), Some(&wrong))
+        .await
+        .err()
+        .expect("handshake should fail");
+    assert!(format!("{:#}", err).contains("CERTIFICATE MISMATCH"), "got: {:#}", err);
+}
+
+#[tokio::test]
+async fn test_connect_without_fingerprint_accepts_any_cert() {
+    let (addr, _) = echo_server().await;
+    connect(tcp(addr).await, addr.ip(), None).await.unwrap();
+}
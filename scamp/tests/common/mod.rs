@@ -0,0 +1,6 @@
+//! Helpers shared by the integration tests.
+
+/// Generate a self-signed RSA 2048 certificate + private key (PKCS8 PEM).
+pub fn generate_test_keypair() -> (Vec<u8>, Vec<u8>) {
+    scamp::testing::generate_keypair().unwrap()
+}
@@ -14,34 +14,8 @@ use scamp::service::{ScampReply, ScampService};
 use scamp::transport::beepish::proto::EnvelopeFormat;
 use scamp::transport::beepish::BeepishClient;
 
-/// Generate a self-signed RSA 2048 certificate + private key (PKCS8 PEM).
-fn generate_test_keypair() -> (Vec<u8>, Vec<u8>) {
-    use openssl::asn1::Asn1Time;
-    use openssl::hash::MessageDigest;
-    use openssl::pkey::PKey;
-    use openssl::rsa::Rsa;
-    use openssl::x509::{X509Builder, X509NameBuilder};
-
-    let rsa = Rsa::generate(2048).unwrap();
-    let pkey = PKey::from_rsa(rsa).unwrap();
-
-    let mut name = X509NameBuilder::new().unwrap();
-    name.append_entry_by_text("CN", "scamp-test").unwrap();
-    let name = name.build();
-
-    let mut builder = X509Builder::new().unwrap();
-    builder.set_version(2).unwrap();
-    builder.set_subject_name(&name).unwrap();
-    builder.set_issuer_name(&name).unwrap();
-    builder.set_pubkey(&pkey).unwrap();
-    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
-    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
-    builder.sign(&pkey, MessageDigest::sha256()).unwrap();
-
-    let cert_pem = builder.build().to_pem().unwrap();
-    let key_pem = pkey.private_key_to_pem_pkcs8().unwrap();
-    (key_pem, cert_pem)
-}
+mod common;
+use common::generate_test_keypair;
 
 /// Set up a ScampService with an echo handler, bound to localhost.
 async fn setup_service() -> (ScampService, Vec<u8>, Vec<u8>) {
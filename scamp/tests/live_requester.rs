@@ -0,0 +1,78 @@
+//! Integration test for a Requester whose registry reloads in the background.
+
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tempfile::NamedTempFile;
+
+use scamp::config::Config;
+use scamp::crypto::cert_pem_fingerprint;
+use scamp::discovery::live::LiveDiscoveryConfig;
+use scamp::requester::Requester;
+use scamp::service::{ScampReply, ScampService};
+
+mod common;
+use common::generate_test_keypair;
+
+/// A service that appears in the cache after the Requester starts becomes routable.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_live_requester_picks_up_new_service() {
+    let (key_pem, cert_pem) = generate_test_keypair();
+    let config = Config::from_content("").unwrap();
+    let mut service = ScampService::builder(&config)
+        .name("LiveTest")
+        .tls_pem(&key_pem, &cert_pem)
+        .bind_ip(Ipv4Addr::LOCALHOST)
+        .announce(false)
+        .build()
+        .unwrap();
+    service.register("LiveTest.echo", 1, |req| async move { ScampReply::ok(req.body) });
+    service.bind().await.unwrap();
+    let announcement = service.build_announcement_packet(true).unwrap();
+
+    let cache_file = NamedTempFile::new().unwrap();
+    let mut auth_file = NamedTempFile::new().unwrap();
+    let fingerprint = cert_pem_fingerprint(std::str::from_utf8(&cert_pem).unwrap()).unwrap();
+    writeln!(auth_file, "{} main:ALL", fingerprint).unwrap();
+    auth_file.flush().unwrap();
+    let config = Config::from_content(&format!(
+        "discovery.cache_path = {}\nbus.authorized_services = {}\n",
+        cache_file.path().display(),
+        auth_file.path().display(),
+    ))
+    .unwrap();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let service_handle = tokio::spawn(service.serve(shutdown_rx));
+
+    let live_config = LiveDiscoveryConfig {
+        observe_multicast: false,
+        cache_reload_interval: Some(Duration::from_millis(50)),
+        ..Default::default()
+    };
+    let requester = Requester::from_config_live(&config, live_config).unwrap();
+    let err = requester.request("LiveTest.echo", 1, b"{}".to_vec()).await.unwrap_err();
+    assert!(err.to_string().contains("Action not found"), "unexpected error: {}", err);
+
+    let mut cache = std::fs::File::create(cache_file.path()).unwrap();
+    cache.write_all(&announcement).unwrap();
+    write!(cache, "\n%%%\n").unwrap();
+    cache.flush().unwrap();
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    let response = loop {
+        match requester.request("LiveTest.echo", 1, b"live".to_vec()).await {
+            Ok(response) => break response,
+            Err(e) if tokio::time::Instant::now() < deadline => {
+                log::debug!("not routable yet: {}", e);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            Err(e) => panic!("service never became routable: {}", e),
+        }
+    };
+    assert_eq!(response.body, b"live");
+
+    drop(requester);
+    shutdown_tx.send(true).unwrap();
+    service_handle.await.unwrap().unwrap();
+}
@@ -0,0 +1,93 @@
+//! Integration tests for the config-driven `ScampService::builder()` path.
+
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+use scamp::config::Config;
+use scamp::discovery::ServiceInfo;
+use scamp::service::{ScampReply, ScampService};
+use scamp::transport::beepish::proto::EnvelopeFormat;
+use scamp::transport::beepish::BeepishClient;
+
+mod common;
+use common::generate_test_keypair;
+
+fn write_temp(contents: &[u8]) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(contents).unwrap();
+    file.flush().unwrap();
+    file
+}
+
+/// Key/cert come from `<name>.key` / `<name>.cert` and the bind address from `bus.address`.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_builder_serves_from_config() {
+    let (key_pem, cert_pem) = generate_test_keypair();
+    let key_file = write_temp(&key_pem);
+    let cert_file = write_temp(&cert_pem);
+    let config = Config::from_content(&format!(
+        "BuilderTest.key = {}\nBuilderTest.cert = {}\nbus.address = 127.0.0.1\n",
+        key_file.path().display(),
+        cert_file.path().display(),
+    ))
+    .unwrap();
+
+    let mut service = ScampService::builder(&config).name("BuilderTest").announce(false).build().unwrap();
+    service.register("BuilderTest.echo", 1, |req| async move { ScampReply::ok(req.body) });
+    service.bind().await.unwrap();
+    assert_eq!(service.address().unwrap().ip().to_string(), "127.0.0.1");
+
+    let service_info = ServiceInfo {
+        identity: service.identity().to_string(),
+        uri: service.uri().unwrap(),
+        fingerprint: None,
+    };
+    let announcement = service.build_announcement_packet(true).unwrap();
+    assert!(
+        String::from_utf8(announcement).unwrap().contains("\"main\""),
+        "sector defaults to main"
+    );
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let service_handle = tokio::spawn(service.serve(shutdown_rx));
+
+    let client = BeepishClient::new(&config);
+    let response = client
+        .request(
+            &service_info,
+            "BuilderTest.echo",
+            1,
+            EnvelopeFormat::Json,
+            "",
+            0,
+            b"built".to_vec(),
+            Some(5),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.body, b"built");
+
+    drop(client);
+    shutdown_tx.send(true).unwrap();
+    service_handle.await.unwrap().unwrap();
+}
+
+#[test]
+fn test_builder_requires_name() {
+    let config = Config::from_content("").unwrap();
+    assert!(ScampService::builder(&config).build().is_err());
+}
+
+#[test]
+fn test_builder_requires_key_and_cert() {
+    let config = Config::from_content("").unwrap();
+    let err = ScampService::builder(&config).name("NoKeys").build().err().unwrap();
+    assert!(err.to_string().contains("NoKeys.key"), "unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn test_serve_requires_builder() {
+    let service = ScampService::new("Plain", "main");
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    assert!(service.serve(shutdown_rx).await.is_err());
+}
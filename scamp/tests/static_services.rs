@@ -0,0 +1,51 @@
+//! Integration test for a Requester that finds services through a static
+//! services file, with no multicast or cache file.
+
+use std::net::Ipv4Addr;
+use tempfile::TempDir;
+
+use scamp::config::Config;
+use scamp::requester::Requester;
+use scamp::service::{ScampReply, ScampService};
+
+mod common;
+use common::generate_test_keypair;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_requester_routes_to_static_service() {
+    let (key_pem, cert_pem) = generate_test_keypair();
+    let config = Config::from_content("").unwrap();
+    let mut service = ScampService::builder(&config)
+        .name("StaticTest")
+        .tls_pem(&key_pem, &cert_pem)
+        .bind_ip(Ipv4Addr::LOCALHOST)
+        .announce(false)
+        .build()
+        .unwrap();
+    service.register("StaticTest.echo", 2, |req| async move { ScampReply::ok(req.body) });
+    service.bind().await.unwrap();
+
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("static.crt"), &cert_pem).unwrap();
+    let services = serde_json::json!({
+        "services": [{
+            "uri": service.uri().unwrap(),
+            "cert": "static.crt",
+            "actions": ["StaticTest.echo~2"],
+        }]
+    });
+    let services_path = dir.path().join("services.json");
+    std::fs::write(&services_path, services.to_string()).unwrap();
+    let config = Config::from_content(&format!("discovery.static_services = {}", services_path.display())).unwrap();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let service_handle = tokio::spawn(service.serve(shutdown_rx));
+
+    let requester = Requester::from_config(&config).unwrap();
+    let response = requester.request("StaticTest.echo", 2, b"static".to_vec()).await.unwrap();
+    assert_eq!(response.body, b"static");
+
+    drop(requester);
+    shutdown_tx.send(true).unwrap();
+    service_handle.await.unwrap().unwrap();
+}
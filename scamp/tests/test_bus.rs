@@ -0,0 +1,71 @@
+//! Integration tests for `scamp::testing::TestBus`: services on one bus
+//! calling each other with no multicast or cache file.
+
+use std::sync::Arc;
+
+use scamp::requester::Requester;
+use scamp::service::{ScampReply, ScampService};
+use scamp::testing::TestBus;
+
+fn echo_service() -> ScampService {
+    let mut service = ScampService::new("Echo", "main");
+    service.register("Echo.say", 1, |req| async move { ScampReply::ok(req.body) });
+    service
+}
+
+/// A front service that answers by calling the echo service.
+fn front_service(requester: Arc<Requester>) -> ScampService {
+    let mut service = ScampService::new("Front", "main");
+    service.register("Front.greet", 1, move |req| {
+        let requester = requester.clone();
+        async move {
+            let mut body = b"hello ".to_vec();
+            body.extend_from_slice(&req.body);
+            match requester.request("Echo.say", 1, body).await {
+                Ok(resp) => ScampReply::ok(resp.body),
+                Err(e) => ScampReply::error(e.to_string(), "downstream".to_string()),
+            }
+        }
+    });
+    service
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_single_service() {
+    let mut bus = TestBus::new();
+    bus.start(echo_service()).await.unwrap();
+
+    let requester = bus.requester();
+    let resp = requester.request("Echo.say", 1, b"ping".to_vec()).await.unwrap();
+    assert_eq!(resp.body, b"ping");
+
+    drop(requester);
+    bus.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cross_service_call_chain() {
+    let mut bus = TestBus::new();
+    let inner = Arc::new(bus.requester());
+    bus.start(front_service(inner.clone())).await.unwrap();
+    bus.start(echo_service()).await.unwrap();
+    assert_eq!(bus.identities().len(), 2);
+
+    let requester = bus.requester();
+    let resp = requester.request("Front.greet", 1, b"world".to_vec()).await.unwrap();
+    assert!(resp.header.error.is_none(), "{:?}", resp.header.error);
+    assert_eq!(resp.body, b"hello world");
+
+    drop(requester);
+    drop(inner);
+    bus.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_unknown_action_is_not_routed() {
+    let mut bus = TestBus::new();
+    bus.start(echo_service()).await.unwrap();
+
+    let err = bus.requester().request("Echo.shout", 1, vec![]).await.unwrap_err();
+    assert!(err.to_string().contains("Action not found"), "{err}");
+}